@@ -12,6 +12,16 @@ pub struct Block {
     g_label: OnReady<Gd<Label>>,
     #[init(node = "HLabel")]
     h_label: OnReady<Gd<Label>>,
+    #[init(node = "ArrowLabel")]
+    arrow_label: OnReady<Gd<Label>>,
+    #[init(node = "OneWayLabel")]
+    one_way_label: OnReady<Gd<Label>>,
+    #[init(node = "TimedWallOverlay")]
+    timed_wall_overlay: OnReady<Gd<ColorRect>>,
+    #[init(node = "TimeWindowLabel")]
+    time_window_label: OnReady<Gd<Label>>,
+    #[init(node = "ClearanceLabel")]
+    clearance_label: OnReady<Gd<Label>>,
     #[init(node = "PosLabel")]
     pos_label: OnReady<Gd<Label>>,
     #[init(node = "Button")]
@@ -29,23 +39,40 @@ impl IPanel for Block {
         self.is_wall = false;
         self.set_color(self.original_color);
         self.reset_labels();
+        self.set_arrow(None);
+        self.set_one_way(None);
+        self.set_time_windows("");
+        self.set_timed_wall_active(false);
+        self.set_clearance(None);
 
         // Connect the button's pressed signal to our method
-        self.button.signals().pressed().connect_other(self, Self::on_button_pressed);
+        self.button
+            .signals()
+            .pressed()
+            .connect_other(self, Self::on_button_pressed);
     }
 }
 
 impl Block {
-    pub fn set_f(&mut self, f: i32) {
-        self.f_label.set_text(&f.to_string());
+    pub fn set_f(&mut self, f: f64) {
+        self.f_label.set_text(&Self::format_score(f));
     }
 
-    pub fn set_g(&mut self, g: i32) {
-        self.g_label.set_text(&g.to_string());
+    pub fn set_g(&mut self, g: f64) {
+        self.g_label.set_text(&Self::format_score(g));
     }
 
-    pub fn set_h(&mut self, h: i32) {
-        self.h_label.set_text(&h.to_string());
+    pub fn set_h(&mut self, h: f64) {
+        self.h_label.set_text(&Self::format_score(h));
+    }
+
+    // Whole numbers are shown as-is, fractional costs with one decimal
+    fn format_score(score: f64) -> String {
+        if score.fract() == 0.0 {
+            format!("{}", score as i64)
+        } else {
+            format!("{:.1}", score)
+        }
     }
 
     pub fn reset_labels(&mut self) {
@@ -54,6 +81,50 @@ impl Block {
         self.h_label.set_text("");
     }
 
+    // Flow field direction as an arrow, e.g. (1, 0) points right; None clears it
+    pub fn set_arrow(&mut self, direction: Option<(i32, i32)>) {
+        let arrow = match direction.map(|(dx, dy)| (dx.signum(), dy.signum())) {
+            Some((1, 0)) => "→",
+            Some((1, 1)) => "↘",
+            Some((0, 1)) => "↓",
+            Some((-1, 1)) => "↙",
+            Some((-1, 0)) => "←",
+            Some((-1, -1)) => "↖",
+            Some((0, -1)) => "↑",
+            Some((1, -1)) => "↗",
+            _ => "",
+        };
+        self.arrow_label.set_text(arrow);
+    }
+
+    // Way a one-way block is crossed, e.g. (0, -1) for up; None for a block crossed any way
+    pub fn set_one_way(&mut self, direction: Option<(i32, i32)>) {
+        let arrow = match direction {
+            Some((0, -1)) => "↑",
+            Some((1, 0)) => "→",
+            Some((0, 1)) => "↓",
+            Some((-1, 0)) => "←",
+            _ => "",
+        };
+        self.one_way_label.set_text(arrow);
+    }
+
+    // Steps the block's timed walls stand at, e.g. "0-3/6"; empty for a block without any
+    pub fn set_time_windows(&mut self, text: &str) {
+        self.time_window_label.set_text(text);
+    }
+
+    // Shade the block while one of its timed walls stands, over whatever the search colored
+    pub fn set_timed_wall_active(&mut self, active: bool) {
+        self.timed_wall_overlay.set_visible(active);
+    }
+
+    // Side of the biggest unit that fits with its top-left corner on the block; None hides it
+    pub fn set_clearance(&mut self, clearance: Option<i32>) {
+        let text = clearance.map_or(String::new(), |clearance| clearance.to_string());
+        self.clearance_label.set_text(&text);
+    }
+
     pub fn set_pos(&mut self, x: i32, y: i32) {
         self.pos = (x, y);
         self.pos_label.set_text(&format!("({},{})", x, y));
@@ -63,13 +134,22 @@ impl Block {
         self.base_mut().set_self_modulate(color);
     }
 
+    // Color the block shows when it is not part of the search (e.g. its terrain color)
+    pub fn set_original_color(&mut self, color: Color) {
+        self.original_color = color;
+        if !self.is_wall {
+            self.set_color(color);
+        }
+    }
+
     pub fn set_as_wall(&mut self) {
         self.is_wall = true;
         self.set_color(crate::game::Game::WALL_BLOCK_COLOR);
     }
 
-    pub fn is_wall(&self) -> bool {
-        self.is_wall
+    pub fn clear_wall(&mut self) {
+        self.is_wall = false;
+        self.set_color(self.original_color);
     }
 
     pub fn reset_color(&mut self) {
@@ -1,77 +1,325 @@
 use crate::block::Block;
+use crate::pathfinding::{
+    Algorithm, Coordination, CornerCutting, DStarLite, FlowField, GoalRegion, Grid, Heuristic,
+    HexOffset, HexOrientation, Hierarchy, Movement, MultiAgentPlan, OneWay, PathError, PathQuery,
+    PathResult, Pathfinder, Portal, Pos, Route, SearchEvent, SearchObserver, SearchStats, Side,
+    Terrain, TimedWall, Topology, Wrap,
+};
 use godot::classes::*;
 use godot::global::{Key, MouseButton};
 use godot::prelude::*;
 use godot_tokio::AsyncRuntime;
-use std::cmp::Ordering;
-use std::collections::{BinaryHeap, HashMap, HashSet};
-use std::ops::DerefMut;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
 use tokio::sync::broadcast::{Receiver, Sender, channel};
 use tokio::time::sleep;
 
-// Node structure for A* algorithm
-#[derive(Copy, Clone, Eq, PartialEq, Debug)]
-struct Node {
-    position: (i32, i32),
-    f_score: i32, // f = g + h
-    g_score: i32, // cost from start to current node
-    h_score: i32, // heuristic (estimated cost from current to goal)
+// View over the headless pathfinding model: owns the blocks and draws what the search reports
+#[derive(Clone, Default)]
+struct AStarController {
+    grid: Grid,
+    blocks: Vec<Vec<Gd<Block>>>,
+    // Straight segments of any-angle paths, drawn over the blocks
+    path_line: Option<Gd<Line2D>>,
+    // Copies of `path_line` for the pieces of a path that continue across a wrapped edge
+    seam_lines: Vec<Gd<Line2D>>,
+    // One line between the two ends of every portal
+    portal_lines: Vec<Gd<Line2D>>,
+    // D* Lite search state kept between runs, so editing walls only repairs the last path
+    planner: Option<DStarLite>,
+    // HPA* abstract graph, kept up to date with wall edits and shared by all queries
+    hierarchy: Option<Hierarchy>,
+    // Shows the f bound of the current IDA* pass
+    threshold_label: Option<Gd<Label>>,
+    // Shows the time step the timed walls are shaded for
+    time_label: Option<Gd<Label>>,
+    time: usize,
+    // Block marking where the unit of the last path is at `time`, with its color before
+    unit_block: Option<(Pos, Color)>,
+
+    start_block: Option<Pos>,
+    end_block: Option<Pos>,
+    // More end blocks; the path goes to whichever end is cheapest to reach
+    extra_end_blocks: Vec<Pos>,
+    // Area the path may end anywhere in, and its first corner while it is being placed
+    goal_region: Option<GoalRegion>,
+    region_corner: Option<Pos>,
+    // First end of the portal being placed with the `P` key
+    portal_end: Option<Pos>,
+    // Stops between start and end, visited in the order they were placed
+    waypoints: Vec<Pos>,
+    // (start, end) of every agent planned together, in the order they were added
+    agents: Vec<(Pos, Pos)>,
+    // Shared with the running replay so a right-click can stop it
+    cancelled: Arc<AtomicBool>,
+}
+
+// IDA* and SMA* can expand the same cells over and over; the demo stops them after this many
+// expansions, as the search runs on the main thread and would freeze it
+const MEMORY_BOUNDED_EXPANSION_LIMIT: usize = 100_000;
+
+// Records the search trace for the replay, and stops the search when the run is cancelled or
+// it expanded more nodes than `expansion_limit`
+struct Recorder {
+    events: Vec<SearchEvent>,
+    cancelled: Arc<AtomicBool>,
+    expanded: usize,
+    expansion_limit: Option<usize>,
 }
 
-impl Node {
-    fn new(position: (i32, i32), g_score: i32, h_score: i32) -> Self {
+impl Recorder {
+    fn new(cancelled: Arc<AtomicBool>, expansion_limit: Option<usize>) -> Self {
         Self {
-            position,
-            f_score: g_score + h_score,
-            g_score,
-            h_score,
+            events: Vec::new(),
+            cancelled,
+            expanded: 0,
+            expansion_limit,
         }
     }
+
+    fn hit_limit(&self) -> bool {
+        self.expansion_limit
+            .is_some_and(|limit| self.expanded >= limit)
+    }
 }
 
-// Custom ordering for the priority queue (min-heap based on f_score)
-impl Ord for Node {
-    fn cmp(&self, other: &Self) -> Ordering {
-        // Reverse ordering for min-heap (lowest f_score has highest priority)
-        other
-            .f_score
-            .cmp(&self.f_score)
-            .then_with(|| other.h_score.cmp(&self.h_score)) // Tie-breaker: prefer lower h_score
-            .then_with(|| other.position.cmp(&self.position)) // Final tie-breaker: position
+impl SearchObserver for Recorder {
+    fn on_event(&mut self, event: SearchEvent) {
+        if matches!(event, SearchEvent::Expand { .. }) {
+            self.expanded += 1;
+        }
+        self.events.push(event);
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed) || self.hit_limit()
     }
 }
 
-impl PartialOrd for Node {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
+// Search algorithm selectable from the editor and with the `A` key
+#[derive(GodotConvert, Var, Export, Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[godot(via = i64)]
+pub enum SearchAlgorithm {
+    #[default]
+    AStar,
+    Dijkstra,
+    BreadthFirst,
+    GreedyBestFirst,
+    WeightedAStar,
+    JumpPointSearch,
+    JumpPointSearchPlus,
+    ThetaStar,
+    LazyThetaStar,
+    BidirectionalAStar,
+    DStarLite,
+    IterativeDeepeningAStar,
+    SmaStar,
+    HierarchicalAStar,
+    SpaceTimeAStar,
+}
+
+impl SearchAlgorithm {
+    const ALL: [SearchAlgorithm; 15] = [
+        SearchAlgorithm::AStar,
+        SearchAlgorithm::Dijkstra,
+        SearchAlgorithm::BreadthFirst,
+        SearchAlgorithm::GreedyBestFirst,
+        SearchAlgorithm::WeightedAStar,
+        SearchAlgorithm::JumpPointSearch,
+        SearchAlgorithm::JumpPointSearchPlus,
+        SearchAlgorithm::ThetaStar,
+        SearchAlgorithm::LazyThetaStar,
+        SearchAlgorithm::BidirectionalAStar,
+        SearchAlgorithm::DStarLite,
+        SearchAlgorithm::IterativeDeepeningAStar,
+        SearchAlgorithm::SmaStar,
+        SearchAlgorithm::HierarchicalAStar,
+        SearchAlgorithm::SpaceTimeAStar,
+    ];
+
+    fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|&a| a == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    fn to_algorithm(self, epsilon: f64, memory_limit: usize, cluster_size: i32) -> Algorithm {
+        match self {
+            SearchAlgorithm::AStar => Algorithm::AStar,
+            SearchAlgorithm::Dijkstra => Algorithm::Dijkstra,
+            SearchAlgorithm::BreadthFirst => Algorithm::BreadthFirst,
+            SearchAlgorithm::GreedyBestFirst => Algorithm::GreedyBestFirst,
+            SearchAlgorithm::WeightedAStar => Algorithm::WeightedAStar { epsilon },
+            SearchAlgorithm::JumpPointSearch => Algorithm::JumpPointSearch,
+            SearchAlgorithm::JumpPointSearchPlus => Algorithm::JumpPointSearchPlus,
+            SearchAlgorithm::ThetaStar => Algorithm::ThetaStar,
+            SearchAlgorithm::LazyThetaStar => Algorithm::LazyThetaStar,
+            SearchAlgorithm::BidirectionalAStar => Algorithm::BidirectionalAStar,
+            SearchAlgorithm::DStarLite => Algorithm::DStarLite,
+            SearchAlgorithm::IterativeDeepeningAStar => Algorithm::IterativeDeepeningAStar,
+            SearchAlgorithm::SmaStar => Algorithm::SmaStar { memory_limit },
+            SearchAlgorithm::HierarchicalAStar => Algorithm::HierarchicalAStar { cluster_size },
+            SearchAlgorithm::SpaceTimeAStar => Algorithm::SpaceTimeAStar,
+        }
     }
 }
 
-#[derive(Clone)]
-struct AStarController {
-    width: i32,
-    height: i32,
-    blocks: Vec<Vec<Gd<Block>>>,
-    open_set: BinaryHeap<Node>,
-    closed_set: HashSet<(i32, i32)>,
-    came_from: HashMap<(i32, i32), Node>,
+// Heuristic selectable from the editor and with the `H` key
+#[derive(GodotConvert, Var, Export, Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[godot(via = i64)]
+pub enum SearchHeuristic {
+    #[default]
+    Manhattan,
+    Euclidean,
+    Chebyshev,
+    Octile,
+    Hex,
+    Zero,
+}
+
+impl SearchHeuristic {
+    const ALL: [SearchHeuristic; 6] = [
+        SearchHeuristic::Manhattan,
+        SearchHeuristic::Euclidean,
+        SearchHeuristic::Chebyshev,
+        SearchHeuristic::Octile,
+        SearchHeuristic::Hex,
+        SearchHeuristic::Zero,
+    ];
+
+    fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|&h| h == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
 
-    start_block: Option<(i32, i32)>,
-    end_block: Option<(i32, i32)>,
+    fn to_heuristic(self) -> Heuristic {
+        match self {
+            SearchHeuristic::Manhattan => Heuristic::Manhattan,
+            SearchHeuristic::Euclidean => Heuristic::Euclidean,
+            SearchHeuristic::Chebyshev => Heuristic::Chebyshev,
+            SearchHeuristic::Octile => Heuristic::Octile,
+            SearchHeuristic::Hex => Heuristic::Hex,
+            SearchHeuristic::Zero => Heuristic::Zero,
+        }
+    }
 }
 
-impl Default for AStarController {
-    fn default() -> Self {
-        Self {
-            width: 0,
-            height: 0,
-            blocks: vec![],
-            open_set: Default::default(),
-            closed_set: Default::default(),
-            came_from: Default::default(),
-            start_block: None,
-            end_block: None,
+// Which edges connect to the opposite one, selectable from the editor and with the `W` key
+#[derive(GodotConvert, Var, Export, Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[godot(via = i64)]
+pub enum SearchWrap {
+    #[default]
+    None,
+    Horizontal,
+    Vertical,
+    Both,
+}
+
+impl SearchWrap {
+    const ALL: [SearchWrap; 4] = [
+        SearchWrap::None,
+        SearchWrap::Horizontal,
+        SearchWrap::Vertical,
+        SearchWrap::Both,
+    ];
+
+    fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|&w| w == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    fn to_wrap(self) -> Wrap {
+        match self {
+            SearchWrap::None => Wrap::None,
+            SearchWrap::Horizontal => Wrap::Horizontal,
+            SearchWrap::Vertical => Wrap::Vertical,
+            SearchWrap::Both => Wrap::Both,
+        }
+    }
+}
+
+// Cell shape, selectable from the editor only since the blocks are laid out once
+#[derive(GodotConvert, Var, Export, Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[godot(via = i64)]
+pub enum SearchTopology {
+    #[default]
+    Square,
+    PointyOddRows,
+    PointyEvenRows,
+    FlatOddColumns,
+    FlatEvenColumns,
+}
+
+impl SearchTopology {
+    fn to_topology(self) -> Topology {
+        match self {
+            SearchTopology::Square => Topology::Square,
+            SearchTopology::PointyOddRows => Topology::hex(HexOrientation::Pointy, HexOffset::Odd),
+            SearchTopology::PointyEvenRows => {
+                Topology::hex(HexOrientation::Pointy, HexOffset::Even)
+            }
+            SearchTopology::FlatOddColumns => Topology::hex(HexOrientation::Flat, HexOffset::Odd),
+            SearchTopology::FlatEvenColumns => Topology::hex(HexOrientation::Flat, HexOffset::Even),
+        }
+    }
+}
+
+// How several agents avoid each other, selectable from the editor and with the `N` key
+#[derive(GodotConvert, Var, Export, Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[godot(via = i64)]
+pub enum SearchCoordination {
+    #[default]
+    Cooperative,
+    ConflictBased,
+}
+
+impl SearchCoordination {
+    const ALL: [SearchCoordination; 2] = [
+        SearchCoordination::Cooperative,
+        SearchCoordination::ConflictBased,
+    ];
+
+    fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|&c| c == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    fn to_coordination(self) -> Coordination {
+        match self {
+            SearchCoordination::Cooperative => Coordination::Cooperative,
+            SearchCoordination::ConflictBased => Coordination::ConflictBased,
+        }
+    }
+}
+
+// Diagonal corner-cutting rule selectable from the editor and with the `C` key
+#[derive(GodotConvert, Var, Export, Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[godot(via = i64)]
+pub enum SearchCornerCutting {
+    Always,
+    #[default]
+    NotBetweenWalls,
+    BothOrthogonalsFree,
+}
+
+impl SearchCornerCutting {
+    const ALL: [SearchCornerCutting; 3] = [
+        SearchCornerCutting::Always,
+        SearchCornerCutting::NotBetweenWalls,
+        SearchCornerCutting::BothOrthogonalsFree,
+    ];
+
+    fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|&c| c == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    fn to_corner_cutting(self) -> CornerCutting {
+        match self {
+            SearchCornerCutting::Always => CornerCutting::Always,
+            SearchCornerCutting::NotBetweenWalls => CornerCutting::NotBetweenWalls,
+            SearchCornerCutting::BothOrthogonalsFree => CornerCutting::BothOrthogonalsFree,
         }
     }
 }
@@ -87,51 +335,223 @@ pub struct Game {
     height: i32,
     #[export]
     step_mode: bool,
+    #[export]
+    algorithm: SearchAlgorithm,
+    // Heuristic weight used by weighted A*; below 1 the cost bound no longer holds
+    #[export(range = (1.0, 10.0, or_greater))]
+    #[init(val = 1.5)]
+    epsilon: f64,
+    // Most nodes SMA* may hold at once
+    #[export]
+    #[init(val = 64)]
+    memory_limit: i32,
+    // Side of the square clusters HPA* cuts the grid into
+    #[export]
+    #[init(val = 5)]
+    cluster_size: i32,
+    #[export]
+    heuristic: SearchHeuristic,
+    // 8-way movement instead of 4-way, toggled with the `D` key
+    #[export]
+    diagonal_movement: bool,
+    // 1.4 mimics the classic 10/14 grid, 1.414... is exact
+    #[export]
+    #[init(val = Movement::DIAGONAL_COST_SQRT2)]
+    diagonal_cost: f64,
+    #[export]
+    corner_cutting: SearchCornerCutting,
+    // Randomly scatter grass, mud and water over the free blocks; off by default, as jump point
+    // search needs uniform terrain costs
+    #[export]
+    scatter_terrain: bool,
+    #[export]
+    coordination: SearchCoordination,
+    // Hex cells are shown as bricks: every other row (pointy) or column (flat) is shifted
+    // by half a block, so each block touches its six neighbors
+    #[export]
+    topology: SearchTopology,
+    #[export]
+    wrap: SearchWrap,
+    // Portals on the map as (x1, y1, x2, y2); more are placed with the `P` key
+    #[export]
+    portal_pairs: Array<Vector4i>,
+    // Cost of going through a portal, 0 for an instant jump
+    #[export]
+    portal_cost: f64,
+    // One-way blocks on the map as (x, y, direction) with direction 0 up, 1 right, 2 down
+    // and 3 left; more are turned with O-click
+    #[export]
+    one_way_blocks: Array<Vector3i>,
+    // Walls that only stand at some time steps as (x, y, start, end), blocking steps start
+    // to end - 1; more are placed with G-click. Only space-time A* sees them.
+    #[export]
+    timed_walls: Array<Vector4i>,
+    // Timed walls stand again every this many steps, 0 for just once
+    #[export]
+    #[init(val = 6)]
+    timed_wall_period: i32,
+    // How many steps a timed wall placed with G-click stands
+    #[export]
+    #[init(val = 3)]
+    timed_wall_steps: i32,
+    // Side of the square unit paths are searched for, cycled from 1 to 3 with the `Z` key;
+    // above 1 the blocks show their clearance
+    #[export]
+    #[init(val = 1)]
+    agent_size: i32,
 
     #[init(node = "%StepMode")]
     step_mode_label: OnReady<Gd<Label>>,
+    #[init(node = "%Algorithm")]
+    algorithm_label: OnReady<Gd<Label>>,
+    #[init(node = "%Heuristic")]
+    heuristic_label: OnReady<Gd<Label>>,
+    #[init(node = "%Movement")]
+    movement_label: OnReady<Gd<Label>>,
     #[init(node = "%Seed")]
     seed_label: OnReady<Gd<Label>>,
+    #[init(node = "%Coordination")]
+    coordination_label: OnReady<Gd<Label>>,
+    #[init(node = "%Wrap")]
+    wrap_label: OnReady<Gd<Label>>,
+    #[init(node = "%AgentSize")]
+    agent_size_label: OnReady<Gd<Label>>,
+    #[init(node = "%Legend")]
+    legend: OnReady<Gd<HBoxContainer>>,
 
     controller: AStarController,
     tx: Option<Sender<bool>>,
     is_processing: bool,
+    last_result: Option<Result<Route, PathError>>,
+    last_plan: Option<Result<MultiAgentPlan, PathError>>,
+    // Shown with the `F` key and rebuilt whenever the grid changes while it is
+    flow_field: Option<FlowField>,
+    // The next two clicked blocks become a portal, set with the `P` key
+    placing_portal: bool,
 }
 
 #[godot_api]
 impl ICanvasLayer for Game {
     fn ready(&mut self) {
-        self.controller.width = self.width;
-        self.controller.height = self.height;
+        self.controller.grid = Grid::new(self.width, self.height);
+        let topology = self.topology.to_topology();
+        self.controller.grid.set_topology(topology);
         self.step_mode_label
             .set_text(self.step_mode.to_string().as_str());
+        self.update_algorithm_label();
+        self.heuristic_label
+            .set_text(self.heuristic.to_heuristic().name());
+        self.update_movement();
+        self.update_wrap();
+        self.coordination_label
+            .set_text(self.coordination.to_coordination().name());
 
         let block_prefab = load::<PackedScene>("res://Block.tscn");
-        let mut container = self.base().get_node_as::<GridContainer>("%GridContainer");
+        let mut grid_container = self.base().get_node_as::<GridContainer>("%GridContainer");
+        let mut hex_container = self.base().get_node_as::<Control>("%HexContainer");
+        let mut path_line = self.base().get_node_as::<Line2D>("%PathLine");
+        let mut container = if topology.is_hex() {
+            grid_container.set_visible(false);
+            hex_container.set_visible(true);
+            path_line.reparent(&hex_container);
+            hex_container
+        } else {
+            grid_container.set_columns(self.width);
+            grid_container.upcast()
+        };
+        self.controller.path_line = Some(path_line);
+        self.controller.threshold_label = Some(self.base().get_node_as::<Label>("%Threshold"));
+        self.controller.time_label = Some(self.base().get_node_as::<Label>("%Time"));
         let mut rng = RandomNumberGenerator::new_gd();
         rng.set_seed(6466529302137445490);
         self.seed_label
             .set_text(rng.get_seed().to_string().as_str());
+        // Terrain has its own generator so the wall layout for a seed stays the same
+        let mut terrain_rng = RandomNumberGenerator::new_gd();
+        terrain_rng.set_seed(rng.get_seed().wrapping_add(1));
+        self.build_legend();
 
-        container.set_columns(self.width);
         self.controller.blocks = vec![vec![]; self.width as usize];
         for y in 0..self.height {
             for x in 0..self.width {
                 let mut block = block_prefab.instantiate_as::<Block>();
                 container.add_child(&block);
+                if topology.is_hex() {
+                    let size = block.get_custom_minimum_size();
+                    block.set_position(Self::hex_block_position(topology, (x, y), size));
+                    block.set_size(size);
+                }
 
                 // Set position
                 block.bind_mut().set_pos(x, y);
 
                 // Randomly generate walls (20% chance)
                 if rng.randf() < 0.2 {
+                    self.controller.grid.set_wall((x, y), true);
                     block.bind_mut().set_as_wall();
                 }
 
-                self.controller.blocks.deref_mut()[x as usize].push(block);
+                // Randomly scatter terrain (15% grass, 10% mud, 5% water)
+                if self.scatter_terrain {
+                    let terrain = match terrain_rng.randf() {
+                        r if r < 0.15 => Terrain::Grass,
+                        r if r < 0.25 => Terrain::Mud,
+                        r if r < 0.30 => Terrain::Water,
+                        _ => Terrain::Ground,
+                    };
+                    self.controller.grid.set_terrain((x, y), terrain);
+                    block
+                        .bind_mut()
+                        .set_original_color(Self::terrain_color(terrain));
+                }
+
+                self.controller.blocks[x as usize].push(block);
             }
         }
 
+        // Blocks placed by hand don't size their container, so make room for them
+        if topology.is_hex()
+            && let Some(block) = self.controller.get_block(0, 0)
+        {
+            let size = block.get_custom_minimum_size();
+            let (width, height) = (self.width as f32, self.height as f32);
+            container.set_custom_minimum_size(match topology {
+                Topology::Hex {
+                    orientation: HexOrientation::Flat,
+                    ..
+                } => Vector2::new(size.x * width, size.y * (height + 0.5)),
+                _ => Vector2::new(size.x * (width + 0.5), size.y * height),
+            });
+        }
+
+        for block in self.one_way_blocks.iter_shared() {
+            let one_way = OneWay::ALL.get(block.z as usize).copied();
+            self.controller.set_one_way((block.x, block.y), one_way);
+        }
+
+        let period = self.timed_wall_period.max(0) as usize;
+        for wall in self.timed_walls.iter_shared() {
+            let (start, end) = (wall.z.max(0) as usize, wall.w.max(0) as usize);
+            let wall = TimedWall::new((wall.x, wall.y), start, end).repeating(period);
+            self.controller.add_timed_wall(wall);
+        }
+        self.controller.show_time(0);
+
+        // Portal ends are kept free of walls
+        for pair in self.portal_pairs.iter_shared() {
+            let (a, b) = ((pair.x, pair.y), (pair.z, pair.w));
+            for pos in [a, b] {
+                self.controller.grid.set_wall(pos, false);
+                if let Some(mut block) = self.controller.get_block(pos.0, pos.1) {
+                    block.bind_mut().clear_wall();
+                }
+            }
+            self.controller.link_portal(a, b, self.portal_cost);
+        }
+        // Blocks in the grid container only get their positions once it is laid out
+        self.base_mut().call_deferred("draw_portals", &[]);
+        self.update_agent_size();
+
         // Connect signals after all blocks are created
         for y in 0..self.height {
             for x in 0..self.width {
@@ -149,34 +569,96 @@ impl ICanvasLayer for Game {
 
     fn input(&mut self, event: Gd<InputEvent>) {
         let mouse_event = event.clone().try_cast::<InputEventMouseButton>();
-        if let Ok(mouse_event) = mouse_event {
-            if mouse_event.is_pressed() && mouse_event.get_button_index() == MouseButton::RIGHT {
-                // Right click - clear start/end blocks
-                self.on_block_right_clicked(); // Position doesn't matter for right-click
-            }
+        if let Ok(mouse_event) = mouse_event
+            && mouse_event.is_pressed()
+            && mouse_event.get_button_index() == MouseButton::RIGHT
+        {
+            // Right click - clear start/end blocks
+            self.on_block_right_clicked(); // Position doesn't matter for right-click
         }
 
         if !self.is_processing {
             let key_event = event.try_cast::<InputEventKey>();
-            if let Ok(key_event) = key_event {
-                if key_event.is_pressed() && key_event.get_keycode() == Key::T {
-                    self.step_mode ^= true;
-                    self.step_mode_label
-                        .set_text(self.step_mode.to_string().as_str());
-                    godot_print!("Toggle step mode: {}", self.step_mode);
+            if let Ok(key_event) = key_event
+                && key_event.is_pressed()
+            {
+                match key_event.get_keycode() {
+                    Key::T => {
+                        self.step_mode ^= true;
+                        self.step_mode_label
+                            .set_text(self.step_mode.to_string().as_str());
+                        godot_print!("Toggle step mode: {}", self.step_mode);
+                    }
+                    Key::A => {
+                        self.algorithm = self.algorithm.next();
+                        self.update_algorithm_label();
+                        godot_print!("Switch algorithm: {}", self.current_algorithm().name());
+                    }
+                    Key::H => {
+                        self.heuristic = self.heuristic.next();
+                        let name = self.heuristic.to_heuristic().name();
+                        self.heuristic_label.set_text(name);
+                        godot_print!("Switch heuristic: {}", name);
+                    }
+                    Key::D => {
+                        self.diagonal_movement ^= true;
+                        self.update_movement();
+                        godot_print!("Toggle diagonal movement: {}", self.diagonal_movement);
+                    }
+                    Key::C => {
+                        self.corner_cutting = self.corner_cutting.next();
+                        self.update_movement();
+                        godot_print!(
+                            "Switch corner cutting: {}",
+                            self.corner_cutting.to_corner_cutting().name()
+                        );
+                    }
+                    Key::M => {
+                        self.add_agent();
+                    }
+                    Key::F => {
+                        self.toggle_flow_field();
+                    }
+                    Key::W => {
+                        self.wrap = self.wrap.next();
+                        self.update_wrap();
+                        // Footprints may go on across the borders too
+                        self.controller.draw_clearance(self.agent_size > 1);
+                        godot_print!("Switch wrap-around: {}", self.wrap.to_wrap().name());
+                    }
+                    Key::P => {
+                        self.placing_portal = true;
+                        godot_print!("Click two blocks to link them, or a portal end to remove it");
+                    }
+                    Key::Z => {
+                        self.agent_size = self.agent_size.max(1) % 3 + 1;
+                        self.update_agent_size();
+                        godot_print!("Switch agent size: {}", self.agent_size);
+                    }
+                    Key::COMMA => {
+                        self.scrub_time(false);
+                    }
+                    Key::PERIOD => {
+                        self.scrub_time(true);
+                    }
+                    Key::N => {
+                        self.coordination = self.coordination.next();
+                        let name = self.coordination.to_coordination().name();
+                        self.coordination_label.set_text(name);
+                        godot_print!("Switch coordination: {}", name);
+                    }
+                    _ => {}
                 }
             }
-        } else {
+        } else if self.step_mode {
             // Handle keyboard input for step mode
-            if self.step_mode {
-                let key_event = event.try_cast::<InputEventKey>();
-                if let Ok(key_event) = key_event {
-                    if key_event.is_pressed() && key_event.get_keycode() == Key::SPACE {
-                        if let Some(tx) = &self.tx {
-                            tx.send(true).unwrap();
-                        }
-                    }
-                }
+            let key_event = event.try_cast::<InputEventKey>();
+            if let Ok(key_event) = key_event
+                && key_event.is_pressed()
+                && key_event.get_keycode() == Key::SPACE
+                && let Some(tx) = &self.tx
+            {
+                tx.send(true).unwrap();
             }
         }
     }
@@ -186,17 +668,95 @@ impl Game {
     pub const END_BLOCK_COLOR: Color = Color::BLUE;
     pub const WALL_BLOCK_COLOR: Color = Color::ORANGE_RED;
     pub const PATH_BLOCK_COLOR: Color = Color::VIOLET;
+    pub const WAYPOINT_BLOCK_COLOR: Color = Color::INDIGO;
+    pub const REGION_BLOCK_COLOR: Color = Color::SILVER;
+    pub const PORTAL_BLOCK_COLOR: Color = Color::LIME_GREEN;
+    pub const FOOTPRINT_BLOCK_COLOR: Color = Color::THISTLE;
+    // Each leg of a route through waypoints gets the next color, the first one the path color
+    pub const LEG_COLORS: [Color; 4] = [
+        Self::PATH_BLOCK_COLOR,
+        Color::MEDIUM_SEA_GREEN,
+        Color::STEEL_BLUE,
+        Color::SIENNA,
+    ];
     pub const OPEN_BLOCK_COLOR: Color = Color::YELLOW;
     pub const CLOSED_BLOCK_COLOR: Color = Color::DARK_ORANGE;
     pub const CURRENT_BLOCK_COLOR: Color = Color::DARK_GREEN;
+    pub const JUMP_POINT_BLOCK_COLOR: Color = Color::AQUAMARINE;
+    pub const CLOSED_JUMP_POINT_BLOCK_COLOR: Color = Color::TEAL;
+    pub const BACKWARD_OPEN_BLOCK_COLOR: Color = Color::LIGHT_PINK;
+    pub const BACKWARD_CLOSED_BLOCK_COLOR: Color = Color::HOT_PINK;
+    pub const MEETING_BLOCK_COLOR: Color = Color::GOLD;
+
+    fn leg_color(index: usize) -> Color {
+        Self::LEG_COLORS[index % Self::LEG_COLORS.len()]
+    }
+
+    fn open_color(side: Side, jump_point: bool) -> Color {
+        match (side, jump_point) {
+            (_, true) => Self::JUMP_POINT_BLOCK_COLOR,
+            (Side::Forward, false) => Self::OPEN_BLOCK_COLOR,
+            (Side::Backward, false) => Self::BACKWARD_OPEN_BLOCK_COLOR,
+        }
+    }
+
+    fn closed_color(side: Side, jump_point: bool) -> Color {
+        match (side, jump_point) {
+            (_, true) => Self::CLOSED_JUMP_POINT_BLOCK_COLOR,
+            (Side::Forward, false) => Self::CLOSED_BLOCK_COLOR,
+            (Side::Backward, false) => Self::BACKWARD_CLOSED_BLOCK_COLOR,
+        }
+    }
+
+    pub fn terrain_color(terrain: Terrain) -> Color {
+        match terrain {
+            Terrain::Ground => Color::WHITE,
+            Terrain::Grass => Color::PALE_GREEN,
+            Terrain::Mud => Color::BURLYWOOD,
+            Terrain::Water => Color::LIGHT_SKY_BLUE,
+        }
+    }
+
+    // Top-left corner of a block in the hex view, shifting the rows (pointy) or columns
+    // (flat) the topology offsets by half a block
+    fn hex_block_position(topology: Topology, (x, y): Pos, size: Vector2) -> Vector2 {
+        let Topology::Hex {
+            orientation,
+            offset,
+        } = topology
+        else {
+            return Vector2::new(x as f32 * size.x, y as f32 * size.y);
+        };
+        let line = match orientation {
+            HexOrientation::Pointy => y,
+            HexOrientation::Flat => x,
+        };
+        let shifted = (line & 1 == 1) == (offset == HexOffset::Odd);
+        let shift = if shifted { 0.5 } else { 0.0 };
+        match orientation {
+            HexOrientation::Pointy => Vector2::new((x as f32 + shift) * size.x, y as f32 * size.y),
+            HexOrientation::Flat => Vector2::new(x as f32 * size.x, (y as f32 + shift) * size.y),
+        }
+    }
+
+    // One colored "name (cost)" entry per terrain type
+    fn build_legend(&mut self) {
+        for terrain in Terrain::ALL {
+            let mut label = Label::new_alloc();
+            label.set_text(&format!("{} ({})", terrain.name(), terrain.cost()));
+            label.add_theme_color_override("font_color", Color::BLACK);
+            let mut panel = PanelContainer::new_alloc();
+            panel.set_self_modulate(Self::terrain_color(terrain));
+            panel.add_child(&label);
+            self.legend.add_child(&panel);
+        }
+    }
 }
 
 impl AStarController {
-    pub const DIRECTIONS: [(i32, i32); 4] = [(0, -1), (1, 0), (0, 1), (-1, 0)]; // Up, Right, Down, Left
-
     // Helper method to get a block at a specific position
     fn get_block(&self, x: i32, y: i32) -> Option<Gd<Block>> {
-        if x >= 0 && x < self.width && y >= 0 && y < self.height {
+        if self.grid.in_bounds((x, y)) {
             Some(self.blocks[x as usize][y as usize].clone())
         } else {
             None
@@ -219,282 +779,981 @@ impl AStarController {
         self.end_block = Some((x, y));
     }
 
-    // Helper method to reset a block's color
-    fn reset_block_color(&mut self, x: i32, y: i32) {
+    // Helper method to add a stop before the end block
+    fn add_waypoint(&mut self, x: i32, y: i32) {
         if let Some(mut block) = self.get_block(x, y) {
-            block.bind_mut().reset_color();
+            block.bind_mut().set_color(Game::WAYPOINT_BLOCK_COLOR);
         }
+        self.waypoints.push((x, y));
     }
 
-    // Calculate Manhattan distance heuristic
-    fn manhattan_distance(a: (i32, i32), b: (i32, i32)) -> i32 {
-        (a.0 - b.0).abs() + (a.1 - b.1).abs()
+    // Helper method to add another block the path may end at
+    fn add_extra_end_block(&mut self, x: i32, y: i32) {
+        if let Some(mut block) = self.get_block(x, y) {
+            block.bind_mut().set_color(Game::END_BLOCK_COLOR);
+        }
+        self.extra_end_blocks.push((x, y));
     }
 
-    // Get neighboring positions (4-way: up, right, down, left)
-    fn get_neighbors(&self, (x, y): (i32, i32)) -> Vec<(i32, i32)> {
-        Self::DIRECTIONS
-            .iter()
-            .map(|(dx, dy)| (x + dx, y + dy))
-            .filter(|&(nx, ny)| {
-                // Check if the neighbor is within bounds and not a wall
-                if nx >= 0 && nx < self.width && ny >= 0 && ny < self.height {
-                    if let Some(block) = self.get_block(nx, ny) {
-                        !block.bind().is_wall()
-                    } else {
-                        false
-                    }
-                } else {
-                    false
-                }
-            })
-            .collect()
+    // Helper method to mark one corner of the goal region; the second one completes it
+    fn add_region_corner(&mut self, x: i32, y: i32) {
+        if self.grid.is_passable((x, y))
+            && let Some(mut block) = self.get_block(x, y)
+        {
+            block.bind_mut().set_color(Game::REGION_BLOCK_COLOR);
+        }
+        match self.region_corner.take() {
+            Some(corner) => self.goal_region = Some(GoalRegion::rect(corner, (x, y))),
+            None => self.region_corner = Some((x, y)),
+        }
     }
 
-    // Calculate the path using A* algorithm
-    async fn calculate_path(&mut self, mut rx: Option<Receiver<bool>>) {
-        godot_print!("Starting A* algorithm");
+    // Helper method to link two blocks as a portal; returns every cell whose portal changed
+    fn link_portal(&mut self, a: Pos, b: Pos, cost: f64) -> Vec<Pos> {
+        let replaced = [a, b]
+            .into_iter()
+            .filter_map(|pos| self.grid.portal_at(pos).copied());
+        let mut changed: Vec<Pos> = replaced.flat_map(|portal| [portal.a, portal.b]).collect();
+        self.grid.add_portal(Portal::new(a, b).with_cost(cost));
+        changed.extend([a, b]);
+        self.color_portal_ends(&changed);
+        changed
+    }
 
-        // Reset all non-wall blocks to their original color
-        self.reset_all_non_wall_blocks();
+    // Helper method to remove the portal with an end at `pos`; returns both of its ends
+    fn unlink_portal(&mut self, pos: Pos) -> Vec<Pos> {
+        let changed = self
+            .grid
+            .remove_portal(pos)
+            .map_or(vec![], |portal| vec![portal.a, portal.b]);
+        self.color_portal_ends(&changed);
+        changed
+    }
 
-        // Get start and end positions
-        let start_pos = match self.start_block {
-            Some(pos) => pos,
-            None => return, // No start block set
-        };
+    // Give open blocks the portal color if they are a portal end, else their own
+    fn color_portal_ends(&mut self, cells: &[Pos]) {
+        for &(x, y) in cells {
+            if self.is_stop((x, y)) || self.grid.is_wall((x, y)) {
+                continue;
+            }
+            if let Some(mut block) = self.get_block(x, y) {
+                if self.grid.portal_at((x, y)).is_some() {
+                    block.bind_mut().set_color(Game::PORTAL_BLOCK_COLOR);
+                } else {
+                    block.bind_mut().reset_color();
+                }
+            }
+        }
+    }
 
-        let end_pos = match self.end_block {
-            Some(pos) => pos,
-            None => return, // No end block set
+    // Draw a line between the centers of the two ends of every portal
+    fn draw_portals(&mut self) {
+        for mut line in self.portal_lines.drain(..) {
+            line.queue_free();
+        }
+        let Some(mut parent) = self.path_line.as_ref().and_then(|line| line.get_parent()) else {
+            return;
         };
+        let center = |block: &Gd<Block>| block.get_position() + block.get_size() / 2.0;
+        for portal in self.grid.portals().to_vec() {
+            let (Some(a), Some(b)) = (
+                self.get_block(portal.a.0, portal.a.1),
+                self.get_block(portal.b.0, portal.b.1),
+            ) else {
+                continue;
+            };
+            let mut line = Line2D::new_alloc();
+            line.set_points(&PackedVector2Array::from([center(&a), center(&b)]));
+            line.set_width(2.0);
+            line.set_default_color(Game::PORTAL_BLOCK_COLOR);
+            line.set_z_index(1);
+            parent.add_child(&line);
+            self.portal_lines.push(line);
+        }
+    }
 
-        godot_print!("Calculating path from {:?} to {:?}", start_pos, end_pos);
+    // Helper method to make a block one-way, or two-way again with None
+    fn set_one_way(&mut self, pos: Pos, one_way: Option<OneWay>) {
+        self.grid.set_one_way(pos, one_way);
+        if let Some(mut block) = self.get_block(pos.0, pos.1) {
+            block
+                .bind_mut()
+                .set_one_way(one_way.map(|one_way| one_way.delta()));
+        }
+    }
 
-        // Initialize open and closed sets
-        self.open_set = BinaryHeap::new();
-        self.closed_set = HashSet::new();
+    // Helper method to add a timed wall to a block
+    fn add_timed_wall(&mut self, wall: TimedWall) {
+        self.grid.add_timed_wall(wall);
+        self.label_timed_walls(wall.pos);
+    }
 
-        // Initialize came_from map to reconstruct the path
-        self.came_from = HashMap::new();
+    // Helper method to remove every timed wall of a block
+    fn remove_timed_walls(&mut self, pos: Pos) {
+        self.grid.remove_timed_walls(pos);
+        self.label_timed_walls(pos);
+    }
 
-        // Add start node to open set
-        let h_score = Self::manhattan_distance(start_pos, end_pos);
-        let f_score = 0 + h_score;
-        godot_print!(
-            "Initializing open set with start node at position {:?} with f_score={}, g_score=0, h_score={}",
-            start_pos,
-            f_score,
-            h_score
-        );
-        self.open_set.push(Node::new(start_pos, 0, h_score));
+    // Write the steps the timed walls of a block stand at on it, e.g. "0-3/6" for steps 0 to
+    // 2 of every 6, and shade it if one stands at the shown time step
+    fn label_timed_walls(&mut self, pos: Pos) {
+        let text = self
+            .grid
+            .timed_walls_at(pos)
+            .map(|wall| match wall.period {
+                Some(period) => format!("{}-{}/{}", wall.start, wall.end, period),
+                None => format!("{}-{}", wall.start, wall.end),
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        let active = self.is_timed_wall_active(pos, self.time);
+        if let Some(mut block) = self.get_block(pos.0, pos.1) {
+            block.bind_mut().set_time_windows(&text);
+            block.bind_mut().set_timed_wall_active(active);
+        }
+    }
 
-        let mut last_block: Option<Gd<Block>> = None;
+    fn is_timed_wall_active(&self, pos: Pos, t: usize) -> bool {
+        self.grid.timed_walls_at(pos).any(|wall| wall.is_active(t))
+    }
 
-        // Main A* loop
-        while let Some(current) = self.open_set.pop() {
-            if let Some(ref mut rx) = rx {
-                rx.recv().await.unwrap();
+    // Shade the blocks whose timed walls stand at time step `t`
+    fn show_time(&mut self, t: usize) {
+        self.time = t;
+        if let Some(label) = &mut self.time_label {
+            label.set_text(&t.to_string());
+        }
+        let cells: HashSet<Pos> = self
+            .grid
+            .timed_walls()
+            .iter()
+            .map(|wall| wall.pos)
+            .collect();
+        for pos in cells {
+            let active = self.is_timed_wall_active(pos, t);
+            if let Some(mut block) = self.get_block(pos.0, pos.1) {
+                block.bind_mut().set_timed_wall_active(active);
             }
-            let current_pos = current.position;
+        }
+    }
 
-            godot_print!(
-                "Processing node at position {:?} with f_score={}, g_score={}, h_score={} ==============================================================",
-                current_pos,
-                current.f_score,
-                current.g_score,
-                current.h_score
-            );
+    // Mark the block the unit is in, giving the previously marked one its color back
+    fn mark_unit(&mut self, pos: Option<Pos>) {
+        if let Some(((x, y), color)) = self.unit_block.take()
+            && let Some(mut block) = self.get_block(x, y)
+        {
+            block.bind_mut().set_color(color);
+        }
+        if let Some((x, y)) = pos
+            && let Some(mut block) = self.get_block(x, y)
+        {
+            self.unit_block = Some(((x, y), block.get_self_modulate()));
+            block.bind_mut().set_color(Game::CURRENT_BLOCK_COLOR);
+        }
+    }
 
-            // If we reached the end, reconstruct and return the path
-            if current_pos == end_pos {
-                godot_print!("Reached end position {:?}! Path found!", end_pos);
-                godot_print!("A* algorithm finished successfully");
-                self.reconstruct_path();
-                return;
+    // Clearance on every open block, or clear them; it only means something on square grids
+    fn draw_clearance(&mut self, show: bool) {
+        let show = show && !self.grid.topology().is_hex();
+        for x in 0..self.grid.width() {
+            for y in 0..self.grid.height() {
+                let clearance = self.grid.clearance((x, y));
+                if let Some(mut block) = self.get_block(x, y) {
+                    block
+                        .bind_mut()
+                        .set_clearance(Some(clearance).filter(|&c| show && c > 0));
+                }
             }
+        }
+    }
 
-            // Skip if already in closed set
-            if self.closed_set.contains(&current_pos) {
-                godot_print!(
-                    "Node at position {:?} is already in closed set, skipping",
-                    current_pos
-                );
-                continue;
+    // Color every cell a unit `size` cells wide covers along the path, not only the top-left
+    // ones the path is made of
+    fn draw_footprint(&mut self, path: &PathResult, size: i32) {
+        let cells: HashSet<Pos> = path
+            .cells
+            .iter()
+            .flat_map(|&(x, y)| {
+                (0..size).flat_map(move |dy| (0..size).map(move |dx| (x + dx, y + dy)))
+            })
+            .map(|pos| self.grid.wrap_pos(pos))
+            .collect();
+        for (x, y) in cells {
+            if !self.is_stop((x, y))
+                && let Some(mut block) = self.get_block(x, y)
+            {
+                block.bind_mut().set_color(Game::FOOTPRINT_BLOCK_COLOR);
             }
+        }
+    }
+
+    // Whether there is anything to search towards
+    fn has_goal(&self) -> bool {
+        self.end_block.is_some() || self.goal_region.is_some()
+    }
 
-            // Add to closed set and visualize
-            self.closed_set.insert(current_pos);
-            godot_print!("Added node at position {:?} to closed set", current_pos);
+    // Helper method to turn the start and end block into the next agent's, in its color
+    fn add_agent(&mut self, start: Pos, end: Pos) {
+        let color = Game::leg_color(self.agents.len());
+        for (x, y) in [start, end] {
+            if let Some(mut block) = self.get_block(x, y) {
+                block.bind_mut().set_color(color);
+            }
+        }
+        self.agents.push((start, end));
+    }
 
-            // Don't color start and end blocks
-            if current_pos != start_pos && current_pos != end_pos {
-                let cur_block = self.get_block(current_pos.0, current_pos.1);
-                if let Some(mut block) = cur_block.clone() {
-                    // Update block's f, g, h values
-                    block.bind_mut().set_f(current.f_score);
-                    block.bind_mut().set_g(current.g_score);
-                    block.bind_mut().set_h(current.h_score);
+    // Every cell a path may end at: the end blocks and the open cells of the goal region
+    fn end_cells(&self) -> Vec<Pos> {
+        let region = self.goal_region.iter().flat_map(GoalRegion::cells);
+        self.end_block
+            .into_iter()
+            .chain(self.extra_end_blocks.iter().copied())
+            .chain(region)
+            .filter(|&pos| self.grid.is_passable(pos))
+            .collect()
+    }
 
-                    // Color as closed (processed) block
-                    if let Some(mut block) = last_block {
-                        block.bind_mut().set_color(Game::CLOSED_BLOCK_COLOR);
+    // Arrow and integration cost on every block, or clear them without a field
+    fn draw_flow_field(&mut self, field: Option<&FlowField>) {
+        for x in 0..self.grid.width() {
+            for y in 0..self.grid.height() {
+                let direction = field.and_then(|field| field.direction((x, y)));
+                if let Some(mut block) = self.get_block(x, y) {
+                    let mut block = block.bind_mut();
+                    block.set_arrow(direction);
+                    match field.map(|field| field.cost((x, y))) {
+                        Some(cost) if cost.is_finite() => block.set_g(cost),
+                        _ => block.reset_labels(),
                     }
-                    block.bind_mut().set_color(Game::CURRENT_BLOCK_COLOR);
                 }
-
-                last_block = cur_block;
             }
+        }
+    }
 
-            // Check all neighbors
-            let neighbors = self.get_neighbors(current_pos);
-            godot_print!(
-                "Found {} neighbors for node at position {:?}",
-                neighbors.len(),
-                current_pos
-            );
+    // Start, end, waypoint and agent blocks keep their own color
+    fn is_stop(&self, pos: Pos) -> bool {
+        self.start_block == Some(pos)
+            || self.end_block == Some(pos)
+            || self.extra_end_blocks.contains(&pos)
+            || self.waypoints.contains(&pos)
+            || self
+                .agents
+                .iter()
+                .any(|&(start, end)| start == pos || end == pos)
+    }
 
-            for neighbor_pos in neighbors {
-                godot_print!("Processing neighbor at position {:?}", neighbor_pos);
+    // Helper method to reset a block's color
+    fn reset_block_color(&mut self, x: i32, y: i32) {
+        if let Some(mut block) = self.get_block(x, y) {
+            block.bind_mut().reset_color();
+        }
+    }
 
-                // Skip if in closed set
-                if self.closed_set.contains(&neighbor_pos) {
-                    godot_print!(
-                        "Neighbor at position {:?} is already in closed set, skipping",
-                        neighbor_pos
-                    );
-                    continue;
+    // Run the headless search, then replay its trace on the blocks
+    async fn calculate_path(
+        &mut self,
+        algorithm: Algorithm,
+        heuristic: Heuristic,
+        agent_size: i32,
+        mut rx: Option<Receiver<bool>>,
+    ) -> Result<Route, PathError> {
+        godot_print!(
+            "Starting {} algorithm with {} heuristic",
+            algorithm.name(),
+            heuristic.name()
+        );
+
+        // Reset all non-wall blocks to their original color
+        self.reset_all_non_wall_blocks();
+        self.set_threshold_text("-");
+        self.show_time(0);
+
+        // Get start and end positions; without an end block the goal region alone is the target
+        let query =
+            PathQuery::from_ends(self.start_block, self.end_block, self.goal_region.clone())?
+                .with_extra_goals(self.extra_end_blocks.iter().copied())
+                .with_algorithm(algorithm)
+                .with_heuristic(heuristic)
+                .with_agent_size(agent_size);
+        let (start_pos, end_pos) = (query.start, query.goal);
+
+        godot_print!(
+            "Calculating path from {:?} to {:?} (or {:?}, {:?}) through {:?}",
+            start_pos,
+            end_pos,
+            self.extra_end_blocks,
+            self.goal_region,
+            self.waypoints
+        );
+
+        let expansion_limit = matches!(
+            algorithm,
+            Algorithm::IterativeDeepeningAStar | Algorithm::SmaStar { .. }
+        )
+        .then_some(MEMORY_BOUNDED_EXPANSION_LIMIT);
+        let mut recorder = Recorder::new(self.cancelled.clone(), expansion_limit);
+        let result = if !self.waypoints.is_empty() {
+            // Every leg is a fresh search; the kept planners only serve a single start and end
+            self.planner = None;
+            Pathfinder::new().find_route(&self.grid, &query, &self.waypoints, &mut recorder)
+        } else {
+            let path = match algorithm {
+                // The kept planners serve one-cell units only
+                _ if agent_size > 1 => {
+                    self.planner = None;
+                    Pathfinder::new().find_path(&self.grid, &query, &mut recorder)
+                }
+                Algorithm::DStarLite => self.replan(&query, &mut recorder),
+                Algorithm::HierarchicalAStar { cluster_size } => {
+                    self.planner = None;
+                    self.hierarchical_path(cluster_size, &query, &mut recorder)
                 }
+                _ => {
+                    self.planner = None;
+                    Pathfinder::new().find_path(&self.grid, &query, &mut recorder)
+                }
+            };
+            path.map(|path| Route::new(vec![path]))
+        };
 
-                // Calculate h_score
-                let h_score = Self::manhattan_distance(neighbor_pos, end_pos);
-                let g_score = current.g_score + 1;
-                let f_score = h_score + g_score;
+        if recorder.hit_limit() {
+            godot_print!(
+                "{} gave up after {} expansions",
+                algorithm.name(),
+                MEMORY_BOUNDED_EXPANSION_LIMIT
+            );
+        }
+        let meetings = self
+            .replay(recorder.events, algorithm.name(), &mut rx)
+            .await?;
 
+        match &result {
+            Ok(route) => {
+                godot_print!(
+                    "Reached end position {:?}! Path found!",
+                    route.path.goal().unwrap_or(end_pos)
+                );
+                if agent_size > 1 {
+                    self.draw_footprint(&route.path, agent_size);
+                }
+                for (index, leg) in route.legs.iter().enumerate() {
+                    self.draw_path(leg, Game::leg_color(index));
+                }
+                if route.legs.len() > 1 {
+                    for (index, cost) in route.leg_costs().enumerate() {
+                        godot_print!("Leg {} costs {}", index + 1, cost);
+                    }
+                    godot_print!("Route costs {} in total", route.path.cost);
+                }
+                if algorithm.is_any_angle() {
+                    self.draw_segments(&route.path);
+                }
+                // Drawn over the path so the meeting cells stay visible
+                for pos in meetings {
+                    if !self.is_stop(pos)
+                        && let Some(mut block) = self.get_block(pos.0, pos.1)
+                    {
+                        block.bind_mut().set_color(Game::MEETING_BLOCK_COLOR);
+                    }
+                }
+            }
+            Err(err) => {
                 godot_print!(
-                    "Adding node at position {:?} to open set with f_score={}, g_score={}, h_score={}",
-                    neighbor_pos,
-                    f_score,
-                    g_score,
-                    h_score
+                    "{} algorithm finished without finding a path from {:?} to {:?}: {}",
+                    algorithm.name(),
+                    start_pos,
+                    end_pos,
+                    err
                 );
+            }
+        }
 
-                // Update came_from map
-                // if let Some(old) = self.came_from.get(&neighbor_pos) {
-                //     if current.g_score < old.g_score {
-                //         self.came_from.insert(neighbor_pos, current);
-                //     }
-                // } else {
-                //     self.came_from.insert(neighbor_pos, current);
-                // }
-                self.came_from
-                    .entry(neighbor_pos)
-                    .and_modify(|x| {
-                        if current.g_score < x.g_score {
-                            *x = current;
-                        }
-                    })
-                    .or_insert(current);
+        result
+    }
+
+    // Plan all agents together, replay the search, then walk them along their paths
+    async fn calculate_agent_paths(
+        &mut self,
+        coordination: Coordination,
+        mut rx: Option<Receiver<bool>>,
+    ) -> Result<MultiAgentPlan, PathError> {
+        godot_print!(
+            "Planning {} agents with {}: {:?}",
+            self.agents.len(),
+            coordination.name(),
+            self.agents
+        );
+        self.reset_all_non_wall_blocks();
+        self.set_threshold_text("-");
+
+        let mut recorder = Recorder::new(self.cancelled.clone(), None);
+        let result =
+            Pathfinder::new().find_paths(&self.grid, &self.agents, coordination, &mut recorder);
+        self.replay(recorder.events, coordination.name(), &mut rx)
+            .await?;
+
+        let plan = match result {
+            Ok(plan) => plan,
+            Err(err) => {
                 godot_print!(
-                    "Node ({}, {}) <- {:?}",
-                    neighbor_pos.0,
-                    neighbor_pos.1,
-                    current_pos
+                    "{} found no plan for the agents: {}",
+                    coordination.name(),
+                    err
                 );
-                // Add to open set
-                self.open_set
-                    .push(Node::new(neighbor_pos, g_score, h_score));
+                return Err(err);
+            }
+        };
+        for (index, path) in plan.paths.iter().enumerate() {
+            godot_print!("Agent {} costs {}: {:?}", index + 1, path.cost, path.cells);
+        }
+        godot_print!(
+            "Agents cost {} in total and are all at their end after {} steps",
+            plan.cost(),
+            plan.makespan()
+        );
 
-                // Visualize open set (but don't color start and end blocks)
-                if neighbor_pos != start_pos && neighbor_pos != end_pos {
-                    if let Some(mut block) = self.get_block(neighbor_pos.0, neighbor_pos.1) {
-                        // Update block's f, g, h values
-                        block.bind_mut().set_f(f_score);
-                        block.bind_mut().set_g(g_score);
-                        block.bind_mut().set_h(h_score);
+        // Every agent moves one cell per time step, in step mode one step per key press
+        self.reset_all_non_wall_blocks();
+        for t in 0..=plan.makespan() {
+            match &mut rx {
+                Some(rx) => {
+                    rx.recv().await.unwrap();
+                }
+                None => {
+                    AsyncRuntime::runtime()
+                        .spawn(sleep(Duration::from_millis(300)))
+                        .await
+                        .unwrap();
+                }
+            }
+            if self.cancelled.load(Ordering::Relaxed) {
+                return Err(PathError::Cancelled(plan.stats));
+            }
+            for path in &plan.paths {
+                if t > 0 && !self.is_stop(path.at(t - 1)) {
+                    let (x, y) = path.at(t - 1);
+                    self.reset_block_color(x, y);
+                }
+            }
+            for (index, path) in plan.paths.iter().enumerate() {
+                let (x, y) = path.at(t);
+                if !self.is_stop((x, y))
+                    && let Some(mut block) = self.get_block(x, y)
+                {
+                    block.bind_mut().set_color(Game::leg_color(index));
+                }
+            }
+        }
+
+        Ok(plan)
+    }
+
+    // Replay a recorded search trace on the blocks, in step mode one expansion per key press;
+    // returns the cells where bidirectional frontiers met
+    async fn replay(
+        &mut self,
+        events: Vec<SearchEvent>,
+        name: &str,
+        rx: &mut Option<Receiver<bool>>,
+    ) -> Result<Vec<Pos>, PathError> {
+        let mut replayed = SearchStats::default();
+        let mut last_block: Option<(Pos, Side, Gd<Block>)> = None;
+        let mut jump_points = HashSet::new();
+        let mut meetings = Vec::new();
+        for event in events {
+            match event {
+                SearchEvent::Expand { pos, f, g, h, side } => {
+                    if let Some(rx) = rx {
+                        rx.recv().await.unwrap();
+                    }
+                    if self.cancelled.load(Ordering::Relaxed) {
+                        godot_print!("{} algorithm cancelled", name);
+                        return Err(PathError::Cancelled(replayed));
+                    }
+                    replayed.nodes_expanded += 1;
+                    godot_print!(
+                        "Processing node at position {:?} with f_score={}, g_score={}, h_score={}",
+                        pos,
+                        f,
+                        g,
+                        h
+                    );
 
-                        // Only color if not already in closed set (which would be colored differently)
-                        if !self.closed_set.contains(&neighbor_pos) {
-                            block.bind_mut().set_color(Game::OPEN_BLOCK_COLOR);
+                    // Don't color start, end and waypoint blocks
+                    if self.is_stop(pos) {
+                        continue;
+                    }
+                    let cur_block = self.get_block(pos.0, pos.1);
+                    if let Some(mut block) = cur_block.clone() {
+                        // Update block's f, g, h values
+                        block.bind_mut().set_f(f);
+                        block.bind_mut().set_g(g);
+                        block.bind_mut().set_h(h);
+
+                        // Color as closed (processed) block
+                        if let Some((last_pos, last_side, mut block)) = last_block {
+                            block.bind_mut().set_color(Game::closed_color(
+                                last_side,
+                                jump_points.contains(&last_pos),
+                            ));
                         }
+                        block.bind_mut().set_color(Game::CURRENT_BLOCK_COLOR);
+                    }
+                    last_block = cur_block.map(|block| (pos, side, block));
+                }
+                SearchEvent::Open { pos, f, g, h, side } => {
+                    // Visualize open set (but don't color start, end and waypoint blocks)
+                    if self.is_stop(pos) {
+                        continue;
+                    }
+                    if let Some(mut block) = self.get_block(pos.0, pos.1) {
+                        block.bind_mut().set_f(f);
+                        block.bind_mut().set_g(g);
+                        block.bind_mut().set_h(h);
+                        block
+                            .bind_mut()
+                            .set_color(Game::open_color(side, jump_points.contains(&pos)));
+                    }
+                }
+                SearchEvent::JumpPoint { pos } => {
+                    jump_points.insert(pos);
+                }
+                SearchEvent::Time { t } => {
+                    // Shade the timed walls standing when the next node is expanded
+                    self.show_time(t);
+                }
+                SearchEvent::Meet { pos } => {
+                    godot_print!("Frontiers met at {:?}", pos);
+                    meetings.push(pos);
+                }
+                SearchEvent::Threshold { bound } => {
+                    // Every IDA* pass starts over from the start block
+                    godot_print!("Starting a pass with f threshold {}", bound);
+                    self.reset_all_non_wall_blocks();
+                    self.set_threshold_text(&format!("{:.2}", bound));
+                    last_block = None;
+                }
+                SearchEvent::Forget { pos } => {
+                    if !self.is_stop(pos) {
+                        self.reset_block_color(pos.0, pos.1);
+                    }
+                }
+                SearchEvent::Agent { index, start, goal } => {
+                    godot_print!(
+                        "Planning agent {} from {:?} to {:?}",
+                        index + 1,
+                        start,
+                        goal
+                    );
+                    // Agents are searched one at a time and replanned as they collide
+                    self.reset_all_non_wall_blocks();
+                    last_block = None;
+                    jump_points.clear();
+                }
+                SearchEvent::Leg { index, start, goal } => {
+                    godot_print!("Searching leg {} from {:?} to {:?}", index + 1, start, goal);
+                    // Each leg is searched from scratch, so is its trace
+                    if index > 0 {
+                        self.reset_all_non_wall_blocks();
                     }
+                    last_block = None;
+                    jump_points.clear();
                 }
             }
         }
+        Ok(meetings)
+    }
 
-        godot_print!("Open set is empty, no path found!");
-        godot_print!(
-            "A* algorithm finished without finding a path from {:?} to {:?}",
-            start_pos,
-            end_pos
-        );
+    fn set_threshold_text(&mut self, text: &str) {
+        if let Some(label) = &mut self.threshold_label {
+            label.set_text(text);
+        }
     }
 
-    // Reconstruct the path from came_from map
-    fn reconstruct_path(&mut self) {
-        let end_pos = self.end_block.unwrap();
-        godot_print!("Reconstructing path from end position {:?}", end_pos);
+    // Repair the kept D* Lite search if it was planned for the same query, otherwise start over
+    fn replan(
+        &mut self,
+        query: &PathQuery,
+        observer: &mut impl SearchObserver,
+    ) -> Result<PathResult, PathError> {
+        let planner = match &mut self.planner {
+            Some(planner) if planner.query() == query => planner,
+            _ => self.planner.insert(DStarLite::new(&self.grid, query)),
+        };
+        planner.plan(&self.grid, observer)
+    }
 
-        let mut current = end_pos;
-        let mut path = Vec::new();
+    // Query the kept HPA* graph, rebuilding it from scratch when the cluster size changed
+    fn hierarchical_path(
+        &mut self,
+        cluster_size: i32,
+        query: &PathQuery,
+        observer: &mut impl SearchObserver,
+    ) -> Result<PathResult, PathError> {
+        let hierarchy = match &mut self.hierarchy {
+            Some(hierarchy) if hierarchy.cluster_size() == cluster_size.max(1) => hierarchy,
+            _ => self
+                .hierarchy
+                .insert(Hierarchy::new(&self.grid, cluster_size)),
+        };
+        hierarchy.find_path(&self.grid, query, observer)
+    }
+
+    // Color the cells of a found path (or one leg of a route)
+    fn draw_path(&mut self, path: &PathResult, color: Color) {
+        for &pos in &path.cells {
+            // Don't color start, end and waypoint blocks
+            if !self.is_stop(pos)
+                && let Some(mut block) = self.get_block(pos.0, pos.1)
+            {
+                block.bind_mut().set_color(color);
+            }
+        }
 
-        // Reconstruct the path by following came_from map
-        while let Some(&prev) = self.came_from.get(&current) {
-            path.push(current);
-            godot_print!("Path node: {:?} <- {:?}", current, prev);
-            current = prev.position;
+        godot_print!(
+            "Path found with {} steps, cost {}, {} nodes expanded, {} duplicate entries, {} stale entries skipped",
+            path.steps(),
+            path.cost,
+            path.stats.nodes_expanded,
+            path.stats.duplicate_entries,
+            path.stats.stale_skipped
+        );
+    }
 
-            // Stop if we reached the start
-            if current == self.start_block.unwrap() {
-                godot_print!("Reached start position {:?}", current);
-                break;
+    // Connect the centers of the path's waypoints with straight lines. A segment crossing a
+    // wrapped edge runs off the grid on one side and comes back in on the other, so the line
+    // is cut there and continued by a seam line, and so is a path going through a portal.
+    fn draw_segments(&mut self, path: &PathResult) {
+        let center = |block: &Gd<Block>| block.get_position() + block.get_size() / 2.0;
+        let mut pieces = vec![PackedVector2Array::new()];
+        for pair in path.waypoints.windows(2) {
+            let (Some(from), Some(to)) = (
+                self.get_block(pair[0].0, pair[0].1),
+                self.get_block(pair[1].0, pair[1].1),
+            ) else {
+                continue;
+            };
+            // The unit disappears into a portal and comes out at the other end
+            if self.grid.is_portal_hop(pair[0], pair[1]) {
+                pieces.push(PackedVector2Array::new());
+                continue;
+            }
+            let (dx, dy) = self.grid.delta(pair[0], pair[1]);
+            let offset = Vector2::new(dx as f32, dy as f32) * from.get_size();
+            let piece = pieces.last_mut().unwrap();
+            if piece.is_empty() {
+                piece.push(center(&from));
+            }
+            piece.push(center(&from) + offset);
+            if center(&from) + offset != center(&to) {
+                pieces.push(PackedVector2Array::from([
+                    center(&to) - offset,
+                    center(&to),
+                ]));
             }
         }
 
-        // Visualize the path
-        for &pos in &path {
-            // Don't color start and end blocks
-            if pos != self.start_block.unwrap() && pos != self.end_block.unwrap() {
-                if let Some(mut block) = self.get_block(pos.0, pos.1) {
-                    block.bind_mut().set_color(Game::PATH_BLOCK_COLOR);
+        if let Some(line) = &mut self.path_line {
+            line.set_points(&pieces[0]);
+            for points in pieces[1..].iter().filter(|points| !points.is_empty()) {
+                if let Some(mut seam_line) = line
+                    .duplicate()
+                    .and_then(|node| node.try_cast::<Line2D>().ok())
+                {
+                    seam_line.set_points(points);
+                    if let Some(mut parent) = line.get_parent() {
+                        parent.add_child(&seam_line);
+                    }
+                    self.seam_lines.push(seam_line);
                 }
             }
         }
 
-        godot_print!("Path found with {} steps", path.len());
+        godot_print!(
+            "Path has {} straight segments, {} long",
+            path.waypoints.len().saturating_sub(1),
+            path.length(&self.grid)
+        );
     }
 
     // Reset all non-wall blocks to their original color
     fn reset_all_non_wall_blocks(&mut self) {
-        for x in 0..self.width {
-            for y in 0..self.height {
-                let is_start = self.start_block == Some((x, y));
-                let is_end = self.end_block == Some((x, y));
-                let is_wall = if let Some(block) = self.get_block(x, y) {
-                    block.bind().is_wall()
-                } else {
-                    false
-                };
+        self.mark_unit(None);
+        if let Some(line) = &mut self.path_line {
+            line.clear_points();
+        }
+        for mut seam_line in self.seam_lines.drain(..) {
+            seam_line.queue_free();
+        }
+        for x in 0..self.grid.width() {
+            for y in 0..self.grid.height() {
+                let is_stop = self.is_stop((x, y));
+                let is_wall = self.grid.is_wall((x, y));
 
-                if !is_start && !is_end && !is_wall {
+                if !is_stop && !is_wall {
                     self.reset_block_color(x, y);
+                    if self
+                        .goal_region
+                        .as_ref()
+                        .is_some_and(|region| region.contains((x, y)))
+                        && let Some(mut block) = self.get_block(x, y)
+                    {
+                        block.bind_mut().set_color(Game::REGION_BLOCK_COLOR);
+                    } else if self.grid.portal_at((x, y)).is_some()
+                        && let Some(mut block) = self.get_block(x, y)
+                    {
+                        block.bind_mut().set_color(Game::PORTAL_BLOCK_COLOR);
+                    }
                 }
             }
         }
     }
 }
 
+#[godot_api]
 impl Game {
-    fn on_block_clicked(&mut self, x: i32, y: i32) {
-        // Check if the block is a wall
-        let is_wall = if let Some(block) = self.controller.get_block(x, y) {
-            block.bind().is_wall()
+    fn last_route(&self) -> Option<&Route> {
+        self.last_result
+            .as_ref()
+            .and_then(|result| result.as_ref().ok())
+    }
+
+    fn last_path(&self) -> Option<&PathResult> {
+        self.last_route().map(|route| &route.path)
+    }
+
+    // Cells of the last found path, from start to end; empty when there is none
+    #[func]
+    fn get_path(&self) -> Array<Vector2i> {
+        self.last_path()
+            .iter()
+            .flat_map(|path| &path.cells)
+            .map(|&(x, y)| Vector2i::new(x, y))
+            .collect()
+    }
+
+    // Start, end and turning points of the last found path; empty when there is none
+    #[func]
+    fn get_waypoints(&self) -> Array<Vector2i> {
+        self.last_path()
+            .iter()
+            .flat_map(|path| &path.waypoints)
+            .map(|&(x, y)| Vector2i::new(x, y))
+            .collect()
+    }
+
+    // End block the last found path reached, or (-1, -1) when there is none
+    #[func]
+    fn get_reached_end(&self) -> Vector2i {
+        self.last_path()
+            .and_then(PathResult::goal)
+            .map_or(Vector2i::new(-1, -1), |(x, y)| Vector2i::new(x, y))
+    }
+
+    // Total cost of the last found path, or -1 when there is none
+    #[func]
+    fn get_path_cost(&self) -> f64 {
+        self.last_path().map_or(-1.0, |path| path.cost)
+    }
+
+    // Cost of each leg of the last route, one entry without waypoints; empty when there is none
+    #[func]
+    fn get_leg_costs(&self) -> PackedFloat64Array {
+        self.last_route()
+            .iter()
+            .flat_map(|route| route.leg_costs())
+            .collect()
+    }
+
+    // Stats of the last search, whether or not it found a path
+    fn last_stats(&self) -> SearchStats {
+        let stats = match &self.last_result {
+            Some(Ok(route)) => Some(route.path.stats),
+            Some(Err(err)) => err.stats(),
+            None => None,
+        };
+        stats.unwrap_or_default()
+    }
+
+    #[func]
+    fn get_nodes_expanded(&self) -> i64 {
+        self.last_stats().nodes_expanded as i64
+    }
+
+    // Open set entries pushed for a cell that already had a more expensive one
+    #[func]
+    fn get_duplicate_entries(&self) -> i64 {
+        self.last_stats().duplicate_entries as i64
+    }
+
+    // Why the last query failed; empty when it succeeded or none has run
+    #[func]
+    fn get_last_error(&self) -> GString {
+        match (&self.last_result, &self.last_plan) {
+            (Some(Err(err)), _) | (_, Some(Err(err))) => err.to_string().into(),
+            _ => GString::new(),
+        }
+    }
+
+    // Step the flow field takes from (x, y), e.g. (1, 0) to the right; (0, 0) at an end block,
+    // where the way goes on through a portal, where no end block can be reached or when no
+    // flow field is shown
+    #[func]
+    fn get_flow_direction(&self, x: i32, y: i32) -> Vector2i {
+        let direction = self
+            .flow_field
+            .as_ref()
+            .and_then(|field| field.direction((x, y)));
+        direction.map_or(Vector2i::ZERO, |(dx, dy)| Vector2i::new(dx, dy))
+    }
+
+    // Cost from (x, y) to the nearest end block in the flow field, or -1 when there is none
+    #[func]
+    fn get_flow_cost(&self, x: i32, y: i32) -> f64 {
+        let cost = self.flow_field.as_ref().map(|field| field.cost((x, y)));
+        cost.filter(|cost| cost.is_finite()).unwrap_or(-1.0)
+    }
+
+    // Cell of agent `index` at every time step of the last multi-agent plan; empty when there
+    // is none
+    #[func]
+    fn get_agent_path(&self, index: i64) -> Array<Vector2i> {
+        let plan = self.last_plan.as_ref().and_then(|plan| plan.as_ref().ok());
+        plan.and_then(|plan| plan.paths.get(usize::try_from(index).ok()?))
+            .iter()
+            .flat_map(|path| &path.cells)
+            .map(|&(x, y)| Vector2i::new(x, y))
+            .collect()
+    }
+
+    // Side of the biggest unit that fits with its top-left corner on (x, y), 0 on a wall
+    #[func]
+    fn get_clearance(&self, x: i32, y: i32) -> i32 {
+        self.controller.grid.clearance((x, y))
+    }
+
+    // Redraw the lines between portal ends, e.g. after the blocks moved
+    #[func]
+    fn draw_portals(&mut self) {
+        self.controller.draw_portals();
+    }
+
+    #[signal]
+    pub fn path_calculated(found: bool);
+
+    #[signal]
+    pub fn path_failed(reason: GString);
+}
+
+impl Game {
+    fn current_algorithm(&self) -> Algorithm {
+        self.algorithm.to_algorithm(
+            self.epsilon.max(1.0),
+            self.memory_limit.max(1) as usize,
+            self.cluster_size,
+        )
+    }
+
+    // Push the movement settings into the grid model and show them
+    fn update_movement(&mut self) {
+        let movement = if self.diagonal_movement {
+            Movement::EightWay {
+                diagonal_cost: self.diagonal_cost,
+                corner_cutting: self.corner_cutting.to_corner_cutting(),
+            }
         } else {
-            return;
+            Movement::FourWay
         };
+        self.controller.grid.set_movement(movement);
+        // Every edge may have changed, nothing of the kept search can be reused
+        self.controller.planner = None;
+        self.controller.hierarchy = None;
+        if self.flow_field.is_some() {
+            self.update_flow_field();
+        }
+
+        // Hex cells always have six neighbors, the movement settings don't apply
+        let topology = self.controller.grid.topology();
+        let text = match movement {
+            _ if topology.is_hex() => topology.name().to_string(),
+            Movement::FourWay => "4-way".to_string(),
+            Movement::EightWay {
+                diagonal_cost,
+                corner_cutting,
+            } => format!("8-way ({}, {:.2})", corner_cutting.name(), diagonal_cost),
+        };
+        self.movement_label.set_text(&text);
+    }
 
-        if is_wall {
-            return; // Can't set a wall as start/end block
+    // Show the agent size, and the clearance of the blocks for units bigger than one cell
+    fn update_agent_size(&mut self) {
+        self.agent_size_label
+            .set_text(&format!("{0}x{0}", self.agent_size.max(1)));
+        self.controller.draw_clearance(self.agent_size > 1);
+    }
+
+    // Push the wrap-around setting into the grid model and show it
+    fn update_wrap(&mut self) {
+        let wrap = self.wrap.to_wrap();
+        self.controller.grid.set_wrap(wrap);
+        // Edges across the borders appear or vanish, nothing of the kept search can be reused
+        self.controller.planner = None;
+        self.controller.hierarchy = None;
+        if self.flow_field.is_some() {
+            self.update_flow_field();
+        }
+        self.wrap_label.set_text(wrap.name());
+    }
+
+    fn update_algorithm_label(&mut self) {
+        let name = self.current_algorithm().name();
+        self.algorithm_label.set_text(name);
+    }
+
+    fn on_block_clicked(&mut self, x: i32, y: i32) {
+        // Shift-click edits the map instead
+        if Input::singleton().is_key_pressed(Key::SHIFT) {
+            self.toggle_wall(x, y);
+            return;
+        }
+
+        // O-click turns a block's one-way arrow: up, right, down, left, then two-way again
+        if Input::singleton().is_key_pressed(Key::O) {
+            self.turn_one_way(x, y);
+            return;
+        }
+
+        // G-click moves a block's timed wall to the next steps of the period, then removes it
+        if Input::singleton().is_key_pressed(Key::G) {
+            self.cycle_timed_wall(x, y);
+            return;
+        }
+
+        // After the `P` key the next two clicks link a portal, or one click on a portal end
+        // removes its portal
+        if self.placing_portal {
+            self.place_portal_end(x, y);
+            return;
+        }
+
+        // Alt-click places the two corners of a goal region, which may cover walls
+        if Input::singleton().is_key_pressed(Key::ALT) {
+            if !self.is_processing && self.controller.grid.in_bounds((x, y)) {
+                self.controller.add_region_corner(x, y);
+                if self.controller.region_corner.is_none() {
+                    godot_print!("Set goal region {:?}", self.controller.goal_region);
+                    if self.controller.start_block.is_some() {
+                        self.start_calculation();
+                    }
+                }
+            }
+            return;
+        }
+
+        // Can't set a wall (or anything off the grid) as start/end block
+        if !self.controller.grid.is_passable((x, y)) {
+            return;
         }
 
         // Check if we need to set start or end block
@@ -502,35 +1761,323 @@ impl Game {
             // Set as start block
             self.controller.set_as_start_block(x, y);
         } else if self.controller.end_block.is_none() {
+            // A search for the goal region may still be running without an end block
+            if self.is_processing {
+                return;
+            }
             // Set as end block
             self.controller.set_as_end_block(x, y);
 
             // Calculate path when both start and end blocks are set
-            self.is_processing = true;
-            let mut ctr = self.controller.clone();
-            let rx = if self.step_mode {
-                let (tx, rx) = channel::<bool>(1);
-                self.tx = Some(tx);
-                Some(rx)
+            self.start_calculation();
+        } else if !self.is_processing && !self.controller.is_stop((x, y)) {
+            if Input::singleton().is_key_pressed(Key::CTRL) {
+                // Ctrl-click adds another end block; the path goes to the nearest one
+                self.controller.add_extra_end_block(x, y);
+                godot_print!("Add end block at {:?}", (x, y));
             } else {
-                None
-            };
-            let mut game = self.to_gd();
-            godot::task::spawn(async move {
-                ctr.calculate_path(rx).await;
-                AsyncRuntime::runtime()
-                    .spawn(async {
-                        sleep(Duration::from_millis(100)).await;
-                    })
-                    .await
-                    .unwrap();
-                game.bind_mut().is_processing = false;
-                game.bind_mut().tx = None;
-            });
+                // Further clicks add stops to visit before the end block
+                self.controller.add_waypoint(x, y);
+                godot_print!(
+                    "Add waypoint {} at {:?}",
+                    self.controller.waypoints.len(),
+                    (x, y)
+                );
+            }
+            self.start_calculation();
+        }
+    }
+
+    // Turn a block into a wall or back, then update the path if there is one
+    fn toggle_wall(&mut self, x: i32, y: i32) {
+        let pos = (x, y);
+        if self.is_processing
+            || !self.controller.grid.in_bounds(pos)
+            || self.controller.is_stop(pos)
+        {
+            return;
+        }
+
+        let wall = !self.controller.grid.is_wall(pos);
+        self.controller.grid.set_wall(pos, wall);
+        if let Some(mut block) = self.controller.get_block(x, y) {
+            if wall {
+                block.bind_mut().set_as_wall();
+            } else {
+                block.bind_mut().clear_wall();
+            }
+        }
+        self.controller.color_portal_ends(&[pos]);
+        godot_print!("Toggle wall at {:?}: {}", pos, wall);
+        self.map_changed(&[pos]);
+    }
+
+    // Turn the arrow of a one-way block, then update the path if there is one
+    fn turn_one_way(&mut self, x: i32, y: i32) {
+        let pos = (x, y);
+        if self.is_processing || !self.controller.grid.in_bounds(pos) {
+            return;
+        }
+
+        let one_way = match self.controller.grid.one_way(pos) {
+            None => Some(OneWay::ALL[0]),
+            Some(one_way) => OneWay::ALL
+                .into_iter()
+                .skip_while(|&other| other != one_way)
+                .nth(1),
+        };
+        self.controller.set_one_way(pos, one_way);
+        godot_print!(
+            "Set one-way at {:?}: {}",
+            pos,
+            one_way.map_or("None", |one_way| one_way.name())
+        );
+        self.map_changed(&[pos]);
+    }
+
+    // Give a block a timed wall standing for `timed_wall_steps` steps from step 0, move it on
+    // by that many steps on every click while it still starts within the period, then remove
+    // it; then update the path if there is one
+    fn cycle_timed_wall(&mut self, x: i32, y: i32) {
+        let pos = (x, y);
+        if self.is_processing || !self.controller.grid.in_bounds(pos) {
+            return;
+        }
+
+        let steps = self.timed_wall_steps.max(1) as usize;
+        let period = self.timed_wall_period.max(0) as usize;
+        let start = match self.controller.grid.timed_walls_at(pos).next() {
+            None => Some(0),
+            Some(wall) => Some(wall.start + steps).filter(|&start| start < period),
+        };
+        self.controller.remove_timed_walls(pos);
+        let wall = start.map(|start| TimedWall::new(pos, start, start + steps).repeating(period));
+        if let Some(wall) = wall {
+            self.controller.add_timed_wall(wall);
+        }
+        godot_print!("Set timed wall at {:?}: {:?}", pos, wall);
+        self.map_changed(&[pos]);
+    }
+
+    // Show the timed walls one time step later or earlier, and where the unit of the last
+    // path is then
+    fn scrub_time(&mut self, forward: bool) {
+        let t = if forward {
+            self.controller.time + 1
+        } else {
+            self.controller.time.saturating_sub(1)
+        };
+        let unit = self
+            .last_path()
+            .and_then(|path| path.cells.get(t.min(path.steps())).copied());
+        self.controller.show_time(t);
+        self.controller.mark_unit(unit);
+        godot_print!("Show time step {}, unit at {:?}", t, unit);
+    }
+
+    // Let the kept searches and the flow field know about edited cells, then search again
+    fn map_changed(&mut self, changed: &[Pos]) {
+        if let Some(planner) = &mut self.controller.planner {
+            planner.update_cells(changed);
+        }
+        if let Some(hierarchy) = &mut self.controller.hierarchy {
+            hierarchy.update_cells(changed);
+        }
+        if self.flow_field.is_some() {
+            self.update_flow_field();
+        }
+        if self.agent_size > 1 {
+            self.controller.draw_clearance(true);
+        }
+
+        if self.controller.start_block.is_some() && self.controller.has_goal() {
+            self.start_calculation();
+        } else if !self.controller.agents.is_empty() {
+            self.start_agent_calculation();
         }
     }
 
+    // Place one end of a portal while in portal mode, then update the path if there is one
+    fn place_portal_end(&mut self, x: i32, y: i32) {
+        let pos = (x, y);
+        if self.is_processing || !self.controller.grid.is_passable(pos) {
+            return;
+        }
+
+        let changed = match self.controller.portal_end.take() {
+            None if self.controller.grid.portal_at(pos).is_some() => {
+                godot_print!("Remove portal at {:?}", pos);
+                self.controller.unlink_portal(pos)
+            }
+            None => {
+                if !self.controller.is_stop(pos)
+                    && let Some(mut block) = self.controller.get_block(x, y)
+                {
+                    block.bind_mut().set_color(Game::PORTAL_BLOCK_COLOR);
+                }
+                self.controller.portal_end = Some(pos);
+                return;
+            }
+            // Clicking the first end again gives up on this portal
+            Some(end) if end == pos => {
+                self.controller.color_portal_ends(&[pos]);
+                vec![]
+            }
+            Some(end) => {
+                godot_print!("Link portal {:?} <-> {:?}", end, pos);
+                self.controller.link_portal(end, pos, self.portal_cost)
+            }
+        };
+        self.placing_portal = false;
+        if changed.is_empty() {
+            return;
+        }
+
+        self.controller.draw_portals();
+        self.map_changed(&changed);
+    }
+
+    // Run the search for the current start and end blocks in the background
+    fn start_calculation(&mut self) {
+        self.is_processing = true;
+        self.controller.cancelled.store(false, Ordering::Relaxed);
+        // The run repaints the blocks, so the unit shown while scrubbing is let go first
+        self.controller.mark_unit(None);
+        let mut ctr = self.controller.clone();
+        let algorithm = self.current_algorithm();
+        let heuristic = self.heuristic.to_heuristic();
+        let agent_size = self.agent_size.max(1);
+        let rx = self.step_receiver();
+        let mut game = self.to_gd();
+        godot::task::spawn(async move {
+            let result = ctr
+                .calculate_path(algorithm, heuristic, agent_size, rx)
+                .await;
+            AsyncRuntime::runtime()
+                .spawn(async {
+                    sleep(Duration::from_millis(100)).await;
+                })
+                .await
+                .unwrap();
+            let error = result.as_ref().err().map(ToString::to_string);
+            {
+                let mut this = game.bind_mut();
+                this.is_processing = false;
+                this.tx = None;
+                this.last_result = Some(result);
+                this.last_plan = None;
+                // A cancelled run was cleared by the right-click; don't bring its state back
+                if !ctr.cancelled.load(Ordering::Relaxed) {
+                    this.controller.planner = ctr.planner;
+                }
+                // Not tied to a query, so it stays valid either way
+                this.controller.hierarchy = ctr.hierarchy;
+                // Scrubbing goes on from the time step the replay stopped at
+                this.controller.time = ctr.time;
+            }
+            game.emit_signal("path_calculated", &[error.is_none().to_variant()]);
+            if let Some(error) = error {
+                game.emit_signal("path_failed", &[GString::from(error).to_variant()]);
+            }
+        });
+    }
+
+    // Show the flow field towards the end blocks, or hide it again
+    fn toggle_flow_field(&mut self) {
+        if self.flow_field.take().is_some() {
+            self.controller.draw_flow_field(None);
+            godot_print!("Hide flow field");
+        } else {
+            self.update_flow_field();
+        }
+    }
+
+    // Build the flow field for the current end blocks and draw it
+    fn update_flow_field(&mut self) {
+        let goals = self.controller.end_cells();
+        if goals.is_empty() {
+            godot_print!("Set an end block before building a flow field");
+            self.flow_field = None;
+            self.controller.draw_flow_field(None);
+            return;
+        }
+        let field = FlowField::new(&self.controller.grid, &goals);
+        godot_print!("Build flow field towards {:?}", goals);
+        self.controller.draw_flow_field(Some(&field));
+        self.flow_field = Some(field);
+    }
+
+    // Plan all agents together in the background, then walk them along their paths
+    fn start_agent_calculation(&mut self) {
+        self.is_processing = true;
+        self.controller.cancelled.store(false, Ordering::Relaxed);
+        // The run repaints the blocks, so the unit shown while scrubbing is let go first
+        self.controller.mark_unit(None);
+        let mut ctr = self.controller.clone();
+        let coordination = self.coordination.to_coordination();
+        let rx = self.step_receiver();
+        let mut game = self.to_gd();
+        godot::task::spawn(async move {
+            let result = ctr.calculate_agent_paths(coordination, rx).await;
+            let error = result.as_ref().err().map(ToString::to_string);
+            {
+                let mut this = game.bind_mut();
+                this.is_processing = false;
+                this.tx = None;
+                this.last_result = None;
+                this.last_plan = Some(result);
+            }
+            game.emit_signal("path_calculated", &[error.is_none().to_variant()]);
+            if let Some(error) = error {
+                game.emit_signal("path_failed", &[GString::from(error).to_variant()]);
+            }
+        });
+    }
+
+    // In step mode, the receiving end of the key presses that advance the replay
+    fn step_receiver(&mut self) -> Option<Receiver<bool>> {
+        if self.step_mode {
+            let (tx, rx) = channel::<bool>(1);
+            self.tx = Some(tx);
+            Some(rx)
+        } else {
+            None
+        }
+    }
+
+    // Turn the start and end blocks into one more agent, then plan all agents together
+    fn add_agent(&mut self) {
+        let (Some(start), Some(end)) = (self.controller.start_block, self.controller.end_block)
+        else {
+            return;
+        };
+        self.controller.start_block = None;
+        self.controller.end_block = None;
+        self.controller.add_agent(start, end);
+        godot_print!(
+            "Add agent {} from {:?} to {:?}",
+            self.controller.agents.len(),
+            start,
+            end
+        );
+        self.start_agent_calculation();
+    }
+
     fn on_block_right_clicked(&mut self) {
+        // Stop a running replay; wake it up in case it is waiting for a step
+        if self.is_processing {
+            self.controller.cancelled.store(true, Ordering::Relaxed);
+            if let Some(tx) = &self.tx {
+                let _ = tx.send(true);
+            }
+        }
+        self.last_result = None;
+        self.last_plan = None;
+        self.controller.planner = None;
+        if self.flow_field.take().is_some() {
+            self.controller.draw_flow_field(None);
+        }
+
         // Clear start and end blocks and reset colors
         if let Some((x, y)) = self.controller.start_block {
             self.controller.reset_block_color(x, y);
@@ -542,21 +2089,18 @@ impl Game {
             self.controller.end_block = None;
         }
 
-        // Reset all path blocks
-        for x in 0..self.width {
-            for y in 0..self.height {
-                let is_start = self.controller.start_block == Some((x, y));
-                let is_end = self.controller.end_block == Some((x, y));
-                let is_wall = if let Some(block) = self.controller.get_block(x, y) {
-                    block.bind().is_wall()
-                } else {
-                    false
-                };
+        // Waypoints, extra end blocks and agents lose their color with the path blocks below
+        self.controller.waypoints.clear();
+        self.controller.extra_end_blocks.clear();
+        self.controller.goal_region = None;
+        self.controller.region_corner = None;
+        self.controller.agents.clear();
+        // Portals stay, like walls; only one half placed is dropped
+        self.placing_portal = false;
+        self.controller.portal_end = None;
 
-                if !is_start && !is_end && !is_wall {
-                    self.controller.reset_block_color(x, y);
-                }
-            }
-        }
+        // Reset all path blocks
+        self.controller.reset_all_non_wall_blocks();
+        self.controller.show_time(0);
     }
 }
@@ -1,5 +1,6 @@
 mod block;
 mod game;
+pub mod pathfinding;
 // mod video_player;
 
 use godot::classes::Engine;
@@ -21,6 +22,8 @@ struct MyExtension;
 
 #[gdextension]
 unsafe impl ExtensionLibrary for MyExtension {
+    // The init levels are matched on so more of them can be handled alongside the scene one
+    #[allow(clippy::single_match)]
     fn on_level_init(level: InitLevel) {
         match level {
             InitLevel::Scene => {
@@ -34,6 +37,7 @@ unsafe impl ExtensionLibrary for MyExtension {
         }
     }
 
+    #[allow(clippy::single_match)]
     fn on_level_deinit(level: InitLevel) {
         match level {
             InitLevel::Scene => {
@@ -0,0 +1,132 @@
+// Which search strategy a query runs. They all share the same open/closed set engine and
+// only differ in how a node's priority is built from its g and h scores.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum Algorithm {
+    // f = g + h
+    #[default]
+    AStar,
+    // f = g, the heuristic is ignored
+    Dijkstra,
+    // Every step costs 1 and there is no heuristic, so nodes are expanded by depth
+    BreadthFirst,
+    // f = h, fast but not optimal
+    GreedyBestFirst,
+    // f = g + epsilon * h; the path costs at most epsilon times the optimum
+    WeightedAStar {
+        epsilon: f64,
+    },
+    // A* over jump points only; needs uniform terrain costs
+    JumpPointSearch,
+    // Jump point search reading ray lengths from the grid's precomputed jump table
+    JumpPointSearchPlus,
+    // Any-angle A*: a neighbor is linked straight to the current node's parent whenever
+    // there is line of sight, so paths are not bound to grid edges
+    ThetaStar,
+    // Theta* that assumes line of sight when opening a node and only checks it on expansion
+    LazyThetaStar,
+    // A* grown from both ends at once, stopping once no cheaper meeting is possible
+    BidirectionalAStar,
+    // Incremental search from the goal; keeps its state between queries through `DStarLite`
+    DStarLite,
+    // Depth-first passes with a growing f bound; memory only for the current path
+    IterativeDeepeningAStar,
+    // A* that holds at most `memory_limit` nodes, forgetting the worst leaves when full
+    SmaStar {
+        memory_limit: usize,
+    },
+    // HPA*: searches a graph of cluster entrances, then refines inside each cluster; keeps
+    // that graph between queries through `Hierarchy`
+    HierarchicalAStar {
+        cluster_size: i32,
+    },
+    // A* over (cell, time step) that steps around timed walls, waiting in place where that
+    // pays off; every move and wait takes one time step, and each leg of a route starts at
+    // step 0 again
+    SpaceTimeAStar,
+}
+
+impl Algorithm {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Algorithm::AStar => "A*",
+            Algorithm::Dijkstra => "Dijkstra",
+            Algorithm::BreadthFirst => "Breadth-first",
+            Algorithm::GreedyBestFirst => "Greedy best-first",
+            Algorithm::WeightedAStar { .. } => "Weighted A*",
+            Algorithm::JumpPointSearch => "JPS",
+            Algorithm::JumpPointSearchPlus => "JPS+",
+            Algorithm::ThetaStar => "Theta*",
+            Algorithm::LazyThetaStar => "Lazy Theta*",
+            Algorithm::BidirectionalAStar => "Bidirectional A*",
+            Algorithm::DStarLite => "D* Lite",
+            Algorithm::IterativeDeepeningAStar => "IDA*",
+            Algorithm::SmaStar { .. } => "SMA*",
+            Algorithm::HierarchicalAStar { .. } => "HPA*",
+            Algorithm::SpaceTimeAStar => "Space-time A*",
+        }
+    }
+
+    pub fn is_jump_point_search(&self) -> bool {
+        matches!(
+            self,
+            Algorithm::JumpPointSearch | Algorithm::JumpPointSearchPlus
+        )
+    }
+
+    // Any-angle searches return waypoints joined by straight segments rather than adjacent cells
+    pub fn is_any_angle(&self) -> bool {
+        matches!(self, Algorithm::ThetaStar | Algorithm::LazyThetaStar)
+    }
+
+    // Whether the heuristic is evaluated at all (h is reported as 0 otherwise)
+    pub fn uses_heuristic(&self) -> bool {
+        !matches!(self, Algorithm::Dijkstra | Algorithm::BreadthFirst)
+    }
+
+    // Cost the search accounts for a move that really costs `cost`
+    pub fn step_cost(&self, cost: f64) -> f64 {
+        match self {
+            Algorithm::BreadthFirst => 1.0,
+            _ => cost,
+        }
+    }
+
+    // Priority of a node in the open set; lower is expanded first
+    pub fn priority(&self, g: f64, h: f64) -> f64 {
+        match self {
+            Algorithm::AStar
+            | Algorithm::Dijkstra
+            | Algorithm::BreadthFirst
+            | Algorithm::JumpPointSearch
+            | Algorithm::JumpPointSearchPlus
+            | Algorithm::ThetaStar
+            | Algorithm::LazyThetaStar
+            | Algorithm::BidirectionalAStar
+            | Algorithm::DStarLite
+            | Algorithm::IterativeDeepeningAStar
+            | Algorithm::SmaStar { .. }
+            | Algorithm::HierarchicalAStar { .. }
+            | Algorithm::SpaceTimeAStar => g + h,
+            Algorithm::GreedyBestFirst => h,
+            Algorithm::WeightedAStar { epsilon } => g + epsilon * h,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn priorities_weigh_the_heuristic() {
+        assert_eq!(Algorithm::AStar.priority(2.0, 3.0), 5.0);
+        assert_eq!(Algorithm::GreedyBestFirst.priority(2.0, 3.0), 3.0);
+        let weighted = Algorithm::WeightedAStar { epsilon: 2.0 };
+        assert_eq!(weighted.priority(2.0, 3.0), 8.0);
+        // Without a heuristic h is always 0, and breadth-first counts steps
+        assert!(!Algorithm::Dijkstra.uses_heuristic());
+        assert!(!Algorithm::BreadthFirst.uses_heuristic());
+        assert_eq!(Algorithm::BreadthFirst.step_cost(2.5), 1.0);
+        assert_eq!(weighted.step_cost(2.5), 2.5);
+    }
+}
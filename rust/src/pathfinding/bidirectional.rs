@@ -0,0 +1,250 @@
+use super::path::turning_points;
+use super::{
+    Grid, Heuristic, Node, PathError, PathResult, Pathfinder, Pos, SearchEvent, SearchObserver,
+    SearchStats, Side,
+};
+
+// Bidirectional A*: one frontier grows forward from the start and one backward from the goal
+// (from all of them at once for multi-goal queries), always expanding the smaller one. Every
+// time a relaxation reaches a cell the other side has a g for, the two halves form a candidate
+// path. With a consistent heuristic, no cheaper path exists once either frontier's lowest f
+// reaches the best candidate's cost.
+impl Pathfinder {
+    pub(super) fn find_path_bidirectional(
+        &mut self,
+        grid: &Grid,
+        start: Pos,
+        goals: &[Pos],
+        heuristic: &Heuristic,
+        observer: &mut impl SearchObserver,
+    ) -> Result<PathResult, PathError> {
+        // The backward frontier estimates the distance back to the start
+        let make_node = |side: Side, pos: Pos, g_score: f64| {
+            let h_score = match side {
+                Side::Forward => heuristic.estimate_nearest(grid, pos, goals),
+                Side::Backward => heuristic.estimate(grid, pos, start),
+            };
+            Node::new(pos, g_score, h_score)
+        };
+
+        let mut stats = SearchStats::default();
+        self.forward.clear();
+        self.backward.clear();
+
+        let roots = goals.iter().map(|&goal| (Side::Backward, goal));
+        for (side, root) in [(Side::Forward, start)].into_iter().chain(roots) {
+            let node = make_node(side, root, 0.0);
+            let frontier = match side {
+                Side::Forward => &mut self.forward,
+                Side::Backward => &mut self.backward,
+            };
+            frontier.best_g.insert(root, 0.0);
+            frontier.open_set.push(node);
+            observer.on_event(SearchEvent::Open {
+                pos: root,
+                f: node.f_score,
+                g: node.g_score,
+                h: node.h_score,
+                side,
+            });
+        }
+
+        // Cheapest path found so far: its cost and the cell where the two halves join
+        let mut best: Option<(f64, Pos)> = goals.contains(&start).then_some((0.0, start));
+
+        loop {
+            if observer.is_cancelled() {
+                return Err(PathError::Cancelled(stats));
+            }
+            let (Some(forward_top), Some(backward_top)) =
+                (self.forward.open_set.peek(), self.backward.open_set.peek())
+            else {
+                // One side ran dry, so every path out of it has been seen
+                break;
+            };
+            if best.is_some_and(|(cost, _)| forward_top.f_score.max(backward_top.f_score) >= cost) {
+                break;
+            }
+
+            let side = if self.forward.open_set.len() <= self.backward.open_set.len() {
+                Side::Forward
+            } else {
+                Side::Backward
+            };
+            let (this, other) = match side {
+                Side::Forward => (&mut self.forward, &self.backward),
+                Side::Backward => (&mut self.backward, &self.forward),
+            };
+
+            let Some(current) = this.open_set.pop() else {
+                break;
+            };
+            let current_pos = current.position;
+
+            // Lazy deletion: skip entries already expanded or superseded by a cheaper one
+            if this.closed_set.contains(&current_pos) || current.g_score > this.best_g[&current_pos]
+            {
+                stats.stale_skipped += 1;
+                continue;
+            }
+
+            this.closed_set.insert(current_pos);
+            stats.nodes_expanded += 1;
+            observer.on_event(SearchEvent::Expand {
+                pos: current_pos,
+                f: current.f_score,
+                g: current.g_score,
+                h: current.h_score,
+                side,
+            });
+
+            let neighbors = match side {
+                Side::Forward => grid.get_neighbors(current_pos),
+                Side::Backward => grid.get_predecessors(current_pos),
+            };
+            for (neighbor_pos, step_cost) in neighbors {
+                // Skip if in closed set
+                if this.closed_set.contains(&neighbor_pos) {
+                    continue;
+                }
+
+                // Only relax if this is a cheaper way to reach the neighbor
+                let tentative_g = current.g_score + step_cost;
+                let previous_g = this.best_g.get(&neighbor_pos).copied();
+                if previous_g.is_some_and(|g| tentative_g >= g) {
+                    continue;
+                }
+                if previous_g.is_some() {
+                    // The older, more expensive entry stays in the heap until it is skipped
+                    stats.duplicate_entries += 1;
+                }
+                this.best_g.insert(neighbor_pos, tentative_g);
+                this.came_from.insert(neighbor_pos, current_pos);
+
+                let neighbor = make_node(side, neighbor_pos, tentative_g);
+                this.open_set.push(neighbor);
+                observer.on_event(SearchEvent::Open {
+                    pos: neighbor_pos,
+                    f: neighbor.f_score,
+                    g: neighbor.g_score,
+                    h: neighbor.h_score,
+                    side,
+                });
+
+                // The other side already reached this cell: the two halves form a path
+                if let Some(&other_g) = other.best_g.get(&neighbor_pos) {
+                    let cost = tentative_g + other_g;
+                    if best.is_none_or(|(best_cost, _)| cost < best_cost) {
+                        best = Some((cost, neighbor_pos));
+                    }
+                }
+            }
+        }
+
+        let Some((_, meeting)) = best else {
+            return Err(PathError::Unreachable(stats));
+        };
+        observer.on_event(SearchEvent::Meet { pos: meeting });
+
+        // Start to meeting cell, then on from the meeting cell to the goal the backward half
+        // grew from
+        let mut cells = self.forward.reconstruct_path(start, meeting);
+        let goal = self.backward.root_of(meeting);
+        let mut to_goal = self.backward.reconstruct_path(goal, meeting);
+        to_goal.reverse();
+        cells.extend(to_goal.into_iter().skip(1));
+
+        Ok(PathResult {
+            cost: grid.path_cost(&cells),
+            waypoints: turning_points(grid, &cells),
+            cells,
+            stats,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pathfinding::test_support::{
+        assert_close, movements, parse, random_grid, reference_cost,
+    };
+    use crate::pathfinding::{
+        Algorithm, Heuristic, Movement, PathError, PathQuery, PathResult, Pathfinder, Pos,
+        SearchEvent,
+    };
+
+    fn query(start: Pos, goal: Pos) -> PathQuery {
+        PathQuery::new(start, goal).with_algorithm(Algorithm::BidirectionalAStar)
+    }
+
+    #[test]
+    fn bidirectional_paths_are_optimal() {
+        let mut reached = 0;
+        for seed in 0..60 {
+            let mut grid = random_grid(seed, 17, 13, 0.3, seed % 2 == 0);
+            grid.set_movement(movements()[seed as usize % 4]);
+            let heuristic = match grid.movement() {
+                Movement::FourWay => Heuristic::Manhattan,
+                Movement::EightWay { .. } => Heuristic::Octile,
+            };
+            let (start, goal) = ((0, 0), (16, 12));
+            let mut events = Vec::new();
+            let result = Pathfinder::new().find_path(
+                &grid,
+                &query(start, goal).with_heuristic(heuristic),
+                &mut events,
+            );
+            match (reference_cost(&grid, start, goal), result) {
+                (Some(cost), Ok(path)) => {
+                    assert_close(path.cost, cost);
+                    assert_close(grid.path_cost(&path.cells), cost);
+                    assert_eq!(path.cells.first(), Some(&start));
+                    assert_eq!(path.goal(), Some(goal));
+                    // The two halves are joined on a cell of the path
+                    let meetings: Vec<Pos> = events
+                        .iter()
+                        .filter_map(|event| match *event {
+                            SearchEvent::Meet { pos } => Some(pos),
+                            _ => None,
+                        })
+                        .collect();
+                    assert_eq!(meetings.len(), 1);
+                    assert!(path.cells.contains(&meetings[0]));
+                    reached += 1;
+                }
+                (None, Err(PathError::Unreachable(_))) => {}
+                (cost, result) => panic!("seed {seed}: {result:?} against {cost:?}"),
+            }
+        }
+        assert!(reached > 0);
+    }
+
+    #[test]
+    fn backward_frontier_grows_from_every_goal() {
+        let (grid, start, goal) = parse(
+            "
+            ..S......G
+            ..........
+            ",
+        );
+        let query = query(start, goal).with_extra_goals([(0, 1)]);
+        let path = Pathfinder::new().find_path(&grid, &query, &mut ()).unwrap();
+        assert_eq!(path.goal(), Some((0, 1)));
+        assert_close(path.cost, 3.0);
+    }
+
+    #[test]
+    fn start_on_the_goal_and_no_way_through() {
+        let (grid, start, goal) = parse("S.#G");
+        let find = |query: &PathQuery| -> Result<PathResult, PathError> {
+            Pathfinder::new().find_path(&grid, query, &mut ())
+        };
+        let path = find(&query(start, start)).unwrap();
+        assert_eq!(path.cells, [start]);
+        assert_close(path.cost, 0.0);
+        assert!(matches!(
+            find(&query(start, goal)),
+            Err(PathError::Unreachable(_))
+        ));
+    }
+}
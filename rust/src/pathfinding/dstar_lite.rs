@@ -0,0 +1,488 @@
+use super::path::turning_points;
+use super::{
+    Grid, PathError, PathQuery, PathResult, Portal, Pos, SearchEvent, SearchObserver, SearchStats,
+    Side,
+};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+// D* Lite: LPA* searching from the goal back to the start. Unlike the other algorithms it keeps
+// its search state between plans, so after walls or terrain change only the cells whose
+// distance to the goal is affected are reconsidered. The start may also move along the path
+// without throwing anything away.
+
+// g + h sums reached along different paths can differ by rounding
+const KEY_EPSILON: f64 = 1e-9;
+
+// Priority of a queued cell: [min(g, rhs) + h + km, min(g, rhs)], compared lexicographically
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Key(f64, f64);
+
+impl Key {
+    fn total_cmp(&self, other: &Self) -> Ordering {
+        self.0
+            .total_cmp(&other.0)
+            .then_with(|| self.1.total_cmp(&other.1))
+    }
+
+    // Like `total_cmp(other) == Less`, but first components that only differ by rounding
+    // count as equal
+    fn is_before(&self, other: &Self) -> bool {
+        if (self.0 - other.0).abs() > KEY_EPSILON {
+            self.0 < other.0
+        } else {
+            self.1 < other.1
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Entry {
+    key: Key,
+    pos: Pos,
+}
+
+// Reverse ordering for min-heap (lowest key has highest priority)
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .key
+            .total_cmp(&self.key)
+            .then_with(|| other.pos.cmp(&self.pos))
+    }
+}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Entry {}
+
+// Incremental planner for one query; every goal cell is a root of the backward search
+#[derive(Clone, Debug)]
+pub struct DStarLite {
+    query: PathQuery,
+    // Heuristic offset accumulated as the start moves, so keys already queued stay valid
+    km: f64,
+    // Distance to the goal as of the last expansion of each cell
+    g: HashMap<Pos, f64>,
+    // One-step lookahead distance: the best neighbor's g plus the step to it
+    rhs: HashMap<Pos, f64>,
+    queue: BinaryHeap<Entry>,
+    // Current key of every queued cell; heap entries with another key are stale
+    queued: HashMap<Pos, Key>,
+    // Cells changed since the last plan
+    pending: Vec<Pos>,
+    // Portals the queued keys were estimated with
+    portals: Vec<Portal>,
+}
+
+impl DStarLite {
+    pub fn new(grid: &Grid, query: &PathQuery) -> Self {
+        let mut planner = Self {
+            query: query.clone(),
+            km: 0.0,
+            g: HashMap::new(),
+            rhs: HashMap::new(),
+            queue: BinaryHeap::new(),
+            queued: HashMap::new(),
+            pending: Vec::new(),
+            portals: grid.portals().to_vec(),
+        };
+        for goal in grid.goal_cells(query) {
+            planner.rhs.insert(goal, 0.0);
+            let key = planner.key(grid, goal);
+            planner.queued.insert(goal, key);
+            planner.queue.push(Entry { key, pos: goal });
+        }
+        planner
+    }
+
+    pub fn query(&self) -> &PathQuery {
+        &self.query
+    }
+
+    // The agent moved: plan from `start` from now on, keeping everything searched so far
+    pub fn set_start(&mut self, grid: &Grid, start: Pos) {
+        self.km += self.query.heuristic.estimate(grid, self.query.start, start);
+        self.query.start = start;
+    }
+
+    // Report cells whose wall or terrain changed, or that became or stopped being a portal
+    // end; they are repaired on the next plan
+    pub fn update_cells(&mut self, changed: &[Pos]) {
+        self.pending.extend_from_slice(changed);
+    }
+
+    // Bring the search up to date with the reported changes and return the current path
+    pub fn plan(
+        &mut self,
+        grid: &Grid,
+        observer: &mut impl SearchObserver,
+    ) -> Result<PathResult, PathError> {
+        let start = self.query.start;
+        grid.validate_query(&self.query)?;
+        // Bigger units need a planner of their own, kept on `Grid::for_agent_size`
+        if self.query.agent_size > 1 {
+            return Err(PathError::Unsupported("D* Lite needs a one-cell unit"));
+        }
+
+        let mut stats = SearchStats::default();
+
+        // A changed cell affects its own edges and, through corner cutting and terrain
+        // costs, the edges of every cell around it, across wrapped edges too
+        for (x, y) in std::mem::take(&mut self.pending) {
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    let pos = grid.wrap_pos((x + dx, y + dy));
+                    self.update_with_partner(grid, pos, &mut stats, observer);
+                }
+            }
+        }
+
+        // New portals can shorten the heuristic anywhere, which would leave queued keys too
+        // high to be expanded in time
+        if self.portals != grid.portals() {
+            self.portals = grid.portals().to_vec();
+            self.rekey(grid);
+        }
+
+        self.compute_shortest_path(grid, &mut stats, observer)?;
+        if self.g(start).is_infinite() {
+            return Err(PathError::Unreachable(stats));
+        }
+
+        // Walk downhill: each step goes to the neighbor that minimizes step cost + g. Ties go
+        // to ordinary steps, so a free portal isn't taken back and forth forever.
+        let mut cells = vec![start];
+        let mut current = start;
+        while !self.is_goal(grid, current) {
+            let next = self
+                .successors(grid, current)
+                .into_iter()
+                .min_by(|a, b| a.1.total_cmp(&b.1).then_with(|| a.2.cmp(&b.2)));
+            match next {
+                Some((pos, cost, _)) if cost.is_finite() && cells.len() < self.cell_count(grid) => {
+                    cells.push(pos);
+                    current = pos;
+                }
+                _ => return Err(PathError::Unreachable(stats)),
+            }
+        }
+
+        Ok(PathResult {
+            cost: grid.path_cost(&cells),
+            waypoints: turning_points(grid, &cells),
+            cells,
+            stats,
+        })
+    }
+
+    fn compute_shortest_path(
+        &mut self,
+        grid: &Grid,
+        stats: &mut SearchStats,
+        observer: &mut impl SearchObserver,
+    ) -> Result<(), PathError> {
+        let start = self.query.start;
+        while let Some(&top) = self.queue.peek() {
+            if observer.is_cancelled() {
+                return Err(PathError::Cancelled(*stats));
+            }
+
+            // Lazy deletion: skip entries that were requeued or removed since
+            if self.queued.get(&top.pos) != Some(&top.key) {
+                self.queue.pop();
+                stats.stale_skipped += 1;
+                continue;
+            }
+
+            // Done once the start is consistent and nothing queued can improve it
+            let start_key = self.key(grid, start);
+            if !top.key.is_before(&start_key) && self.rhs(start) == self.g(start) {
+                break;
+            }
+
+            self.queue.pop();
+            self.queued.remove(&top.pos);
+            let pos = top.pos;
+
+            // Queued before the start moved: requeue with the up-to-date key
+            let new_key = self.key(grid, pos);
+            if top.key.total_cmp(&new_key) == Ordering::Less {
+                self.queued.insert(pos, new_key);
+                self.queue.push(Entry { key: new_key, pos });
+                continue;
+            }
+
+            stats.nodes_expanded += 1;
+            let overconsistent = self.g(pos) > self.rhs(pos);
+            let g_score = if overconsistent {
+                self.rhs(pos)
+            } else {
+                // Underconsistent: the cell got more expensive, so forget its g and let it
+                // and its predecessors be recomputed
+                f64::INFINITY
+            };
+            self.g.insert(pos, g_score);
+            observer.on_event(SearchEvent::Expand {
+                pos,
+                f: top.key.0,
+                g: g_score,
+                h: self.h(grid, pos),
+                side: Side::Backward,
+            });
+
+            for (predecessor, _) in grid.get_predecessors(pos) {
+                self.update_with_partner(grid, predecessor, stats, observer);
+            }
+            if !overconsistent {
+                self.update_vertex(grid, pos, stats, observer);
+            }
+        }
+        Ok(())
+    }
+
+    // Recompute a cell's rhs and (re)queue it if it became inconsistent
+    fn update_vertex(
+        &mut self,
+        grid: &Grid,
+        pos: Pos,
+        stats: &mut SearchStats,
+        observer: &mut impl SearchObserver,
+    ) {
+        if !grid.in_bounds(pos) {
+            return;
+        }
+        // Cells of the goal region turn into goals and back as walls come and go
+        let rhs = if self.is_goal(grid, pos) {
+            0.0
+        } else if grid.is_passable(pos) {
+            self.successors(grid, pos)
+                .into_iter()
+                .map(|(_, cost, _)| cost)
+                .fold(f64::INFINITY, f64::min)
+        } else {
+            f64::INFINITY
+        };
+        self.rhs.insert(pos, rhs);
+
+        let was_queued = self.queued.remove(&pos).is_some();
+        if self.g(pos) != self.rhs(pos) {
+            if was_queued {
+                // The older entry stays in the heap until it is skipped
+                stats.duplicate_entries += 1;
+            }
+            let key = self.key(grid, pos);
+            self.queued.insert(pos, key);
+            self.queue.push(Entry { key, pos });
+            observer.on_event(SearchEvent::Open {
+                pos,
+                f: key.0,
+                g: key.1,
+                h: self.h(grid, pos),
+                side: Side::Backward,
+            });
+        }
+    }
+
+    // Every neighbor with the step cost to it plus its distance to the goal, and whether the
+    // step goes through a portal. Through a portal, look one step past the other end instead
+    // of at its g: a free portal would otherwise let both ends hold on to a distance neither
+    // has anymore.
+    fn successors(&self, grid: &Grid, pos: Pos) -> Vec<(Pos, f64, bool)> {
+        let exit = grid.portal_at(pos).and_then(|portal| portal.other_end(pos));
+        grid.get_neighbors(pos)
+            .into_iter()
+            .map(|(neighbor, cost)| {
+                if Some(neighbor) == exit {
+                    (neighbor, cost + self.lookahead(grid, neighbor, pos), true)
+                } else {
+                    (neighbor, cost + self.g(neighbor), false)
+                }
+            })
+            .collect()
+    }
+
+    // Distance to the goal from `pos` via its best neighbor other than `from`
+    fn lookahead(&self, grid: &Grid, pos: Pos, from: Pos) -> f64 {
+        if self.is_goal(grid, pos) {
+            return 0.0;
+        }
+        grid.get_neighbors(pos)
+            .into_iter()
+            .filter(|&(neighbor, _)| neighbor != from)
+            .map(|(neighbor, cost)| cost + self.g(neighbor))
+            .fold(f64::INFINITY, f64::min)
+    }
+
+    // A portal end's rhs looks past the other end, so it depends on the same cells
+    fn update_with_partner(
+        &mut self,
+        grid: &Grid,
+        pos: Pos,
+        stats: &mut SearchStats,
+        observer: &mut impl SearchObserver,
+    ) {
+        self.update_vertex(grid, pos, stats, observer);
+        if let Some(other) = grid.portal_at(pos).and_then(|portal| portal.other_end(pos)) {
+            self.update_vertex(grid, other, stats, observer);
+        }
+    }
+
+    // Recompute the key of every queued cell, dropping the stale heap entries
+    fn rekey(&mut self, grid: &Grid) {
+        let cells: Vec<Pos> = self.queued.keys().copied().collect();
+        self.queue.clear();
+        for pos in cells {
+            let key = self.key(grid, pos);
+            self.queued.insert(pos, key);
+            self.queue.push(Entry { key, pos });
+        }
+    }
+
+    fn g(&self, pos: Pos) -> f64 {
+        self.g.get(&pos).copied().unwrap_or(f64::INFINITY)
+    }
+
+    fn rhs(&self, pos: Pos) -> f64 {
+        self.rhs.get(&pos).copied().unwrap_or(f64::INFINITY)
+    }
+
+    // Estimated distance from the start, which is where the backward search is heading
+    fn h(&self, grid: &Grid, pos: Pos) -> f64 {
+        self.query.heuristic.estimate(grid, self.query.start, pos)
+    }
+
+    // Walls never end the search, even inside the goal region
+    fn is_goal(&self, grid: &Grid, pos: Pos) -> bool {
+        self.query.is_goal(pos) && grid.is_passable(pos)
+    }
+
+    fn key(&self, grid: &Grid, pos: Pos) -> Key {
+        let g = self.g(pos).min(self.rhs(pos));
+        Key(g + self.h(grid, pos) + self.km, g)
+    }
+
+    fn cell_count(&self, grid: &Grid) -> usize {
+        (grid.width() * grid.height()) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pathfinding::test_support::{assert_close, movements, random_grid, reference_cost};
+    use crate::pathfinding::{Algorithm, Heuristic, Movement, Terrain, Wrap};
+
+    // Compare a plan with a search from scratch on the grid as it is now
+    fn check(grid: &Grid, start: Pos, goal: Pos, result: Result<PathResult, PathError>) -> bool {
+        match (reference_cost(grid, start, goal), result) {
+            (Some(cost), Ok(path)) => {
+                assert_close(path.cost, cost);
+                assert_close(grid.path_cost(&path.cells), cost);
+                assert_eq!(path.cells.first(), Some(&start));
+                assert_eq!(path.goal(), Some(goal));
+                true
+            }
+            (None, Err(PathError::Unreachable(_))) => false,
+            (cost, result) => panic!("{result:?} against {cost:?}"),
+        }
+    }
+
+    // Plan, then keep flipping walls and terrain (and moving the start along the path) and
+    // repairing the plan, which must match a fresh search every time
+    fn repairs_match_fresh_searches(wraps: &[Wrap]) {
+        let (width, height) = (15, 11);
+        let (goal, mut reached) = ((width - 1, height - 1), 0);
+        for seed in 0..24 {
+            let mut grid = random_grid(seed, width, height, 0.25, seed % 3 == 0);
+            grid.set_movement(movements()[seed as usize % 4]);
+            grid.set_wrap(wraps[seed as usize % wraps.len()]);
+            let heuristic = match grid.movement() {
+                Movement::FourWay => Heuristic::Manhattan,
+                Movement::EightWay { .. } => Heuristic::Octile,
+            };
+            let mut start = (0, 0);
+            let query = PathQuery::new(start, goal)
+                .with_algorithm(Algorithm::DStarLite)
+                .with_heuristic(heuristic);
+            let mut planner = DStarLite::new(&grid, &query);
+            check(&grid, start, goal, planner.plan(&grid, &mut ()));
+
+            // The changed cells go round the grid, edges included
+            let mut next = seed as i32 * 7;
+            for round in 0..8 {
+                let mut changed = Vec::new();
+                for _ in 0..3 {
+                    next = (next + 37) % (width * height);
+                    let pos = (next % width, next / width);
+                    if pos == start || pos == goal {
+                        continue;
+                    }
+                    if next % 3 == 0 {
+                        grid.set_terrain(pos, Terrain::ALL[next as usize % 4]);
+                    } else {
+                        grid.set_wall(pos, !grid.is_wall(pos));
+                    }
+                    changed.push(pos);
+                }
+                planner.update_cells(&changed);
+                let result = planner.plan(&grid, &mut ());
+                if round % 3 == 2
+                    && let Ok(path) = &result
+                    && path.cells.len() > 2
+                {
+                    start = path.cells[1];
+                    planner.set_start(&grid, start);
+                    let result = planner.plan(&grid, &mut ());
+                    reached += usize::from(check(&grid, start, goal, result));
+                } else {
+                    reached += usize::from(check(&grid, start, goal, result));
+                }
+            }
+        }
+        assert!(reached > 0);
+    }
+
+    #[test]
+    fn repaired_plans_match_fresh_searches() {
+        repairs_match_fresh_searches(&[Wrap::None]);
+    }
+
+    #[test]
+    fn repaired_plans_match_fresh_searches_on_wrapped_grids() {
+        repairs_match_fresh_searches(&[Wrap::Horizontal, Wrap::Vertical, Wrap::Both]);
+    }
+
+    #[test]
+    fn wall_next_to_a_wrapped_edge_is_repaired_on_both_sides() {
+        // The way from (5, 0) to (1, 0) is shorter round the back, through (6, 0) and (0, 0)
+        let mut grid = Grid::new(7, 1);
+        grid.set_wrap(Wrap::Horizontal);
+        let query = PathQuery::new((5, 0), (1, 0)).with_algorithm(Algorithm::DStarLite);
+        let mut planner = DStarLite::new(&grid, &query);
+        let path = planner.plan(&grid, &mut ()).unwrap();
+        assert_eq!(path.cells, [(5, 0), (6, 0), (0, 0), (1, 0)]);
+
+        // (6, 0) is only next to (0, 0) across the edge, and has to learn it is blocked
+        grid.set_wall((0, 0), true);
+        planner.update_cells(&[(0, 0)]);
+        let path = planner.plan(&grid, &mut ()).unwrap();
+        assert_eq!(path.cells, [(5, 0), (4, 0), (3, 0), (2, 0), (1, 0)]);
+        assert_close(path.cost, 4.0);
+
+        grid.set_wall((0, 0), false);
+        planner.update_cells(&[(0, 0)]);
+        let path = planner.plan(&grid, &mut ()).unwrap();
+        assert_close(path.cost, 3.0);
+    }
+}
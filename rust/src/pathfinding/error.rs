@@ -0,0 +1,223 @@
+use super::{Pos, SearchStats};
+use std::error::Error;
+use std::fmt;
+
+// Why a path query failed
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PathError {
+    MissingStart,
+    MissingEnd,
+    StartOutOfBounds(Pos),
+    EndOutOfBounds(Pos),
+    StartIsWall(Pos),
+    EndIsWall(Pos),
+    // The unit's footprint with this top-left cell would cover a wall or stick out of the grid
+    TooNarrow(Pos),
+    // The open set ran dry before reaching the goal
+    Unreachable(SearchStats),
+    // The observer asked the search to stop
+    Cancelled(SearchStats),
+    // The algorithm can't run on this grid
+    Unsupported(&'static str),
+    // A memory-bounded search couldn't fit a path to the goal in its node limit
+    MemoryLimit(SearchStats),
+    // Every agent can reach its goal, but not without running into another one
+    Deadlock(SearchStats),
+    // Two agents of a multi-agent query start, or end, on this cell
+    SharedStart(Pos),
+    SharedEnd(Pos),
+}
+
+impl PathError {
+    // Stats of the search that ran, None if the query was rejected before searching
+    pub fn stats(&self) -> Option<SearchStats> {
+        match self {
+            PathError::Unreachable(stats)
+            | PathError::Cancelled(stats)
+            | PathError::MemoryLimit(stats)
+            | PathError::Deadlock(stats) => Some(*stats),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathError::MissingStart => write!(f, "no start position set"),
+            PathError::MissingEnd => write!(f, "no end position set"),
+            PathError::StartOutOfBounds(pos) => {
+                write!(f, "start position {:?} is out of bounds", pos)
+            }
+            PathError::EndOutOfBounds(pos) => write!(f, "end position {:?} is out of bounds", pos),
+            PathError::StartIsWall(pos) => write!(f, "start position {:?} is a wall", pos),
+            PathError::EndIsWall(pos) => write!(f, "end position {:?} is a wall", pos),
+            PathError::TooNarrow(pos) => {
+                write!(
+                    f,
+                    "position {:?} has too little clearance for the unit",
+                    pos
+                )
+            }
+            PathError::Unreachable(stats) => write!(
+                f,
+                "end position is unreachable ({} nodes expanded)",
+                stats.nodes_expanded
+            ),
+            PathError::Unsupported(reason) => write!(f, "unsupported query: {}", reason),
+            PathError::MemoryLimit(stats) => write!(
+                f,
+                "memory limit too small to reach the end position ({} nodes expanded)",
+                stats.nodes_expanded
+            ),
+            PathError::Deadlock(stats) => write!(
+                f,
+                "no collision-free paths for all agents ({} nodes expanded)",
+                stats.nodes_expanded
+            ),
+            PathError::SharedStart(pos) => write!(f, "two agents start at {:?}", pos),
+            PathError::SharedEnd(pos) => write!(f, "two agents end at {:?}", pos),
+            PathError::Cancelled(stats) => write!(
+                f,
+                "search was cancelled ({} nodes expanded)",
+                stats.nodes_expanded
+            ),
+        }
+    }
+}
+
+impl Error for PathError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pathfinding::test_support::parse;
+    use crate::pathfinding::{
+        GoalRegion, PathQuery, PathResult, Pathfinder, SearchEvent, SearchObserver,
+    };
+
+    const MAP: &str = "
+        S.#.
+        ..#.
+        ...G
+        ";
+
+    fn find(query: &PathQuery) -> Result<PathResult, PathError> {
+        let (grid, _, _) = parse(MAP);
+        Pathfinder::new().find_path(&grid, query, &mut ())
+    }
+
+    // Asks to stop once the search has expanded `limit` nodes
+    struct StopAfter {
+        limit: usize,
+        expanded: usize,
+    }
+
+    impl SearchObserver for StopAfter {
+        fn on_event(&mut self, event: SearchEvent) {
+            if matches!(event, SearchEvent::Expand { .. }) {
+                self.expanded += 1;
+            }
+        }
+
+        fn is_cancelled(&self) -> bool {
+            self.expanded >= self.limit
+        }
+    }
+
+    #[test]
+    fn missing_start() {
+        let result = PathQuery::from_ends(None, Some((3, 2)), None);
+        assert_eq!(result, Err(PathError::MissingStart));
+        assert_eq!(PathError::MissingStart.to_string(), "no start position set");
+    }
+
+    #[test]
+    fn missing_end() {
+        assert_eq!(
+            PathQuery::from_ends(Some((0, 0)), None, None),
+            Err(PathError::MissingEnd)
+        );
+        // An empty goal region is no end either
+        let empty = GoalRegion::Cells(Vec::new());
+        assert_eq!(
+            PathQuery::from_ends(Some((0, 0)), None, Some(empty)),
+            Err(PathError::MissingEnd)
+        );
+        let region = GoalRegion::rect((3, 1), (3, 2));
+        let query = PathQuery::from_ends(Some((0, 0)), None, Some(region)).unwrap();
+        assert_eq!(query.goal, (3, 1));
+        assert_eq!(PathError::MissingEnd.to_string(), "no end position set");
+    }
+
+    #[test]
+    fn start_out_of_bounds() {
+        let result = find(&PathQuery::new((-1, 0), (3, 2)));
+        assert_eq!(result, Err(PathError::StartOutOfBounds((-1, 0))));
+    }
+
+    #[test]
+    fn end_out_of_bounds() {
+        let result = find(&PathQuery::new((0, 0), (4, 2)));
+        assert_eq!(result, Err(PathError::EndOutOfBounds((4, 2))));
+        assert_eq!(
+            PathError::EndOutOfBounds((4, 2)).to_string(),
+            "end position (4, 2) is out of bounds"
+        );
+    }
+
+    #[test]
+    fn start_is_wall() {
+        let result = find(&PathQuery::new((2, 0), (3, 2)));
+        assert_eq!(result, Err(PathError::StartIsWall((2, 0))));
+    }
+
+    #[test]
+    fn end_is_wall() {
+        let result = find(&PathQuery::new((0, 0), (2, 1)));
+        assert_eq!(result, Err(PathError::EndIsWall((2, 1))));
+        // Rejected before searching, so there are no stats
+        assert_eq!(PathError::EndIsWall((2, 1)).stats(), None);
+    }
+
+    #[test]
+    fn unreachable() {
+        let (mut grid, start, goal) = parse(MAP);
+        grid.set_wall((2, 2), true);
+        let result = Pathfinder::new().find_path(&grid, &PathQuery::new(start, goal), &mut ());
+        let Err(error @ PathError::Unreachable(stats)) = result else {
+            panic!("expected an unreachable goal, got {result:?}");
+        };
+        // The whole left part was searched
+        assert_eq!(stats.nodes_expanded, 6);
+        assert_eq!(error.stats(), Some(stats));
+        assert_eq!(
+            error.to_string(),
+            "end position is unreachable (6 nodes expanded)"
+        );
+    }
+
+    #[test]
+    fn cancelled() {
+        let (grid, start, goal) = parse(MAP);
+        let mut observer = StopAfter {
+            limit: 3,
+            expanded: 0,
+        };
+        let result =
+            Pathfinder::new().find_path(&grid, &PathQuery::new(start, goal), &mut observer);
+        let Err(PathError::Cancelled(stats)) = result else {
+            panic!("expected a cancelled search, got {result:?}");
+        };
+        assert_eq!(stats.nodes_expanded, 3);
+
+        // Cancelled before it began
+        let mut observer = StopAfter {
+            limit: 0,
+            expanded: 0,
+        };
+        let result =
+            Pathfinder::new().find_path(&grid, &PathQuery::new(start, goal), &mut observer);
+        assert_eq!(result, Err(PathError::Cancelled(SearchStats::default())));
+    }
+}
@@ -0,0 +1,198 @@
+use super::{Grid, Node, Pos};
+use std::collections::BinaryHeap;
+
+// Shared guidance for crowds heading to the same place: a single Dijkstra from the goals
+// fills the integration field (the cost of the cheapest way from every cell to the nearest
+// goal), and every cell remembers which neighbor that way starts with. Units then only look up
+// the cell they stand on instead of searching a path each.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FlowField {
+    width: i32,
+    height: i32,
+    // Integration field, row-major; infinite for walls and cells no goal can be reached from
+    costs: Vec<f64>,
+    // Next cell towards the nearest goal; None at goals and wherever the cost is infinite
+    next: Vec<Option<Pos>>,
+    // Which way the step to `next` goes, taken when the field is built as only the grid knows
+    // about its wrapped edges and portals; None where there is no step or it takes a portal
+    directions: Vec<Option<(i32, i32)>>,
+}
+
+impl FlowField {
+    // Goals that are walls or off the grid are skipped
+    pub fn new(grid: &Grid, goals: &[Pos]) -> Self {
+        let cell_count = (grid.width() * grid.height()) as usize;
+        let mut field = Self {
+            width: grid.width(),
+            height: grid.height(),
+            costs: vec![f64::INFINITY; cell_count],
+            next: vec![None; cell_count],
+            directions: vec![None; cell_count],
+        };
+
+        let mut open_set = BinaryHeap::new();
+        for &goal in goals {
+            if let Some(index) = field.index(goal)
+                && grid.is_passable(goal)
+            {
+                field.costs[index] = 0.0;
+                open_set.push(Node::new(goal, 0.0, 0.0));
+            }
+        }
+        while let Some(current) = open_set.pop() {
+            let pos = current.position;
+            if current.g_score > field.cost(pos) {
+                continue;
+            }
+            // Grow backwards: whoever steps into `pos` continues from there
+            for (predecessor, step_cost) in grid.get_predecessors(pos) {
+                let cost = current.g_score + step_cost;
+                let Some(index) = field.index(predecessor) else {
+                    continue;
+                };
+                if cost < field.costs[index] {
+                    field.costs[index] = cost;
+                    field.next[index] = Some(pos);
+                    field.directions[index] = (!grid.is_portal_hop(predecessor, pos))
+                        .then(|| grid.step_direction(predecessor, pos));
+                    open_set.push(Node::new(predecessor, cost, 0.0));
+                }
+            }
+        }
+        field
+    }
+
+    fn index(&self, (x, y): Pos) -> Option<usize> {
+        if (0..self.width).contains(&x) && (0..self.height).contains(&y) {
+            Some((y * self.width + x) as usize)
+        } else {
+            None
+        }
+    }
+
+    // Cost of the cheapest way from `pos` to the nearest goal, infinite when there is none
+    pub fn cost(&self, pos: Pos) -> f64 {
+        self.index(pos)
+            .map_or(f64::INFINITY, |index| self.costs[index])
+    }
+
+    // Cell to step to from `pos`; None at a goal or where no goal can be reached
+    pub fn next(&self, pos: Pos) -> Option<Pos> {
+        self.index(pos).and_then(|index| self.next[index])
+    }
+
+    // Direction of that step on screen, e.g. (1, -1) for up and to the right, going the short
+    // way across wrapped edges. None wherever `next` is, and also where the way goes on
+    // through a portal, which has no direction: `next` then is the portal's other end.
+    pub fn direction(&self, pos: Pos) -> Option<(i32, i32)> {
+        self.index(pos).and_then(|index| self.directions[index])
+    }
+
+    // Follow the field from `start` to a goal; None when no goal can be reached
+    pub fn path_from(&self, start: Pos) -> Option<Vec<Pos>> {
+        if self.cost(start).is_infinite() {
+            return None;
+        }
+        let mut cells = vec![start];
+        let mut current = start;
+        while let Some(next) = self.next(current) {
+            cells.push(next);
+            current = next;
+        }
+        Some(cells)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pathfinding::test_support::{
+        assert_close, movements, parse, random_grid, reference_cost,
+    };
+    use crate::pathfinding::{Portal, Wrap};
+
+    #[test]
+    fn costs_lead_to_the_nearest_goal_on_random_grids() {
+        let goals = [(2, 1), (6, 4)];
+        let mut reached = 0;
+        for seed in 0..24 {
+            let mut grid = random_grid(seed, 8, 6, 0.25, seed % 2 == 0);
+            grid.set_movement(movements()[seed as usize % 4]);
+            let field = FlowField::new(&grid, &goals);
+            for y in 0..grid.height() {
+                for x in 0..grid.width() {
+                    let pos = (x, y);
+                    let nearest = goals
+                        .iter()
+                        .filter(|&&goal| grid.is_passable(goal))
+                        .filter_map(|&goal| reference_cost(&grid, pos, goal))
+                        .min_by(f64::total_cmp);
+                    let Some(nearest) = nearest.filter(|_| grid.is_passable(pos)) else {
+                        assert!(field.cost(pos).is_infinite(), "seed {seed} at {pos:?}");
+                        assert_eq!(field.path_from(pos), None);
+                        continue;
+                    };
+                    assert_close(field.cost(pos), nearest);
+                    // Following the field walks real moves and costs what the field says
+                    let cells = field.path_from(pos).unwrap();
+                    let mut cost = 0.0;
+                    for pair in cells.windows(2) {
+                        cost += grid
+                            .get_neighbors(pair[0])
+                            .into_iter()
+                            .find(|&(next, _)| next == pair[1])
+                            .unwrap_or_else(|| panic!("{pair:?} is not a move"))
+                            .1;
+                    }
+                    assert_close(cost, nearest);
+                    assert!(goals.contains(cells.last().unwrap()));
+                    reached += 1;
+                }
+            }
+        }
+        assert!(reached > 0);
+    }
+
+    #[test]
+    fn walled_and_outside_goals_are_skipped() {
+        let (grid, start, goal) = parse(
+            "
+            S.#
+            ..G
+            ",
+        );
+        let field = FlowField::new(&grid, &[(2, 0), (5, 5), goal]);
+        assert!(field.cost((2, 0)).is_infinite());
+        assert_eq!(field.next(goal), None);
+        assert_eq!(field.path_from(goal), Some(vec![goal]));
+        assert_close(field.cost(start), 3.0);
+        assert!(field.cost((-1, 0)).is_infinite());
+        assert_eq!(field.next((9, 9)), None);
+
+        let nowhere = FlowField::new(&grid, &[(2, 0)]);
+        assert_eq!(nowhere.path_from(start), None);
+        assert_eq!(nowhere.direction(start), None);
+    }
+
+    #[test]
+    fn directions_cross_wrapped_edges_the_short_way() {
+        let mut grid = Grid::new(5, 1);
+        grid.set_wrap(Wrap::Horizontal);
+        let field = FlowField::new(&grid, &[(4, 0)]);
+        assert_eq!(field.next((0, 0)), Some((4, 0)));
+        assert_eq!(field.direction((0, 0)), Some((-1, 0)));
+        assert_eq!(field.direction((3, 0)), Some((1, 0)));
+        assert_eq!(field.direction((4, 0)), None);
+    }
+
+    #[test]
+    fn portal_hops_have_no_direction() {
+        let mut grid = Grid::new(6, 1);
+        grid.add_portal(Portal::new((1, 0), (5, 0)));
+        let field = FlowField::new(&grid, &[(5, 0)]);
+        assert_eq!(field.next((1, 0)), Some((5, 0)));
+        assert_eq!(field.direction((1, 0)), None);
+        assert_eq!(field.direction((0, 0)), Some((1, 0)));
+        assert_eq!(field.path_from((0, 0)), Some(vec![(0, 0), (1, 0), (5, 0)]));
+    }
+}
@@ -0,0 +1,815 @@
+use super::{
+    GoalRegion, HexOrientation, JumpTable, Movement, OneWay, PathError, PathQuery, Portal, Pos,
+    Terrain, TimedWall, Topology, Wrap,
+};
+use std::cell::OnceCell;
+use std::collections::HashSet;
+
+// What the model knows about a single cell
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Cell {
+    pub wall: bool,
+    pub terrain: Terrain,
+    // Way a unit has to cross the cell; None for any way
+    pub one_way: Option<OneWay>,
+}
+
+// Pure grid model: dimensions, passability and costs, with no engine types involved
+#[derive(Clone, Debug, Default)]
+pub struct Grid {
+    width: i32,
+    height: i32,
+    cells: Vec<Cell>,
+    movement: Movement,
+    topology: Topology,
+    wrap: Wrap,
+    portals: Vec<Portal>,
+    // Walls that only stand at some time steps, which only space-time search sees
+    timed_walls: Vec<TimedWall>,
+    // Built on first use by JPS+, dropped whenever walls, movement, topology or wrap change
+    jump_table: OnceCell<JumpTable>,
+    // Clearance of every cell, built on first use and dropped whenever walls or wrap change
+    clearance: OnceCell<Vec<i32>>,
+}
+
+impl Grid {
+    pub const DIRECTIONS: [(i32, i32); 4] = [(0, -1), (1, 0), (0, 1), (-1, 0)]; // Up, Right, Down, Left
+    pub const DIAGONALS: [(i32, i32); 4] = [(1, -1), (1, 1), (-1, 1), (-1, -1)]; // Up-right, down-right, down-left, up-left
+
+    pub fn new(width: i32, height: i32) -> Self {
+        let width = width.max(0);
+        let height = height.max(0);
+        Self {
+            width,
+            height,
+            cells: vec![Cell::default(); (width * height) as usize],
+            movement: Movement::default(),
+            topology: Topology::default(),
+            wrap: Wrap::default(),
+            portals: Vec::new(),
+            timed_walls: Vec::new(),
+            jump_table: OnceCell::new(),
+            clearance: OnceCell::new(),
+        }
+    }
+
+    pub fn movement(&self) -> Movement {
+        self.movement
+    }
+
+    pub fn set_movement(&mut self, movement: Movement) {
+        self.movement = movement;
+        self.jump_table.take();
+    }
+
+    pub fn topology(&self) -> Topology {
+        self.topology
+    }
+
+    // Cells keep their walls and terrain; on hex grids they are just laid out differently
+    pub fn set_topology(&mut self, topology: Topology) {
+        self.topology = topology;
+        self.jump_table.take();
+    }
+
+    pub fn wrap(&self) -> Wrap {
+        self.wrap
+    }
+
+    pub fn set_wrap(&mut self, wrap: Wrap) {
+        self.wrap = wrap;
+        self.jump_table.take();
+        self.clearance.take();
+    }
+
+    pub fn portals(&self) -> &[Portal] {
+        &self.portals
+    }
+
+    // The portal with an end at `pos`, if any
+    pub fn portal_at(&self, pos: Pos) -> Option<&Portal> {
+        self.portals.iter().find(|portal| portal.touches(pos))
+    }
+
+    // Whether going from `from` to `to` takes a portal rather than a step
+    pub fn is_portal_hop(&self, from: Pos, to: Pos) -> bool {
+        self.portal_at(from)
+            .is_some_and(|portal| portal.other_end(from) == Some(to))
+    }
+
+    // Link two cells, replacing the portals either of them was an end of. Ends off the grid
+    // or on the same cell are ignored, and a negative cost counts as 0 so searches stay sound.
+    pub fn add_portal(&mut self, portal: Portal) {
+        if portal.a == portal.b || !self.in_bounds(portal.a) || !self.in_bounds(portal.b) {
+            return;
+        }
+        self.remove_portal(portal.a);
+        self.remove_portal(portal.b);
+        self.portals.push(portal.with_cost(portal.cost.max(0.0)));
+    }
+
+    // Remove the portal with an end at `pos` and return it
+    pub fn remove_portal(&mut self, pos: Pos) -> Option<Portal> {
+        let index = self.portals.iter().position(|portal| portal.touches(pos))?;
+        Some(self.portals.swap_remove(index))
+    }
+
+    pub fn timed_walls(&self) -> &[TimedWall] {
+        &self.timed_walls
+    }
+
+    // The timed walls of `pos`; a cell may have several, e.g. one per pass of a patrol
+    pub fn timed_walls_at(&self, pos: Pos) -> impl Iterator<Item = &TimedWall> {
+        self.timed_walls.iter().filter(move |wall| wall.pos == pos)
+    }
+
+    // Walls off the grid or that never stand are ignored
+    pub fn add_timed_wall(&mut self, wall: TimedWall) {
+        if self.in_bounds(wall.pos) && wall.end > wall.start {
+            self.timed_walls.push(wall);
+        }
+    }
+
+    // Remove every timed wall of `pos` and return them
+    pub fn remove_timed_walls(&mut self, pos: Pos) -> Vec<TimedWall> {
+        let (removed, kept) = std::mem::take(&mut self.timed_walls)
+            .into_iter()
+            .partition(|wall| wall.pos == pos);
+        self.timed_walls = kept;
+        removed
+    }
+
+    // Whether a unit may be in `pos` at time step `t`
+    pub fn is_passable_at(&self, pos: Pos, t: usize) -> bool {
+        self.is_passable(pos) && !self.timed_walls_at(pos).any(|wall| wall.is_active(t))
+    }
+
+    // (settle, period): from time step `settle` on, every timed wall stands at step
+    // t + period exactly when it stands at step t
+    pub fn time_cycle(&self) -> (usize, usize) {
+        let settle = self
+            .timed_walls
+            .iter()
+            .map(TimedWall::settles_at)
+            .max()
+            .unwrap_or(0);
+        let period = self
+            .timed_walls
+            .iter()
+            .filter_map(|wall| wall.period)
+            .fold(1, lcm);
+        (settle, period)
+    }
+
+    // Precomputed JPS+ jump distances for the current walls and movement
+    pub fn jump_table(&self) -> &JumpTable {
+        self.jump_table.get_or_init(|| JumpTable::new(self))
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    pub fn in_bounds(&self, (x, y): Pos) -> bool {
+        x >= 0 && x < self.width && y >= 0 && y < self.height
+    }
+
+    // Bring a position that went past a wrapped edge back onto the grid; positions past the
+    // other edges stay out of bounds
+    pub fn wrap_pos(&self, (x, y): Pos) -> Pos {
+        let wrap = |v: i32, size: i32, wraps: bool| {
+            if wraps && size > 0 {
+                v.rem_euclid(size)
+            } else {
+                v
+            }
+        };
+        (
+            wrap(x, self.width, self.wrap.wraps_x()),
+            wrap(y, self.height, self.wrap.wraps_y()),
+        )
+    }
+
+    // Offset from `from` to `to` in cells, the shorter way round on wrapped axes
+    pub fn delta(&self, from: Pos, to: Pos) -> (i32, i32) {
+        let shortest = |d: i32, size: i32, wraps: bool| {
+            if !wraps || size == 0 {
+                return d;
+            }
+            let d = d.rem_euclid(size);
+            if d > size / 2 { d - size } else { d }
+        };
+        (
+            shortest(to.0 - from.0, self.width, self.wrap.wraps_x()),
+            shortest(to.1 - from.1, self.height, self.wrap.wraps_y()),
+        )
+    }
+
+    // Hex steps between two cells with nothing in the way. On wrapped axes `to` repeats
+    // every width (or height) cells, and the closest of those copies counts.
+    pub fn hex_distance(&self, from: Pos, to: Pos) -> i32 {
+        let copies = |size: i32, wraps: bool| if wraps { vec![-size, 0, size] } else { vec![0] };
+        let (dx, dy) = self.delta(from, to);
+        let mut best = i32::MAX;
+        for sx in copies(self.width, self.wrap.wraps_x()) {
+            for &sy in &copies(self.height, self.wrap.wraps_y()) {
+                let to = (from.0 + dx + sx, from.1 + dy + sy);
+                best = best.min(self.topology.hex_distance(from, to));
+            }
+        }
+        best
+    }
+
+    // Index into the row-major cell storage, None when out of bounds
+    fn index(&self, pos: Pos) -> Option<usize> {
+        if self.in_bounds(pos) {
+            Some((pos.1 * self.width + pos.0) as usize)
+        } else {
+            None
+        }
+    }
+
+    pub fn cell(&self, pos: Pos) -> Option<&Cell> {
+        self.index(pos).map(|i| &self.cells[i])
+    }
+
+    fn cell_mut(&mut self, pos: Pos) -> Option<&mut Cell> {
+        self.index(pos).map(|i| &mut self.cells[i])
+    }
+
+    // Out-of-bounds positions are never walls, but they are not passable either
+    pub fn is_wall(&self, pos: Pos) -> bool {
+        self.cell(pos).is_some_and(|cell| cell.wall)
+    }
+
+    pub fn set_wall(&mut self, pos: Pos, wall: bool) {
+        if let Some(cell) = self.cell_mut(pos) {
+            cell.wall = wall;
+            self.jump_table.take();
+            self.clearance.take();
+        }
+    }
+
+    pub fn is_passable(&self, pos: Pos) -> bool {
+        self.cell(pos).is_some_and(|cell| !cell.wall)
+    }
+
+    pub fn terrain(&self, pos: Pos) -> Terrain {
+        self.cell(pos).map(|cell| cell.terrain).unwrap_or_default()
+    }
+
+    pub fn set_terrain(&mut self, pos: Pos, terrain: Terrain) {
+        if let Some(cell) = self.cell_mut(pos) {
+            cell.terrain = terrain;
+        }
+    }
+
+    pub fn one_way(&self, pos: Pos) -> Option<OneWay> {
+        self.cell(pos).and_then(|cell| cell.one_way)
+    }
+
+    pub fn set_one_way(&mut self, pos: Pos, one_way: Option<OneWay>) {
+        if let Some(cell) = self.cell_mut(pos) {
+            cell.one_way = one_way;
+        }
+    }
+
+    // Whether any cell can only be crossed one way, i.e. some steps can't be taken back
+    pub fn has_one_way(&self) -> bool {
+        self.cells.iter().any(|cell| cell.one_way.is_some())
+    }
+
+    // Which way a step between neighboring cells goes on screen, e.g. (1, -1) for up and to
+    // the right. Hex cells are shown as bricks, where a step to the next row (pointy) or
+    // column (flat) also goes half a block sideways.
+    pub fn step_direction(&self, from: Pos, to: Pos) -> (i32, i32) {
+        let (dx, dy) = self.delta(from, to);
+        let (q1, r1) = self.topology.to_axial(from);
+        let (q2, r2) = self.topology.to_axial((from.0 + dx, from.1 + dy));
+        let (dq, dr) = (q2 - q1, r2 - r1);
+        let (x, y) = match self.topology {
+            Topology::Square => (dq, dr),
+            Topology::Hex {
+                orientation: HexOrientation::Pointy,
+                ..
+            } => (2 * dq + dr, dr),
+            Topology::Hex {
+                orientation: HexOrientation::Flat,
+                ..
+            } => (dq, 2 * dr + dq),
+        };
+        (x.signum(), y.signum())
+    }
+
+    // Whether the one-way cells at either end of a step let it through
+    pub fn allows_step(&self, from: Pos, to: Pos) -> bool {
+        let (a, b) = (self.one_way(from), self.one_way(to));
+        if a.is_none() && b.is_none() {
+            return true;
+        }
+        let direction = self.step_direction(from, to);
+        a.into_iter()
+            .chain(b)
+            .all(|one_way| one_way.allows(direction))
+    }
+
+    // Side of the largest square of open cells with `pos` as its top-left corner, 0 for a wall
+    // or a position off the grid. Squares may go on across wrapped edges. A unit bigger than
+    // one cell is placed by the top-left cell of its footprint, so it fits wherever the
+    // clearance is at least its size.
+    pub fn clearance(&self, pos: Pos) -> i32 {
+        let clearance = self.clearance.get_or_init(|| self.measure_clearance());
+        self.index(pos).map_or(0, |i| clearance[i])
+    }
+
+    fn measure_clearance(&self) -> Vec<i32> {
+        let max = self.width.min(self.height);
+        let mut clearance = Vec::with_capacity(self.cells.len());
+        for y in 0..self.height {
+            for x in 0..self.width {
+                // Grow the square by a column on the right and a row at the bottom
+                let mut size = 0;
+                while size < max
+                    && (0..=size).all(|i| {
+                        self.is_passable(self.wrap_pos((x + size, y + i)))
+                            && self.is_passable(self.wrap_pos((x + i, y + size)))
+                    })
+                {
+                    size += 1;
+                }
+                clearance.push(size);
+            }
+        }
+        clearance
+    }
+
+    // The grid as a unit `size` cells wide sees it, each cell standing for the footprint
+    // with that cell as its top-left corner. Cells without room for the unit become walls,
+    // entering a cell costs the most expensive terrain under the footprint, and a timed wall
+    // anywhere under it blocks it. One-way arrows and portals still only apply to the
+    // top-left cell.
+    pub fn for_agent_size(&self, size: i32) -> Grid {
+        let footprint = |(x, y): Pos| {
+            (0..size).flat_map(move |dy| (0..size).map(move |dx| self.wrap_pos((x + dx, y + dy))))
+        };
+        let mut grid = self.clone();
+        grid.jump_table.take();
+        grid.clearance.take();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let pos = (x, y);
+                if self.clearance(pos) < size {
+                    grid.set_wall(pos, true);
+                    continue;
+                }
+                let terrain = footprint(pos)
+                    .map(|cell| self.terrain(cell))
+                    .max_by(|a, b| a.cost().total_cmp(&b.cost()))
+                    .unwrap_or_default();
+                grid.set_terrain(pos, terrain);
+            }
+        }
+        // A timed wall blocks every footprint it is under, i.e. those with their top-left
+        // corner up to `size - 1` cells left of and above it
+        grid.timed_walls = self
+            .timed_walls
+            .iter()
+            .flat_map(|wall| {
+                footprint((wall.pos.0 - size + 1, wall.pos.1 - size + 1))
+                    .filter(|&corner| self.in_bounds(corner))
+                    .map(|corner| TimedWall {
+                        pos: corner,
+                        ..*wall
+                    })
+            })
+            .collect();
+        grid
+    }
+
+    // Cost multiplier for entering a cell
+    pub fn cost(&self, pos: Pos) -> f64 {
+        self.terrain(pos).cost()
+    }
+
+    // The cost shared by every passable cell, None if terrain costs differ
+    pub fn uniform_cost(&self) -> Option<f64> {
+        let mut costs = self
+            .cells
+            .iter()
+            .filter(|cell| !cell.wall)
+            .map(|cell| cell.terrain.cost());
+        let first = costs.next().unwrap_or(1.0);
+        costs.all(|cost| cost == first).then_some(first)
+    }
+
+    // Check that both ends of a query are inside the grid and not walls
+    pub fn validate_endpoints(&self, start: Pos, goal: Pos) -> Result<(), PathError> {
+        if !self.in_bounds(start) {
+            return Err(PathError::StartOutOfBounds(start));
+        }
+        if !self.in_bounds(goal) {
+            return Err(PathError::EndOutOfBounds(goal));
+        }
+        if self.is_wall(start) {
+            return Err(PathError::StartIsWall(start));
+        }
+        if self.is_wall(goal) {
+            return Err(PathError::EndIsWall(goal));
+        }
+        Ok(())
+    }
+
+    // Check the start and every goal of a query
+    pub fn validate_query(&self, query: &PathQuery) -> Result<(), PathError> {
+        let mut explicit = std::iter::once(query.goal).chain(query.extra_goals.iter().copied());
+        let Some(region) = &query.goal_region else {
+            return explicit.try_for_each(|goal| self.validate_endpoints(query.start, goal));
+        };
+        // Cells of the goal region may be walls or off the grid, as long as one is open
+        let open = self.goal_cells(query).first().copied();
+        self.validate_endpoints(query.start, open.unwrap_or(query.goal))?;
+        explicit
+            .filter(|&goal| !region.contains(goal))
+            .try_for_each(|goal| self.validate_endpoints(query.start, goal))
+    }
+
+    // Every open cell that ends the query: `goal` first, then the extra goals and the goal
+    // region, without repeats
+    pub fn goal_cells(&self, query: &PathQuery) -> Vec<Pos> {
+        let region = query.goal_region.iter().flat_map(GoalRegion::cells);
+        let mut seen = HashSet::new();
+        std::iter::once(query.goal)
+            .chain(query.extra_goals.iter().copied())
+            .chain(region)
+            .filter(|&goal| self.is_passable(goal) && seen.insert(goal))
+            .collect()
+    }
+
+    // Cells crossed by the straight segment between two cell centers, `from` excluded. None
+    // when the segment hits a wall or squeezes past a corner the movement doesn't allow.
+    pub fn line_cells(&self, from: Pos, to: Pos) -> Option<Vec<Pos>> {
+        let (dx, dy) = self.delta(from, to);
+        let (sx, sy) = (dx.signum(), dy.signum());
+        let (dx, dy) = (dx.abs(), dy.abs());
+        let (mut x, mut y) = from;
+        let (mut ix, mut iy) = (0, 0);
+        let mut cells = Vec::with_capacity((dx + dy) as usize);
+        while ix < dx || iy < dy {
+            // Which cell border the segment crosses next: vertical (< 0), horizontal (> 0)
+            // or both at once through a corner (0)
+            let decision = (1 + 2 * ix) * dy - (1 + 2 * iy) * dx;
+            if decision == 0 {
+                let free_orthogonals = [(x + sx, y), (x, y + sy)]
+                    .into_iter()
+                    .filter(|&p| self.is_passable(self.wrap_pos(p)))
+                    .count();
+                if !self.movement.corner_cutting().allows(free_orthogonals) {
+                    return None;
+                }
+                (x, y) = (x + sx, y + sy);
+                (ix, iy) = (ix + 1, iy + 1);
+            } else if decision < 0 {
+                x += sx;
+                ix += 1;
+            } else {
+                y += sy;
+                iy += 1;
+            }
+            // The walk goes on past wrapped edges; the cells are on the other side
+            let pos = self.wrap_pos((x, y));
+            if !self.is_passable(pos) {
+                return None;
+            }
+            cells.push(pos);
+        }
+        Some(cells)
+    }
+
+    // Euclidean distance between two cell centers
+    pub fn line_length(&self, from: Pos, to: Pos) -> f64 {
+        let (dx, dy) = self.delta(from, to);
+        f64::from(dx).hypot(f64::from(dy))
+    }
+
+    // Cost of a straight segment: its length times the most expensive terrain it enters.
+    // None without line of sight.
+    pub fn line_cost(&self, from: Pos, to: Pos) -> Option<f64> {
+        let terrain = self
+            .line_cells(from, to)?
+            .into_iter()
+            .map(|pos| self.cost(pos))
+            .fold(0.0, f64::max);
+        Some(self.line_length(from, to) * terrain)
+    }
+
+    // Sum of the step costs along a path of adjacent cells
+    pub fn path_cost(&self, cells: &[Pos]) -> f64 {
+        cells
+            .windows(2)
+            .map(|pair| {
+                self.get_neighbors(pair[0])
+                    .into_iter()
+                    .find(|&(pos, _)| pos == pair[1])
+                    .map_or(f64::INFINITY, |(_, cost)| cost)
+            })
+            .sum()
+    }
+
+    // Get passable neighboring positions with their step cost: the 4 orthogonal ones, plus
+    // the diagonals allowed by the corner-cutting rule in 8-way mode. A step costs its base
+    // distance times the terrain cost of the cell being entered. Hex cells have six
+    // neighbors at distance 1 whatever the movement. Steps onto or off one-way cells must
+    // go their way. A portal end also leads to the other end, for the portal's cost.
+    pub fn get_neighbors(&self, pos: Pos) -> Vec<(Pos, f64)> {
+        let mut neighbors: Vec<(Pos, f64)> = if self.topology.is_hex() {
+            self.topology
+                .hex_neighbors(pos)
+                .into_iter()
+                .map(|pos| self.wrap_pos(pos))
+                .filter(|&pos| self.is_passable(pos))
+                .map(|pos| (pos, self.cost(pos)))
+                .collect()
+        } else {
+            self.square_neighbors(pos)
+        };
+        neighbors.retain(|&(to, _)| self.allows_step(pos, to));
+
+        if let Some(portal) = self.portal_at(pos)
+            && let Some(exit) = portal.other_end(pos)
+            && self.is_passable(exit)
+        {
+            // A portal between adjacent cells is just a second way to take the same step
+            match neighbors.iter_mut().find(|(neighbor, _)| *neighbor == exit) {
+                Some((_, cost)) => *cost = cost.min(portal.cost),
+                None => neighbors.push((exit, portal.cost)),
+            }
+        }
+        neighbors
+    }
+
+    fn square_neighbors(&self, (x, y): Pos) -> Vec<(Pos, f64)> {
+        let mut neighbors: Vec<(Pos, f64)> = Self::DIRECTIONS
+            .iter()
+            .map(|(dx, dy)| self.wrap_pos((x + dx, y + dy)))
+            .filter(|&pos| self.is_passable(pos))
+            .map(|pos| (pos, self.cost(pos)))
+            .collect();
+
+        if let Movement::EightWay {
+            diagonal_cost,
+            corner_cutting,
+        } = self.movement
+        {
+            for (dx, dy) in Self::DIAGONALS {
+                let pos = self.wrap_pos((x + dx, y + dy));
+                if !self.is_passable(pos) {
+                    continue;
+                }
+                let free_orthogonals = [(x + dx, y), (x, y + dy)]
+                    .into_iter()
+                    .filter(|&p| self.is_passable(self.wrap_pos(p)))
+                    .count();
+                if corner_cutting.allows(free_orthogonals) {
+                    neighbors.push((pos, diagonal_cost * self.cost(pos)));
+                }
+            }
+        }
+
+        neighbors
+    }
+
+    // Cells a unit can step from into `pos`, with the cost of that step; what a search
+    // growing backwards from the goal expands
+    pub fn get_predecessors(&self, (x, y): Pos) -> Vec<(Pos, f64)> {
+        let candidates: Vec<Pos> = if self.topology.is_hex() {
+            self.topology.hex_neighbors((x, y))
+        } else {
+            Self::DIRECTIONS
+                .iter()
+                .chain(&Self::DIAGONALS)
+                .map(|(dx, dy)| (x + dx, y + dy))
+                .collect()
+        };
+        candidates
+            .into_iter()
+            .map(|from| self.wrap_pos(from))
+            .chain(
+                self.portal_at((x, y))
+                    .and_then(|portal| portal.other_end((x, y))),
+            )
+            .filter(|&from| self.is_passable(from))
+            .filter_map(|from| {
+                self.get_neighbors(from)
+                    .into_iter()
+                    .find(|&(to, _)| to == (x, y))
+                    .map(|(_, cost)| (from, cost))
+            })
+            .collect()
+    }
+}
+
+// Least common multiple, saturating instead of overflowing
+fn lcm(a: usize, b: usize) -> usize {
+    let (mut x, mut y) = (a, b);
+    while y != 0 {
+        (x, y) = (y, x % y);
+    }
+    (a / x).saturating_mul(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pathfinding::CornerCutting;
+    use crate::pathfinding::test_support::{assert_close, parse};
+    use std::collections::HashMap;
+    use std::f64::consts::SQRT_2;
+
+    fn neighbor_cells(grid: &Grid, pos: Pos) -> Vec<Pos> {
+        let mut cells: Vec<Pos> = grid
+            .get_neighbors(pos)
+            .into_iter()
+            .map(|(p, _)| p)
+            .collect();
+        cells.sort();
+        cells
+    }
+
+    #[test]
+    fn four_way_neighbors_are_the_orthogonal_cells() {
+        let grid = Grid::new(3, 3);
+        assert_eq!(
+            neighbor_cells(&grid, (1, 1)),
+            [(0, 1), (1, 0), (1, 2), (2, 1)]
+        );
+        assert!(
+            grid.get_neighbors((1, 1))
+                .iter()
+                .all(|&(_, cost)| cost == 1.0)
+        );
+    }
+
+    #[test]
+    fn neighbors_stay_in_bounds() {
+        let mut grid = Grid::new(3, 2);
+        assert_eq!(neighbor_cells(&grid, (0, 0)), [(0, 1), (1, 0)]);
+        assert_eq!(neighbor_cells(&grid, (2, 1)), [(1, 1), (2, 0)]);
+        grid.set_movement(Movement::eight_way(CornerCutting::Always));
+        assert_eq!(neighbor_cells(&grid, (0, 0)), [(0, 1), (1, 0), (1, 1)]);
+    }
+
+    #[test]
+    fn bounds() {
+        let grid = Grid::new(4, 3);
+        assert!(grid.in_bounds((0, 0)) && grid.in_bounds((3, 2)));
+        for pos in [(-1, 0), (0, -1), (4, 0), (0, 3)] {
+            assert!(!grid.in_bounds(pos));
+            assert!(grid.cell(pos).is_none());
+            // Off the grid is neither a wall nor passable
+            assert!(!grid.is_wall(pos) && !grid.is_passable(pos));
+        }
+        assert_eq!(Grid::new(-2, 3).width(), 0);
+    }
+
+    #[test]
+    fn walls_block_neighbors() {
+        let (mut grid, _, _) = parse(
+            "
+            .#.
+            ...
+            ",
+        );
+        assert!(grid.is_wall((1, 0)) && !grid.is_passable((1, 0)));
+        assert_eq!(neighbor_cells(&grid, (1, 1)), [(0, 1), (2, 1)]);
+
+        grid.set_wall((1, 0), false);
+        assert_eq!(neighbor_cells(&grid, (1, 1)), [(0, 1), (1, 0), (2, 1)]);
+        // Setting a wall off the grid does nothing
+        grid.set_wall((5, 5), true);
+        assert!(!grid.is_wall((5, 5)));
+    }
+
+    #[test]
+    fn terrain_scales_step_costs() {
+        let (mut grid, _, _) = parse("..~");
+        grid.set_movement(Movement::eight_way(CornerCutting::Always));
+        let costs: HashMap<Pos, f64> = grid.get_neighbors((1, 0)).into_iter().collect();
+        assert_close(costs[&(0, 0)], 1.0);
+        assert_close(costs[&(2, 0)], Terrain::Water.cost());
+        assert_close(
+            grid.path_cost(&[(0, 0), (1, 0), (2, 0)]),
+            1.0 + Terrain::Water.cost(),
+        );
+    }
+
+    #[test]
+    fn corner_cutting_rules() {
+        // The step from the top-left to the bottom-right cell passes no wall corner, one, or
+        // squeezes between two walls
+        let maps = ["..\n..", ".#\n..", ".#\n#."];
+        let rules = [
+            (CornerCutting::Always, [true, true, true]),
+            (CornerCutting::NotBetweenWalls, [true, true, false]),
+            (CornerCutting::BothOrthogonalsFree, [true, false, false]),
+        ];
+        for (rule, allowed) in rules {
+            for (map, allowed) in maps.iter().zip(allowed) {
+                let (mut grid, _, _) = parse(map);
+                grid.set_movement(Movement::eight_way(rule));
+                let steps = grid.get_neighbors((0, 0)).iter().any(|&(p, _)| p == (1, 1));
+                assert_eq!(steps, allowed, "{} on {map:?}", rule.name());
+            }
+        }
+        // 4-way units never take the diagonal
+        let (grid, _, _) = parse("..\n..");
+        assert!(grid.get_neighbors((0, 0)).iter().all(|&(p, _)| p != (1, 1)));
+    }
+
+    #[test]
+    fn diagonal_steps_cost_the_diagonal_cost() {
+        let mut grid = Grid::new(2, 2);
+        grid.set_movement(Movement::eight_way(CornerCutting::Always));
+        let costs: HashMap<Pos, f64> = grid.get_neighbors((0, 0)).into_iter().collect();
+        assert_close(costs[&(1, 1)], SQRT_2);
+        grid.set_movement(Movement::EightWay {
+            diagonal_cost: Movement::DIAGONAL_COST_14,
+            corner_cutting: CornerCutting::Always,
+        });
+        let costs: HashMap<Pos, f64> = grid.get_neighbors((0, 0)).into_iter().collect();
+        assert_close(costs[&(1, 1)], 1.4);
+    }
+
+    #[test]
+    fn predecessors_mirror_neighbors() {
+        let (mut grid, _, _) = parse(
+            "
+            ..~.
+            .#..
+            ....
+            ",
+        );
+        grid.set_movement(Movement::eight_way(CornerCutting::NotBetweenWalls));
+        for y in 0..grid.height() {
+            for x in 0..grid.width() {
+                if grid.is_wall((x, y)) {
+                    continue;
+                }
+                for (to, cost) in grid.get_neighbors((x, y)) {
+                    assert!(grid.get_predecessors(to).contains(&((x, y), cost)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn clearance_is_the_largest_open_square() {
+        let (mut grid, _, _) = parse(
+            "
+            ....
+            .#..
+            ....
+            ",
+        );
+        assert_eq!(grid.clearance((0, 0)), 1);
+        assert_eq!(grid.clearance((2, 0)), 2);
+        assert_eq!(grid.clearance((2, 1)), 2);
+        assert_eq!(grid.clearance((1, 1)), 0);
+        assert_eq!(grid.clearance((3, 2)), 1);
+        assert_eq!(grid.clearance((9, 9)), 0);
+
+        // Squares go on across wrapped edges
+        grid.set_wrap(Wrap::Both);
+        assert_eq!(grid.clearance((3, 2)), 2);
+        grid.set_wall((0, 0), true);
+        assert_eq!(grid.clearance((3, 2)), 1);
+    }
+
+    #[test]
+    fn a_bigger_unit_sees_its_footprints() {
+        let (mut grid, _, _) = parse(
+            "
+            ....
+            .#.~
+            ....
+            ",
+        );
+        grid.add_timed_wall(TimedWall::new((3, 2), 1, 2));
+        let sized = grid.for_agent_size(2);
+        let open: Vec<Pos> = (0..3)
+            .flat_map(|y| (0..4).map(move |x| (x, y)))
+            .filter(|&pos| !sized.is_wall(pos))
+            .collect();
+        assert_eq!(open, [(2, 0), (2, 1)]);
+        // Both footprints cover the water
+        assert_eq!(sized.terrain((2, 0)), Terrain::Water);
+        assert_eq!(sized.terrain((2, 1)), Terrain::Water);
+        let blocked: Vec<Pos> = sized.timed_walls().iter().map(|wall| wall.pos).collect();
+        assert_eq!(blocked, [(2, 1), (3, 1), (2, 2), (3, 2)]);
+        assert!(!sized.is_passable_at((2, 1), 1));
+        assert!(sized.is_passable_at((2, 0), 1));
+    }
+}
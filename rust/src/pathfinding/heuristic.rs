@@ -0,0 +1,240 @@
+use super::{Grid, Pos};
+use std::f64::consts::SQRT_2;
+use std::fmt;
+use std::sync::Arc;
+
+// Estimated remaining cost from a cell to the goal, chosen per query
+#[derive(Clone, Default)]
+pub enum Heuristic {
+    // |dx| + |dy|, exact for 4-way movement with unit cost
+    #[default]
+    Manhattan,
+    // Straight-line distance
+    Euclidean,
+    // max(|dx|, |dy|), exact for 8-way movement where diagonals cost 1
+    Chebyshev,
+    // Exact for 8-way movement, using the grid's diagonal cost (sqrt(2) on 4-way grids)
+    Octile,
+    // Number of hex steps on hex grids, Octile on square ones. Only this and Zero stay
+    // admissible on hex grids: the others measure offsets in the skewed rectangular layout.
+    Hex,
+    // Always 0, turns A* into Dijkstra
+    Zero,
+    // Any caller-provided estimate; it has to be admissible for A* to stay optimal
+    Custom(Arc<dyn Fn(Pos, Pos) -> f64 + Send + Sync>),
+}
+
+impl Heuristic {
+    pub fn custom(f: impl Fn(Pos, Pos) -> f64 + Send + Sync + 'static) -> Self {
+        Heuristic::Custom(Arc::new(f))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Heuristic::Manhattan => "Manhattan",
+            Heuristic::Euclidean => "Euclidean",
+            Heuristic::Chebyshev => "Chebyshev",
+            Heuristic::Octile => "Octile",
+            Heuristic::Hex => "Hex",
+            Heuristic::Zero => "Zero",
+            Heuristic::Custom(_) => "Custom",
+        }
+    }
+
+    // Estimate the cost of getting from `from` to `to` on `grid`. With portals the way may
+    // also be a chain of them, each reached straight from the last one's other end; the
+    // cheapest chain bounds the cost, so the estimate stays admissible and consistent.
+    pub fn estimate(&self, grid: &Grid, from: Pos, to: Pos) -> f64 {
+        let direct = self.estimate_direct(grid, from, to);
+        if grid.portals().is_empty() {
+            return direct;
+        }
+        // Every portal in both directions: (entry, exit, cost)
+        let links: Vec<(Pos, Pos, f64)> = grid
+            .portals()
+            .iter()
+            .flat_map(|portal| {
+                [
+                    (portal.a, portal.b, portal.cost),
+                    (portal.b, portal.a, portal.cost),
+                ]
+            })
+            .collect();
+        // Dijkstra over the links: `reach[i]` bounds the cost of getting to entry i
+        let mut reach: Vec<f64> = links
+            .iter()
+            .map(|&(entry, _, _)| self.estimate_direct(grid, from, entry))
+            .collect();
+        let mut done = vec![false; links.len()];
+        let mut best = direct;
+        while let Some(i) = (0..links.len())
+            .filter(|&i| !done[i] && reach[i] < best)
+            .min_by(|&i, &j| reach[i].total_cmp(&reach[j]))
+        {
+            done[i] = true;
+            let (_, exit, cost) = links[i];
+            let at_exit = reach[i] + cost;
+            best = best.min(at_exit + self.estimate_direct(grid, exit, to));
+            for j in 0..links.len() {
+                if !done[j] {
+                    let via = at_exit + self.estimate_direct(grid, exit, links[j].0);
+                    reach[j] = reach[j].min(via);
+                }
+            }
+        }
+        best
+    }
+
+    // Estimate without taking portals into account
+    fn estimate_direct(&self, grid: &Grid, from: Pos, to: Pos) -> f64 {
+        let (dx, dy) = grid.delta(from, to);
+        let (dx, dy) = (dx.abs() as f64, dy.abs() as f64);
+        match self {
+            Heuristic::Manhattan => dx + dy,
+            Heuristic::Euclidean => dx.hypot(dy),
+            Heuristic::Chebyshev => dx.max(dy),
+            Heuristic::Octile => {
+                let diagonal_cost = grid.movement().diagonal_cost().unwrap_or(SQRT_2);
+                dx.max(dy) + (diagonal_cost - 1.0) * dx.min(dy)
+            }
+            Heuristic::Hex if grid.topology().is_hex() => f64::from(grid.hex_distance(from, to)),
+            Heuristic::Hex => Heuristic::Octile.estimate_direct(grid, from, to),
+            Heuristic::Zero => 0.0,
+            Heuristic::Custom(f) => f(from, to),
+        }
+    }
+
+    // Estimate towards the closest of several goals; admissible whenever `estimate` is
+    pub fn estimate_nearest(&self, grid: &Grid, from: Pos, goals: &[Pos]) -> f64 {
+        goals
+            .iter()
+            .map(|&goal| self.estimate(grid, from, goal))
+            .fold(f64::INFINITY, f64::min)
+    }
+}
+
+impl fmt::Debug for Heuristic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl PartialEq for Heuristic {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Heuristic::Custom(a), Heuristic::Custom(b)) => Arc::ptr_eq(a, b),
+            _ => std::mem::discriminant(self) == std::mem::discriminant(other),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pathfinding::test_support::{assert_close, movements, random_grid, reference_cost};
+    use crate::pathfinding::{CornerCutting, HexOffset, HexOrientation, Movement, Topology};
+
+    #[test]
+    fn estimates_on_an_open_grid() {
+        let mut grid = Grid::new(10, 10);
+        let (from, to) = ((0, 0), (3, 4));
+        assert_close(Heuristic::Manhattan.estimate(&grid, from, to), 7.0);
+        assert_close(Heuristic::Euclidean.estimate(&grid, from, to), 5.0);
+        assert_close(Heuristic::Chebyshev.estimate(&grid, from, to), 4.0);
+        assert_close(
+            Heuristic::Octile.estimate(&grid, from, to),
+            1.0 + 3.0 * SQRT_2,
+        );
+        // Hex falls back to Octile on square grids
+        assert_close(Heuristic::Hex.estimate(&grid, from, to), 1.0 + 3.0 * SQRT_2);
+        assert_close(Heuristic::Zero.estimate(&grid, from, to), 0.0);
+        let custom = Heuristic::custom(|from, to| f64::from(to.0 - from.0));
+        assert_close(custom.estimate(&grid, from, to), 3.0);
+
+        // Octile follows the grid's diagonal cost
+        grid.set_movement(Movement::EightWay {
+            diagonal_cost: Movement::DIAGONAL_COST_14,
+            corner_cutting: CornerCutting::Always,
+        });
+        assert_close(Heuristic::Octile.estimate(&grid, from, to), 1.0 + 3.0 * 1.4);
+        assert_close(
+            Heuristic::Octile.estimate_nearest(&grid, from, &[to, (1, 1)]),
+            1.4,
+        );
+    }
+
+    #[test]
+    fn custom_heuristics_are_only_equal_to_themselves() {
+        let custom = Heuristic::custom(|_, _| 0.0);
+        assert_eq!(custom, custom.clone());
+        assert_ne!(custom, Heuristic::custom(|_, _| 0.0));
+        assert_eq!(Heuristic::Octile, Heuristic::Octile);
+        assert_eq!(format!("{custom:?}"), "Custom");
+    }
+
+    #[test]
+    fn manhattan_overestimates_diagonal_and_hex_steps() {
+        let mut grid = Grid::new(3, 3);
+        grid.set_movement(Movement::eight_way(CornerCutting::Always));
+        assert!(Heuristic::Manhattan.estimate(&grid, (0, 0), (1, 1)) > SQRT_2);
+        grid.set_topology(Topology::hex(HexOrientation::Pointy, HexOffset::Odd));
+        // Two hex steps down, each shifting half a cell to the right
+        assert!(Heuristic::Manhattan.estimate(&grid, (0, 0), (1, 2)) > 2.0);
+        assert_close(Heuristic::Hex.estimate(&grid, (0, 0), (1, 2)), 2.0);
+    }
+
+    #[test]
+    fn heuristics_never_overestimate_where_they_are_admissible() {
+        let zero = || Heuristic::Zero;
+        let custom = || Heuristic::custom(|_, _| 0.0);
+        let mut checked = 0;
+        for seed in 0..12 {
+            let mut grid = random_grid(seed, 8, 6, 0.2, seed % 2 == 0);
+            let movement = movements()[seed as usize % 4];
+            grid.set_movement(movement);
+            let mut admissible = vec![
+                Heuristic::Euclidean,
+                Heuristic::Chebyshev,
+                Heuristic::Octile,
+                Heuristic::Hex,
+                zero(),
+                custom(),
+            ];
+            if movement == Movement::FourWay {
+                admissible.push(Heuristic::Manhattan);
+            }
+            // Cheap diagonals are shorter than a straight line
+            let mut cheap_diagonals = grid.clone();
+            cheap_diagonals.set_movement(Movement::EightWay {
+                diagonal_cost: Movement::DIAGONAL_COST_14,
+                corner_cutting: movement.corner_cutting(),
+            });
+            let mut hex = grid.clone();
+            hex.set_topology(Topology::hex(HexOrientation::Flat, HexOffset::Even));
+            let cases = [
+                (grid, admissible),
+                (
+                    cheap_diagonals,
+                    vec![Heuristic::Chebyshev, Heuristic::Octile, zero()],
+                ),
+                (hex, vec![Heuristic::Hex, zero()]),
+            ];
+            for (grid, heuristics) in cases {
+                let goal = (7, 5);
+                for y in 0..grid.height() {
+                    for x in 0..grid.width() {
+                        let Some(cost) = reference_cost(&grid, (x, y), goal) else {
+                            continue;
+                        };
+                        for heuristic in &heuristics {
+                            let estimate = heuristic.estimate(&grid, (x, y), goal);
+                            assert!(estimate <= cost + 1e-9, "{heuristic:?} at {:?}", (x, y));
+                            checked += 1;
+                        }
+                    }
+                }
+            }
+        }
+        assert!(checked > 0);
+    }
+}
@@ -0,0 +1,591 @@
+use super::path::turning_points;
+use super::{
+    Grid, Node, PathError, PathQuery, PathResult, Pos, SearchEvent, SearchObserver, SearchStats,
+    Side,
+};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+// HPA*: the grid is cut into square clusters and the cells where a unit can cross from one
+// cluster into the next become the nodes of a much smaller abstract graph. Inside a cluster
+// the entrances are linked by their shortest in-cluster distance. A query connects start and
+// goal to the entrances of their clusters, searches the abstract graph and then refines each
+// abstract edge into cells with a search that never leaves one cluster. Paths are close to
+// optimal but not guaranteed to be.
+
+// Cluster coordinate (column, row)
+type Cluster = (i32, i32);
+
+// Runs of open border at least this long get an entrance at both ends instead of one in the
+// middle
+const WIDE_ENTRANCE: usize = 6;
+
+// The border a cluster shares with its neighbor to the right or below
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+enum Border {
+    Right,
+    Down,
+}
+
+// Best known distance and the previous cell on the way, for every cell a cluster search reached
+type ClusterTree = HashMap<Pos, (f64, Pos)>;
+
+// Abstract graph of a grid, kept between queries so a changed cell only rebuilds the
+// clusters around it
+#[derive(Clone, Debug)]
+pub struct Hierarchy {
+    cluster_size: i32,
+    // Pairs of cells on both sides of each border that a unit can step between
+    entrances: HashMap<(Cluster, Border), Vec<(Pos, Pos)>>,
+    // Steps between entrance cells of neighboring clusters, with their cost
+    inter_edges: HashMap<Pos, Vec<(Pos, f64)>>,
+    // Shortest in-cluster distances between the entrance cells of each cluster
+    intra_edges: HashMap<Cluster, HashMap<Pos, Vec<(Pos, f64)>>>,
+    // Cells changed since the abstract graph was last brought up to date
+    pending: Vec<Pos>,
+}
+
+impl Hierarchy {
+    pub fn new(grid: &Grid, cluster_size: i32) -> Self {
+        let mut hierarchy = Self {
+            cluster_size: cluster_size.max(1),
+            entrances: HashMap::new(),
+            inter_edges: HashMap::new(),
+            intra_edges: HashMap::new(),
+            pending: Vec::new(),
+        };
+        let (columns, rows) = hierarchy.cluster_count(grid);
+        for cy in 0..rows {
+            for cx in 0..columns {
+                for border in [Border::Right, Border::Down] {
+                    hierarchy.build_entrances(grid, (cx, cy), border);
+                }
+            }
+        }
+        hierarchy.build_inter_edges(grid);
+        for cy in 0..rows {
+            for cx in 0..columns {
+                hierarchy.build_intra_edges(grid, (cx, cy));
+            }
+        }
+        hierarchy
+    }
+
+    pub fn cluster_size(&self) -> i32 {
+        self.cluster_size
+    }
+
+    // Cells of the abstract graph: the entrances between clusters
+    pub fn abstract_nodes(&self) -> impl Iterator<Item = Pos> + '_ {
+        self.inter_edges.keys().copied()
+    }
+
+    // Report cells whose wall or terrain changed, or that became or stopped being a portal
+    // end; their clusters are rebuilt on the next query
+    pub fn update_cells(&mut self, changed: &[Pos]) {
+        self.pending.extend_from_slice(changed);
+    }
+
+    // Search the abstract graph, then refine the result into a path of adjacent cells
+    pub fn find_path(
+        &mut self,
+        grid: &Grid,
+        query: &PathQuery,
+        observer: &mut impl SearchObserver,
+    ) -> Result<PathResult, PathError> {
+        let start = query.start;
+        grid.validate_query(query)?;
+        // Bigger units need a hierarchy of their own, built on `Grid::for_agent_size`
+        if query.agent_size > 1 {
+            return Err(PathError::Unsupported("HPA* needs a one-cell unit"));
+        }
+        let goals = grid.goal_cells(query);
+        self.rebuild_pending(grid);
+
+        let h = |pos: Pos| query.heuristic.estimate_nearest(grid, pos, &goals);
+        let mut stats = SearchStats::default();
+
+        // Temporarily hook start and goals into the entrances of their clusters
+        let from_start = self.search_cluster(grid, self.cluster_of(start), start, false);
+        let to_goals: HashMap<Pos, ClusterTree> = goals
+            .iter()
+            .map(|&goal| {
+                (
+                    goal,
+                    self.search_cluster(grid, self.cluster_of(goal), goal, true),
+                )
+            })
+            .collect();
+        let start_edges: Vec<(Pos, f64)> = from_start
+            .iter()
+            .filter(|&(&pos, _)| goals.contains(&pos) || self.inter_edges.contains_key(&pos))
+            .map(|(&pos, &(cost, _))| (pos, cost))
+            .collect();
+
+        let mut open_set = BinaryHeap::new();
+        let mut best_g = HashMap::from([(start, 0.0)]);
+        let mut came_from = HashMap::new();
+        let mut closed_set = HashSet::new();
+        open_set.push(Node::new(start, 0.0, h(start)));
+        observer.on_event(SearchEvent::Open {
+            pos: start,
+            f: h(start),
+            g: 0.0,
+            h: h(start),
+            side: Side::Forward,
+        });
+
+        let mut found = None;
+        while let Some(current) = open_set.pop() {
+            if observer.is_cancelled() {
+                return Err(PathError::Cancelled(stats));
+            }
+            let pos = current.position;
+            if !closed_set.insert(pos) {
+                stats.stale_skipped += 1;
+                continue;
+            }
+
+            stats.nodes_expanded += 1;
+            observer.on_event(SearchEvent::Expand {
+                pos,
+                f: current.f_score,
+                g: current.g_score,
+                h: current.h_score,
+                side: Side::Forward,
+            });
+            if goals.contains(&pos) {
+                found = Some(pos);
+                break;
+            }
+
+            let mut edges: Vec<(Pos, f64)> = self
+                .inter_edges
+                .get(&pos)
+                .into_iter()
+                .flatten()
+                .chain(
+                    self.intra_edges
+                        .get(&self.cluster_of(pos))
+                        .and_then(|edges| edges.get(&pos))
+                        .into_iter()
+                        .flatten(),
+                )
+                .copied()
+                .collect();
+            if pos == start {
+                edges.extend_from_slice(&start_edges);
+            }
+            for (&goal, to_goal) in &to_goals {
+                if let Some(&(cost, _)) = to_goal.get(&pos) {
+                    edges.push((goal, cost));
+                }
+            }
+
+            for (neighbor, cost) in edges {
+                if closed_set.contains(&neighbor) {
+                    continue;
+                }
+                let g = current.g_score + cost;
+                if best_g.get(&neighbor).is_some_and(|&best| best <= g) {
+                    continue;
+                }
+                if best_g.insert(neighbor, g).is_some() {
+                    stats.duplicate_entries += 1;
+                }
+                came_from.insert(neighbor, pos);
+                let node = Node::new(neighbor, g, h(neighbor));
+                open_set.push(node);
+                observer.on_event(SearchEvent::Open {
+                    pos: neighbor,
+                    f: node.f_score,
+                    g,
+                    h: node.h_score,
+                    side: Side::Forward,
+                });
+            }
+        }
+        let Some(goal) = found else {
+            return Err(PathError::Unreachable(stats));
+        };
+
+        let mut abstract_path = vec![goal];
+        let mut current = goal;
+        while let Some(&previous) = came_from.get(&current) {
+            abstract_path.push(previous);
+            current = previous;
+        }
+        abstract_path.reverse();
+
+        // Refine: steps between clusters are single moves, the rest is searched again
+        // inside its cluster
+        let mut cells = vec![start];
+        for pair in abstract_path.windows(2) {
+            let (from, to) = (pair[0], pair[1]);
+            if self.cluster_of(from) != self.cluster_of(to) {
+                cells.push(to);
+                continue;
+            }
+            if to == goal {
+                // Already searched backwards from the goal: follow the next steps
+                let mut current = from;
+                while current != goal {
+                    current = to_goals[&goal][&current].1;
+                    cells.push(current);
+                }
+                continue;
+            }
+            let searched;
+            let tree = if from == start {
+                &from_start
+            } else {
+                searched = self.search_cluster(grid, self.cluster_of(from), from, false);
+                &searched
+            };
+            let mut segment = vec![to];
+            let mut current = to;
+            while current != from {
+                current = tree[&current].1;
+                segment.push(current);
+            }
+            segment.pop();
+            segment.reverse();
+            cells.extend(segment);
+        }
+
+        Ok(PathResult {
+            cost: grid.path_cost(&cells),
+            waypoints: turning_points(grid, &cells),
+            cells,
+            stats,
+        })
+    }
+
+    fn cluster_count(&self, grid: &Grid) -> (i32, i32) {
+        let size = self.cluster_size;
+        (
+            (grid.width() + size - 1) / size,
+            (grid.height() + size - 1) / size,
+        )
+    }
+
+    // The cluster at `(cx, cy)`, brought back across wrapped edges; None off the grid
+    fn wrap_cluster(&self, grid: &Grid, (cx, cy): Cluster) -> Option<Cluster> {
+        let (columns, rows) = self.cluster_count(grid);
+        let wrap = grid.wrap();
+        let cx = if wrap.wraps_x() {
+            cx.rem_euclid(columns.max(1))
+        } else {
+            cx
+        };
+        let cy = if wrap.wraps_y() {
+            cy.rem_euclid(rows.max(1))
+        } else {
+            cy
+        };
+        ((0..columns).contains(&cx) && (0..rows).contains(&cy)).then_some((cx, cy))
+    }
+
+    fn cluster_of(&self, (x, y): Pos) -> Cluster {
+        (
+            x.div_euclid(self.cluster_size),
+            y.div_euclid(self.cluster_size),
+        )
+    }
+
+    // Dijkstra that never leaves `cluster`. Forward it measures the way from `source` to each
+    // cell and the previous cell points back towards `source`; backward it measures the way
+    // from each cell to `source` and the previous cell is the next step towards it.
+    fn search_cluster(
+        &self,
+        grid: &Grid,
+        cluster: Cluster,
+        source: Pos,
+        backward: bool,
+    ) -> ClusterTree {
+        let mut tree = ClusterTree::from([(source, (0.0, source))]);
+        let mut closed_set = HashSet::new();
+        let mut open_set = BinaryHeap::from([Node::new(source, 0.0, 0.0)]);
+        while let Some(current) = open_set.pop() {
+            let pos = current.position;
+            if !closed_set.insert(pos) {
+                continue;
+            }
+            let edges = if backward {
+                grid.get_predecessors(pos)
+            } else {
+                grid.get_neighbors(pos)
+            };
+            for (neighbor, cost) in edges {
+                if self.cluster_of(neighbor) != cluster {
+                    continue;
+                }
+                let g = current.g_score + cost;
+                if tree.get(&neighbor).is_some_and(|&(best, _)| best <= g) {
+                    continue;
+                }
+                tree.insert(neighbor, (g, pos));
+                open_set.push(Node::new(neighbor, g, 0.0));
+            }
+        }
+        tree
+    }
+
+    // Scan a border for runs of cell pairs a unit can cross and place entrances in them
+    fn build_entrances(&mut self, grid: &Grid, (cx, cy): Cluster, border: Border) {
+        let size = self.cluster_size;
+        // Clusters along the right and bottom edges may be cut short
+        let right = (cx * size + size).min(grid.width()) - 1;
+        let bottom = (cy * size + size).min(grid.height()) - 1;
+        // The i-th cell along the border on this side, the step across it and how many
+        // cells the border has
+        let (first, along, across, length) = match border {
+            Border::Right => (
+                (right, cy * size),
+                (0, 1),
+                (1, 0),
+                (grid.height() - cy * size).min(size),
+            ),
+            Border::Down => (
+                (cx * size, bottom),
+                (1, 0),
+                (0, 1),
+                (grid.width() - cx * size).min(size),
+            ),
+        };
+        // Past a wrapped edge the border goes on with the clusters on the other side
+        let inside = |i: i32| grid.wrap_pos((first.0 + along.0 * i, first.1 + along.1 * i));
+        let outside = |i: i32| {
+            let (x, y) = inside(i);
+            grid.wrap_pos((x + across.0, y + across.1))
+        };
+        // Neighbors are listed even from inside a wall, so check both ends
+        let crossable = |from: Pos, to: Pos| {
+            grid.is_passable(from)
+                && grid.is_passable(to)
+                && (grid.get_neighbors(from).iter().any(|&(pos, _)| pos == to)
+                    || grid.get_neighbors(to).iter().any(|&(pos, _)| pos == from))
+        };
+
+        // A run is only walked along to its entrances if every cell in it can be crossed any
+        // way, so one-way cells are entrances of their own
+        let one_way =
+            |i: i32| grid.one_way(inside(i)).is_some() || grid.one_way(outside(i)).is_some();
+
+        let mut entrances = Vec::new();
+        if length > 0 && grid.in_bounds(outside(0)) {
+            let mut run: Vec<i32> = Vec::new();
+            for i in 0..=length {
+                let crossing = i < length && crossable(inside(i), outside(i));
+                if crossing && !one_way(i) {
+                    run.push(i);
+                    continue;
+                }
+                match run.as_slice() {
+                    [] => {}
+                    [first, .., last] if run.len() >= WIDE_ENTRANCE => {
+                        entrances.push((inside(*first), outside(*first)));
+                        entrances.push((inside(*last), outside(*last)));
+                    }
+                    _ => {
+                        let middle = run[run.len() / 2];
+                        entrances.push((inside(middle), outside(middle)));
+                    }
+                }
+                run.clear();
+                if crossing {
+                    entrances.push((inside(i), outside(i)));
+                }
+            }
+
+            // With 8-way movement a unit may also slip diagonally through a gap that no
+            // straight step crosses, or past one-way cells that turn it away
+            for i in 0..length {
+                for j in [i - 1, i + 1] {
+                    let open = |k: i32| grid.is_passable(inside(k)) && grid.is_passable(outside(k));
+                    let blocked = !open(i) && !open(j) || one_way(i) || one_way(j);
+                    if blocked && crossable(inside(i), outside(j)) {
+                        entrances.push((inside(i), outside(j)));
+                    }
+                }
+            }
+        }
+        self.entrances.insert(((cx, cy), border), entrances);
+    }
+
+    // Portals join the abstract graph like entrances, wherever their ends are
+    fn build_inter_edges(&mut self, grid: &Grid) {
+        self.inter_edges.clear();
+        let portals = grid.portals().iter().map(|portal| (portal.a, portal.b));
+        for (a, b) in self.entrances.values().flatten().copied().chain(portals) {
+            for (from, to) in [(a, b), (b, a)] {
+                let edges = self.inter_edges.entry(from).or_default();
+                if let Some(&(_, cost)) =
+                    grid.get_neighbors(from).iter().find(|&&(pos, _)| pos == to)
+                    && !edges.iter().any(|&(pos, _)| pos == to)
+                {
+                    edges.push((to, cost));
+                }
+            }
+        }
+    }
+
+    fn build_intra_edges(&mut self, grid: &Grid, cluster: Cluster) {
+        let nodes: Vec<Pos> = self
+            .abstract_nodes()
+            .filter(|&pos| self.cluster_of(pos) == cluster)
+            .collect();
+        let mut edges = HashMap::new();
+        for &from in &nodes {
+            let tree = self.search_cluster(grid, cluster, from, false);
+            let reachable = nodes
+                .iter()
+                .filter(|&&to| to != from)
+                .filter_map(|to| tree.get(to).map(|&(cost, _)| (*to, cost)))
+                .collect();
+            edges.insert(from, reachable);
+        }
+        self.intra_edges.insert(cluster, edges);
+    }
+
+    // Rebuild the borders and clusters a changed cell can affect. Corner cutting and
+    // diagonal entrances reach one cell further, so the clusters around it are included.
+    fn rebuild_pending(&mut self, grid: &Grid) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let mut dirty = HashSet::new();
+        for (x, y) in std::mem::take(&mut self.pending) {
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    let pos = grid.wrap_pos((x + dx, y + dy));
+                    if grid.in_bounds(pos) {
+                        dirty.insert(self.cluster_of(pos));
+                    }
+                }
+            }
+        }
+
+        for &(cx, cy) in &dirty {
+            let borders = [
+                ((cx, cy), Border::Right),
+                ((cx, cy), Border::Down),
+                ((cx - 1, cy), Border::Right),
+                ((cx, cy - 1), Border::Down),
+            ];
+            for (cluster, border) in borders {
+                if let Some(cluster) = self.wrap_cluster(grid, cluster) {
+                    self.build_entrances(grid, cluster, border);
+                }
+            }
+        }
+        self.build_inter_edges(grid);
+
+        // Entrances on a rebuilt border also belong to the cluster on its other side
+        let mut rebuild = HashSet::new();
+        for &(cx, cy) in &dirty {
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    if let Some(cluster) = self.wrap_cluster(grid, (cx + dx, cy + dy)) {
+                        rebuild.insert(cluster);
+                    }
+                }
+            }
+        }
+        for cluster in rebuild {
+            self.build_intra_edges(grid, cluster);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pathfinding::test_support::{assert_close, movements, random_grid, reference_cost};
+
+    fn nodes(hierarchy: &Hierarchy) -> Vec<Pos> {
+        let mut nodes: Vec<Pos> = hierarchy.abstract_nodes().collect();
+        nodes.sort();
+        nodes
+    }
+
+    // A valid path of adjacent cells, never cheaper than the optimum
+    fn check(grid: &Grid, query: &PathQuery, result: &Result<PathResult, PathError>) {
+        match (reference_cost(grid, query.start, query.goal), result) {
+            (Some(optimum), Ok(path)) => {
+                assert_eq!(path.cells.first(), Some(&query.start));
+                assert_eq!(path.goal(), Some(query.goal));
+                assert_close(grid.path_cost(&path.cells), path.cost);
+                assert!(path.cost + 1e-9 >= optimum);
+            }
+            (None, Err(PathError::Unreachable(_))) => {}
+            (_, Err(PathError::StartIsWall(_) | PathError::EndIsWall(_))) => {}
+            (optimum, result) => panic!("{result:?} against {optimum:?}"),
+        }
+    }
+
+    #[test]
+    fn narrow_borders_get_one_entrance_in_the_middle() {
+        let grid = Grid::new(6, 3);
+        let hierarchy = Hierarchy::new(&grid, 3);
+        assert_eq!(nodes(&hierarchy), [(2, 1), (3, 1)]);
+    }
+
+    #[test]
+    fn wide_borders_get_an_entrance_at_both_ends() {
+        let grid = Grid::new(16, 8);
+        let hierarchy = Hierarchy::new(&grid, 8);
+        assert_eq!(nodes(&hierarchy), [(7, 0), (7, 7), (8, 0), (8, 7)]);
+    }
+
+    #[test]
+    fn hierarchical_paths_are_valid() {
+        let (width, height) = (23, 17);
+        for seed in 0..30 {
+            let mut grid = random_grid(seed, width, height, 0.25, seed % 3 == 0);
+            grid.set_movement(movements()[seed as usize % 4]);
+            let mut hierarchy = Hierarchy::new(&grid, 2 + seed as i32 % 6);
+            for (start, goal) in [((0, 0), (22, 16)), ((22, 16), (0, 0)), ((0, 0), (0, 0))] {
+                let query = PathQuery::new(start, goal);
+                let result = hierarchy.find_path(&grid, &query, &mut ());
+                check(&grid, &query, &result);
+            }
+        }
+    }
+
+    #[test]
+    fn updated_hierarchy_matches_a_rebuilt_one() {
+        let (width, height) = (23, 17);
+        for seed in 0..12 {
+            let mut grid = random_grid(seed, width, height, 0.25, seed % 3 == 0);
+            grid.set_movement(movements()[seed as usize % 4]);
+            let cluster_size = 2 + seed as i32 % 6;
+            let mut hierarchy = Hierarchy::new(&grid, cluster_size);
+            let query = PathQuery::new((0, 0), (width - 1, height - 1));
+            let mut next = seed as i32 * 11;
+            for _ in 0..4 {
+                // Flip a few walls, the edges of the grid and of the clusters included
+                let mut changed = Vec::new();
+                for _ in 0..3 {
+                    next = (next + 53) % (width * height);
+                    let pos = (next % width, next / width);
+                    if pos != query.start && pos != query.goal {
+                        grid.set_wall(pos, !grid.is_wall(pos));
+                        changed.push(pos);
+                    }
+                }
+                hierarchy.update_cells(&changed);
+
+                let result = hierarchy.find_path(&grid, &query, &mut ());
+                let mut rebuilt = Hierarchy::new(&grid, cluster_size);
+                let fresh = rebuilt.find_path(&grid, &query, &mut ());
+                assert_eq!(nodes(&hierarchy), nodes(&rebuilt), "seed {seed}");
+                assert_eq!(
+                    result.as_ref().ok().map(|path| path.cost),
+                    fresh.as_ref().ok().map(|path| path.cost),
+                    "seed {seed}"
+                );
+                check(&grid, &query, &result);
+            }
+        }
+    }
+}
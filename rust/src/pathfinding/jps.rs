@@ -0,0 +1,587 @@
+use super::{CornerCutting, Grid, Movement, PathError, Pos, Wrap};
+
+// Jump Point Search: instead of pushing every neighbor, scan along straight and diagonal
+// rays and only push the cells where the optimal path may turn (jump points). Only valid on
+// grids where every passable cell costs the same.
+
+// How diagonal moves interact with walls; each variant has its own pruning rules
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Variant {
+    FourWay,
+    Always,
+    NotBetweenWalls,
+    BothOrthogonalsFree,
+}
+
+impl Variant {
+    fn of(grid: &Grid) -> Self {
+        match grid.movement() {
+            Movement::FourWay => Variant::FourWay,
+            Movement::EightWay { corner_cutting, .. } => match corner_cutting {
+                CornerCutting::Always => Variant::Always,
+                CornerCutting::NotBetweenWalls => Variant::NotBetweenWalls,
+                CornerCutting::BothOrthogonalsFree => Variant::BothOrthogonalsFree,
+            },
+        }
+    }
+
+    // Directions a ray can be cast in
+    fn directions(&self) -> &'static [(i32, i32)] {
+        match self {
+            Variant::FourWay => &Grid::DIRECTIONS,
+            _ => &JumpTable::DIRECTIONS,
+        }
+    }
+
+    // Rays that scan perpendicular sub-rays at every step: diagonals, or vertical moves on
+    // 4-way grids. The goal can be found by a sub-ray, so they need target handling.
+    fn is_composite(&self, (dx, dy): (i32, i32)) -> bool {
+        match self {
+            Variant::FourWay => dy != 0,
+            _ => dx != 0 && dy != 0,
+        }
+    }
+}
+
+// Check that a grid can be searched with JPS
+pub(crate) fn check_grid(grid: &Grid) -> Result<(), PathError> {
+    if grid.topology().is_hex() {
+        return Err(PathError::Unsupported(
+            "jump point search needs a square grid",
+        ));
+    }
+    if grid.wrap() != Wrap::None {
+        return Err(PathError::Unsupported(
+            "jump point search needs a grid without wrap-around",
+        ));
+    }
+    if !grid.portals().is_empty() {
+        return Err(PathError::Unsupported(
+            "jump point search needs a grid without portals",
+        ));
+    }
+    if grid.has_one_way() {
+        return Err(PathError::Unsupported(
+            "jump point search needs a grid without one-way cells",
+        ));
+    }
+    if grid.uniform_cost().is_none() {
+        return Err(PathError::Unsupported(
+            "jump point search needs uniform terrain costs",
+        ));
+    }
+    Ok(())
+}
+
+fn sign((dx, dy): (i32, i32)) -> (i32, i32) {
+    (dx.signum(), dy.signum())
+}
+
+// Ray scanning for one query; `goals` is empty when precomputing goal-independent jumps
+struct Jumper<'a> {
+    grid: &'a Grid,
+    variant: Variant,
+    goals: &'a [Pos],
+}
+
+impl Jumper<'_> {
+    fn walkable(&self, x: i32, y: i32) -> bool {
+        self.grid.is_passable((x, y))
+    }
+
+    // Scan from `pos` (the first cell of the ray) in direction (dx, dy) and return the first
+    // jump point, or None when the ray runs into a wall
+    fn jump(&self, (mut x, mut y): Pos, (dx, dy): (i32, i32)) -> Option<Pos> {
+        loop {
+            if !self.walkable(x, y) {
+                return None;
+            }
+            if self.goals.contains(&(x, y)) {
+                return Some((x, y));
+            }
+            if self.is_jump_point((x, y), (dx, dy)) {
+                return Some((x, y));
+            }
+            if !self.can_continue((x, y), (dx, dy)) {
+                return None;
+            }
+            x += dx;
+            y += dy;
+        }
+    }
+
+    // Whether (x, y), reached by moving in (dx, dy), has a forced neighbor or leads to one
+    // through a perpendicular sub-ray
+    fn is_jump_point(&self, (x, y): Pos, (dx, dy): (i32, i32)) -> bool {
+        let w = |x, y| self.walkable(x, y);
+        match self.variant {
+            Variant::FourWay | Variant::BothOrthogonalsFree => {
+                if dx != 0 && dy != 0 {
+                    self.jump((x + dx, y), (dx, 0)).is_some()
+                        || self.jump((x, y + dy), (0, dy)).is_some()
+                } else if dx != 0 {
+                    (w(x, y - 1) && !w(x - dx, y - 1)) || (w(x, y + 1) && !w(x - dx, y + 1))
+                } else {
+                    let forced =
+                        (w(x - 1, y) && !w(x - 1, y - dy)) || (w(x + 1, y) && !w(x + 1, y - dy));
+                    // Moving vertically on a 4-way grid has to look for horizontal jump points
+                    forced
+                        || (self.variant == Variant::FourWay
+                            && (self.jump((x + 1, y), (1, 0)).is_some()
+                                || self.jump((x - 1, y), (-1, 0)).is_some()))
+                }
+            }
+            Variant::Always | Variant::NotBetweenWalls => {
+                if dx != 0 && dy != 0 {
+                    (w(x - dx, y + dy) && !w(x - dx, y))
+                        || (w(x + dx, y - dy) && !w(x, y - dy))
+                        || self.jump((x + dx, y), (dx, 0)).is_some()
+                        || self.jump((x, y + dy), (0, dy)).is_some()
+                } else if dx != 0 {
+                    (w(x + dx, y + 1) && !w(x, y + 1)) || (w(x + dx, y - 1) && !w(x, y - 1))
+                } else {
+                    (w(x + 1, y + dy) && !w(x + 1, y)) || (w(x - 1, y + dy) && !w(x - 1, y))
+                }
+            }
+        }
+    }
+
+    // Whether the ray may take another step from (x, y) under the corner-cutting rule
+    fn can_continue(&self, (x, y): Pos, (dx, dy): (i32, i32)) -> bool {
+        if dx == 0 || dy == 0 {
+            return true;
+        }
+        let free = [(x + dx, y), (x, y + dy)]
+            .into_iter()
+            .filter(|&(x, y)| self.walkable(x, y))
+            .count();
+        match self.variant {
+            Variant::FourWay => false,
+            Variant::Always => true,
+            Variant::NotBetweenWalls => free >= 1,
+            Variant::BothOrthogonalsFree => free == 2,
+        }
+    }
+
+    // Neighbors worth scanning from `pos` given the direction it was reached from
+    fn pruned_neighbors(&self, (x, y): Pos, parent: Option<Pos>) -> Vec<Pos> {
+        let Some(parent) = parent else {
+            return self
+                .grid
+                .get_neighbors((x, y))
+                .into_iter()
+                .map(|(pos, _)| pos)
+                .collect();
+        };
+        let (dx, dy) = sign((x - parent.0, y - parent.1));
+        let w = |x, y| self.walkable(x, y);
+        let mut neighbors = Vec::new();
+        let mut push = |x: i32, y: i32| {
+            if self.walkable(x, y) {
+                neighbors.push((x, y));
+            }
+        };
+
+        match self.variant {
+            Variant::FourWay => {
+                if dx != 0 {
+                    push(x, y - 1);
+                    push(x, y + 1);
+                    push(x + dx, y);
+                } else {
+                    push(x - 1, y);
+                    push(x + 1, y);
+                    push(x, y + dy);
+                }
+            }
+            Variant::BothOrthogonalsFree => {
+                if dx != 0 && dy != 0 {
+                    push(x, y + dy);
+                    push(x + dx, y);
+                    if w(x, y + dy) && w(x + dx, y) {
+                        push(x + dx, y + dy);
+                    }
+                } else if dx != 0 {
+                    let (next, top, bottom) = (w(x + dx, y), w(x, y + 1), w(x, y - 1));
+                    if next {
+                        push(x + dx, y);
+                        if top {
+                            push(x + dx, y + 1);
+                        }
+                        if bottom {
+                            push(x + dx, y - 1);
+                        }
+                    }
+                    push(x, y + 1);
+                    push(x, y - 1);
+                } else {
+                    let (next, right, left) = (w(x, y + dy), w(x + 1, y), w(x - 1, y));
+                    if next {
+                        push(x, y + dy);
+                        if right {
+                            push(x + 1, y + dy);
+                        }
+                        if left {
+                            push(x - 1, y + dy);
+                        }
+                    }
+                    push(x + 1, y);
+                    push(x - 1, y);
+                }
+            }
+            Variant::Always => {
+                if dx != 0 && dy != 0 {
+                    push(x, y + dy);
+                    push(x + dx, y);
+                    push(x + dx, y + dy);
+                    if !w(x - dx, y) {
+                        push(x - dx, y + dy);
+                    }
+                    if !w(x, y - dy) {
+                        push(x + dx, y - dy);
+                    }
+                } else if dx != 0 {
+                    push(x + dx, y);
+                    if !w(x, y + 1) {
+                        push(x + dx, y + 1);
+                    }
+                    if !w(x, y - 1) {
+                        push(x + dx, y - 1);
+                    }
+                } else {
+                    push(x, y + dy);
+                    if !w(x + 1, y) {
+                        push(x + 1, y + dy);
+                    }
+                    if !w(x - 1, y) {
+                        push(x - 1, y + dy);
+                    }
+                }
+            }
+            Variant::NotBetweenWalls => {
+                if dx != 0 && dy != 0 {
+                    let (vertical, horizontal) = (w(x, y + dy), w(x + dx, y));
+                    push(x, y + dy);
+                    push(x + dx, y);
+                    if vertical || horizontal {
+                        push(x + dx, y + dy);
+                    }
+                    if !w(x - dx, y) && vertical {
+                        push(x - dx, y + dy);
+                    }
+                    if !w(x, y - dy) && horizontal {
+                        push(x + dx, y - dy);
+                    }
+                } else if dx != 0 {
+                    if w(x + dx, y) {
+                        push(x + dx, y);
+                        if !w(x, y + 1) {
+                            push(x + dx, y + 1);
+                        }
+                        if !w(x, y - 1) {
+                            push(x + dx, y - 1);
+                        }
+                    }
+                } else if w(x, y + dy) {
+                    push(x, y + dy);
+                    if !w(x + 1, y) {
+                        push(x + 1, y + dy);
+                    }
+                    if !w(x - 1, y) {
+                        push(x - 1, y + dy);
+                    }
+                }
+            }
+        }
+
+        neighbors
+    }
+}
+
+// Cost of a straight or diagonal segment between two cells on a uniform-cost grid
+fn segment_cost(grid: &Grid, from: Pos, to: Pos) -> f64 {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let unit = grid.uniform_cost().unwrap_or(1.0);
+    let steps = dx.abs().max(dy.abs()) as f64;
+    if dx != 0 && dy != 0 {
+        steps * grid.movement().diagonal_cost().unwrap_or(1.0) * unit
+    } else {
+        steps * unit
+    }
+}
+
+// Jump point successors of `pos` (reached from `parent`) with the cost of getting to each
+pub(crate) fn successors(
+    grid: &Grid,
+    goals: &[Pos],
+    pos: Pos,
+    parent: Option<Pos>,
+    table: Option<&JumpTable>,
+) -> Vec<(Pos, f64)> {
+    let jumper = Jumper {
+        grid,
+        variant: Variant::of(grid),
+        goals,
+    };
+
+    let mut successors = Vec::new();
+    for neighbor in jumper.pruned_neighbors(pos, parent) {
+        let direction = (neighbor.0 - pos.0, neighbor.1 - pos.1);
+        match table {
+            Some(table) => {
+                successors.extend(table.successors(jumper.variant, goals, pos, direction))
+            }
+            None => successors.extend(jumper.jump(neighbor, direction)),
+        }
+    }
+
+    successors.sort();
+    successors.dedup();
+    successors
+        .into_iter()
+        .map(|jump_point| (jump_point, segment_cost(grid, pos, jump_point)))
+        .collect()
+}
+
+// Fill in the cells between consecutive jump points
+pub(crate) fn expand_path(jump_points: &[Pos]) -> Vec<Pos> {
+    let mut cells = Vec::new();
+    for pair in jump_points.windows(2) {
+        let (mut current, to) = (pair[0], pair[1]);
+        let (dx, dy) = sign((to.0 - current.0, to.1 - current.1));
+        while current != to {
+            cells.push(current);
+            current = (current.0 + dx, current.1 + dy);
+        }
+    }
+    cells.extend(jump_points.last());
+    cells
+}
+
+// Precomputed ray lengths for JPS+: for every cell and direction, how far the first
+// goal-independent jump point is and how far the ray can travel at all
+#[derive(Clone, Debug, Default)]
+pub struct JumpTable {
+    width: i32,
+    rays: Vec<[Ray; 8]>,
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+struct Ray {
+    // Steps to the first jump point, if the ray reaches one
+    jump: Option<i32>,
+    // Steps the ray can travel before stopping at that jump point or a wall
+    reach: i32,
+}
+
+impl JumpTable {
+    // Up, up-right, right, down-right, down, down-left, left, up-left
+    pub const DIRECTIONS: [(i32, i32); 8] = [
+        (0, -1),
+        (1, -1),
+        (1, 0),
+        (1, 1),
+        (0, 1),
+        (-1, 1),
+        (-1, 0),
+        (-1, -1),
+    ];
+
+    pub fn new(grid: &Grid) -> Self {
+        let jumper = Jumper {
+            grid,
+            variant: Variant::of(grid),
+            goals: &[],
+        };
+
+        let mut rays = vec![[Ray::default(); 8]; (grid.width() * grid.height()) as usize];
+        for y in 0..grid.height() {
+            for x in 0..grid.width() {
+                if !grid.is_passable((x, y)) {
+                    continue;
+                }
+                let cell_rays = &mut rays[(y * grid.width() + x) as usize];
+                for &direction in jumper.variant.directions() {
+                    let (dx, dy) = direction;
+                    let jump = jumper
+                        .jump((x + dx, y + dy), direction)
+                        .map(|(jx, jy)| (jx - x).abs().max((jy - y).abs()));
+                    let reach = jump.unwrap_or_else(|| {
+                        // Count the cells the ray passes before it is blocked
+                        let mut steps = 0;
+                        let mut cell = (x, y);
+                        while jumper.can_continue(cell, direction)
+                            && grid.is_passable((cell.0 + dx, cell.1 + dy))
+                        {
+                            cell = (cell.0 + dx, cell.1 + dy);
+                            steps += 1;
+                        }
+                        steps
+                    });
+                    cell_rays[Self::direction_index(direction)] = Ray { jump, reach };
+                }
+            }
+        }
+
+        Self {
+            width: grid.width(),
+            rays,
+        }
+    }
+
+    fn direction_index(direction: (i32, i32)) -> usize {
+        Self::DIRECTIONS
+            .iter()
+            .position(|&d| d == direction)
+            .unwrap_or(0)
+    }
+
+    fn ray(&self, (x, y): Pos, direction: (i32, i32)) -> Ray {
+        self.rays[(y * self.width + x) as usize][Self::direction_index(direction)]
+    }
+
+    // Jump points along one ray from `pos`, adding a goal or a cell aligned with one when the
+    // ray passes by before its precomputed jump point
+    fn successors(
+        &self,
+        variant: Variant,
+        goals: &[Pos],
+        pos: Pos,
+        (dx, dy): (i32, i32),
+    ) -> Vec<Pos> {
+        let ray = self.ray(pos, (dx, dy));
+        let limit = ray.jump.unwrap_or(ray.reach);
+        let at = |steps: i32| (pos.0 + dx * steps, pos.1 + dy * steps);
+
+        // Steps after which the ray lines up with a goal
+        let mut targets = Vec::new();
+        for goal in goals {
+            let (gx, gy) = (goal.0 - pos.0, goal.1 - pos.1);
+            if variant.is_composite((dx, dy)) {
+                if dy != 0 && gy.signum() == dy {
+                    targets.push(gy.abs());
+                }
+                if dx != 0 && gx.signum() == dx {
+                    targets.push(gx.abs());
+                }
+            } else if (dx == 0 && gx == 0 && gy.signum() == dy)
+                || (dy == 0 && gy == 0 && gx.signum() == dx)
+            {
+                // The goal lies on this straight ray
+                targets.push(gx.abs().max(gy.abs()));
+            }
+        }
+
+        let mut successors: Vec<Pos> = targets
+            .into_iter()
+            .filter(|&steps| steps >= 1 && steps <= limit)
+            .map(at)
+            .collect();
+        successors.extend(ray.jump.map(at));
+        successors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pathfinding::test_support::{assert_close, movements, random_grid, reference_cost};
+    use crate::pathfinding::{Algorithm, Heuristic, PathQuery, PathResult, Pathfinder, Terrain};
+
+    fn find(
+        grid: &Grid,
+        start: Pos,
+        goal: Pos,
+        algorithm: Algorithm,
+    ) -> Result<PathResult, PathError> {
+        let heuristic = match grid.movement() {
+            Movement::FourWay => Heuristic::Manhattan,
+            Movement::EightWay { .. } => Heuristic::Octile,
+        };
+        let query = PathQuery::new(start, goal)
+            .with_algorithm(algorithm)
+            .with_heuristic(heuristic);
+        Pathfinder::new().find_path(grid, &query, &mut ())
+    }
+
+    #[test]
+    fn jump_point_costs_match_a_star() {
+        let (width, height) = (17, 13);
+        let (start, goal) = ((0, 0), (width - 1, height - 1));
+        let mut reached = 0;
+        for seed in 0..40 {
+            let mut grid = random_grid(seed, width, height, 0.3, false);
+            grid.set_movement(movements()[seed as usize % 4]);
+            let a_star = find(&grid, start, goal, Algorithm::AStar);
+            for algorithm in [Algorithm::JumpPointSearch, Algorithm::JumpPointSearchPlus] {
+                let result = find(&grid, start, goal, algorithm);
+                match (&a_star, result) {
+                    (Ok(a_star), Ok(path)) => {
+                        assert_close(path.cost, a_star.cost);
+                        // The cells between the jump points are filled in
+                        assert_close(grid.path_cost(&path.cells), path.cost);
+                        assert_eq!(path.cells.first(), Some(&start));
+                        assert_eq!(path.goal(), Some(goal));
+                        reached += 1;
+                    }
+                    (Err(PathError::Unreachable(_)), Err(PathError::Unreachable(_))) => {}
+                    (a_star, result) => {
+                        panic!(
+                            "seed {seed}, {}: {result:?} against {a_star:?}",
+                            algorithm.name()
+                        )
+                    }
+                }
+            }
+        }
+        assert!(reached > 0);
+    }
+
+    #[test]
+    fn jump_points_skip_open_ground() {
+        let grid = Grid::new(20, 20);
+        let a_star = find(&grid, (0, 0), (19, 12), Algorithm::AStar).unwrap();
+        let jps = find(&grid, (0, 0), (19, 12), Algorithm::JumpPointSearch).unwrap();
+        assert_close(jps.cost, a_star.cost);
+        assert!(jps.stats.nodes_expanded < a_star.stats.nodes_expanded);
+    }
+
+    #[test]
+    fn uniform_costs_other_than_one() {
+        let mut grid = random_grid(7, 10, 10, 0.2, false);
+        for y in 0..10 {
+            for x in 0..10 {
+                grid.set_terrain((x, y), Terrain::Mud);
+            }
+        }
+        let path = find(&grid, (0, 0), (9, 9), Algorithm::JumpPointSearchPlus).unwrap();
+        assert_close(path.cost, reference_cost(&grid, (0, 0), (9, 9)).unwrap());
+    }
+
+    #[test]
+    fn unsupported_grids() {
+        let mut grid = Grid::new(4, 4);
+        grid.set_terrain((1, 1), Terrain::Water);
+        assert!(matches!(
+            find(&grid, (0, 0), (3, 3), Algorithm::JumpPointSearch),
+            Err(PathError::Unsupported(_))
+        ));
+        grid.set_terrain((1, 1), Terrain::Ground);
+        grid.set_wrap(Wrap::Both);
+        assert_eq!(
+            find(&grid, (0, 0), (3, 3), Algorithm::JumpPointSearchPlus),
+            Err(PathError::Unsupported(
+                "jump point search needs a grid without wrap-around"
+            ))
+        );
+    }
+
+    #[test]
+    fn expand_path_fills_in_straight_runs() {
+        assert_eq!(
+            expand_path(&[(0, 0), (2, 2), (2, 4)]),
+            [(0, 0), (1, 1), (2, 2), (2, 3), (2, 4)]
+        );
+        assert_eq!(expand_path(&[(3, 1)]), [(3, 1)]);
+    }
+}
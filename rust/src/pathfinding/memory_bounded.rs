@@ -0,0 +1,554 @@
+use super::path::turning_points;
+use super::{
+    Grid, Heuristic, PathError, PathResult, Pos, SearchEvent, SearchObserver, SearchStats, Side,
+};
+use std::collections::HashSet;
+
+// Searches whose memory doesn't grow with the number of cells explored. IDA* only keeps the
+// current path and pays for it by re-expanding nodes in every iteration. SMA* keeps as many
+// nodes as the memory limit allows and forgets the least promising ones when it runs out,
+// remembering the best f below each forgotten subtree so it can come back later.
+
+// f values from different paths to the same cell can differ by rounding
+const F_EPSILON: f64 = 1e-9;
+
+// A node on the IDA* depth-first path
+struct Frame {
+    pos: Pos,
+    g: f64,
+    successors: std::vec::IntoIter<(Pos, f64)>,
+}
+
+// Whether a flood fill from `start` gets to any of the goals. Neither search can tell an
+// unreachable goal on its own short of trying every path there is, which an open grid has
+// far too many of, so both check this first.
+fn any_goal_reachable(grid: &Grid, start: Pos, goals: &[Pos]) -> bool {
+    let mut seen = HashSet::from([start]);
+    let mut stack = vec![start];
+    while let Some(pos) = stack.pop() {
+        if goals.contains(&pos) {
+            return true;
+        }
+        for (next, _) in grid.get_neighbors(pos) {
+            if seen.insert(next) {
+                stack.push(next);
+            }
+        }
+    }
+    false
+}
+
+pub(super) fn find_path_ida(
+    grid: &Grid,
+    start: Pos,
+    goals: &[Pos],
+    heuristic: &Heuristic,
+    observer: &mut impl SearchObserver,
+) -> Result<PathResult, PathError> {
+    if !any_goal_reachable(grid, start, goals) {
+        return Err(PathError::Unreachable(SearchStats::default()));
+    }
+    let h = |pos: Pos| heuristic.estimate_nearest(grid, pos, goals);
+    // Try the most promising successors first
+    let successors = |pos: Pos| {
+        let mut successors = grid.get_neighbors(pos);
+        successors.sort_by(|a, b| (a.1 + h(a.0)).total_cmp(&(b.1 + h(b.0))));
+        successors.into_iter()
+    };
+
+    let mut stats = SearchStats::default();
+    let mut bound = h(start);
+    loop {
+        observer.on_event(SearchEvent::Threshold { bound });
+        stats.nodes_expanded += 1;
+        observer.on_event(SearchEvent::Expand {
+            pos: start,
+            f: bound,
+            g: 0.0,
+            h: h(start),
+            side: Side::Forward,
+        });
+        if goals.contains(&start) {
+            return Ok(PathResult {
+                cells: vec![start],
+                waypoints: vec![start],
+                cost: 0.0,
+                stats,
+            });
+        }
+
+        // Depth-first within the bound; the smallest f beyond it becomes the next bound
+        let mut next_bound = f64::INFINITY;
+        let mut stack = vec![Frame {
+            pos: start,
+            g: 0.0,
+            successors: successors(start),
+        }];
+        let mut on_path = HashSet::from([start]);
+        while let Some(frame) = stack.last_mut() {
+            if observer.is_cancelled() {
+                return Err(PathError::Cancelled(stats));
+            }
+            let Some((pos, step_cost)) = frame.successors.next() else {
+                on_path.remove(&frame.pos);
+                stack.pop();
+                continue;
+            };
+            // Only simple paths: never step back onto the current one
+            if on_path.contains(&pos) {
+                continue;
+            }
+
+            let g = frame.g + step_cost;
+            let h_score = h(pos);
+            let f = g + h_score;
+            if f > bound + F_EPSILON {
+                next_bound = next_bound.min(f);
+                continue;
+            }
+
+            stats.nodes_expanded += 1;
+            observer.on_event(SearchEvent::Expand {
+                pos,
+                f,
+                g,
+                h: h_score,
+                side: Side::Forward,
+            });
+            if goals.contains(&pos) {
+                let cells: Vec<Pos> = stack.iter().map(|frame| frame.pos).chain([pos]).collect();
+                return Ok(PathResult {
+                    cost: grid.path_cost(&cells),
+                    waypoints: turning_points(grid, &cells),
+                    cells,
+                    stats,
+                });
+            }
+            on_path.insert(pos);
+            stack.push(Frame {
+                pos,
+                g,
+                successors: successors(pos),
+            });
+        }
+
+        if next_bound.is_infinite() {
+            return Err(PathError::Unreachable(stats));
+        }
+        bound = next_bound;
+    }
+}
+
+// A node SMA* holds in memory
+#[derive(Clone, Debug)]
+struct SmaNode {
+    pos: Pos,
+    parent: Option<usize>,
+    g: f64,
+    f: f64,
+    depth: usize,
+    children: Vec<usize>,
+    // Backed-up f of each child that was forgotten, used when it is generated again
+    forgotten: Vec<(Pos, f64)>,
+    // f of the best successor not in memory, infinite once there is none left to generate
+    next_f: f64,
+}
+
+pub(super) fn find_path_sma(
+    grid: &Grid,
+    start: Pos,
+    goals: &[Pos],
+    heuristic: &Heuristic,
+    memory_limit: usize,
+    observer: &mut impl SearchObserver,
+) -> Result<PathResult, PathError> {
+    if !any_goal_reachable(grid, start, goals) {
+        return Err(PathError::Unreachable(SearchStats::default()));
+    }
+    let memory_limit = memory_limit.max(1);
+    let h = |pos: Pos| heuristic.estimate_nearest(grid, pos, goals);
+
+    let mut stats = SearchStats::default();
+    // Slots of forgotten nodes are reused
+    let mut nodes: Vec<Option<SmaNode>> = Vec::new();
+    let mut free: Vec<usize> = Vec::new();
+    let mut in_memory = 0;
+    // A node was cut off because its path alone would fill the memory
+    let mut hit_limit = false;
+
+    let root = SmaNode {
+        pos: start,
+        parent: None,
+        g: 0.0,
+        f: h(start),
+        depth: 0,
+        children: Vec::new(),
+        forgotten: Vec::new(),
+        next_f: h(start),
+    };
+    observer.on_event(SearchEvent::Open {
+        pos: start,
+        f: root.f,
+        g: 0.0,
+        h: root.f,
+        side: Side::Forward,
+    });
+    nodes.push(Some(root));
+    in_memory += 1;
+
+    let node =
+        |nodes: &[Option<SmaNode>], id: usize| -> SmaNode { nodes[id].clone().expect("live node") };
+
+    loop {
+        if observer.is_cancelled() {
+            return Err(PathError::Cancelled(stats));
+        }
+
+        // Deepest of the nodes whose next successor has the lowest f
+        let best = nodes
+            .iter()
+            .enumerate()
+            .filter_map(|(id, node)| node.as_ref().map(|n| (id, n)))
+            .min_by(|(_, a), (_, b)| a.next_f.total_cmp(&b.next_f).then(b.depth.cmp(&a.depth)))
+            .map(|(id, _)| id);
+        let Some(id) = best.filter(|&id| node(&nodes, id).next_f.is_finite()) else {
+            return Err(if hit_limit {
+                PathError::MemoryLimit(stats)
+            } else {
+                PathError::Unreachable(stats)
+            });
+        };
+        let current = node(&nodes, id);
+
+        stats.nodes_expanded += 1;
+        observer.on_event(SearchEvent::Expand {
+            pos: current.pos,
+            f: current.f,
+            g: current.g,
+            h: h(current.pos),
+            side: Side::Forward,
+        });
+
+        if goals.contains(&current.pos) {
+            let mut cells = vec![current.pos];
+            let mut parent = current.parent;
+            while let Some(parent_id) = parent {
+                let parent_node = node(&nodes, parent_id);
+                cells.push(parent_node.pos);
+                parent = parent_node.parent;
+            }
+            cells.reverse();
+            return Ok(PathResult {
+                cost: grid.path_cost(&cells),
+                waypoints: turning_points(grid, &cells),
+                cells,
+                stats,
+            });
+        }
+
+        // Successors that aren't on the path to this node nor already in memory
+        let mut on_path = HashSet::new();
+        let mut ancestor = current.parent;
+        while let Some(ancestor_id) = ancestor {
+            let ancestor_node = node(&nodes, ancestor_id);
+            on_path.insert(ancestor_node.pos);
+            ancestor = ancestor_node.parent;
+        }
+        let in_children: HashSet<Pos> = current
+            .children
+            .iter()
+            .map(|&child| node(&nodes, child).pos)
+            .collect();
+        let mut missing: Vec<(Pos, f64, f64)> = Vec::new();
+        let mut dominated: Vec<Pos> = Vec::new();
+        for (pos, step_cost) in grid.get_neighbors(current.pos) {
+            if on_path.contains(&pos) || in_children.contains(&pos) {
+                continue;
+            }
+            let g = current.g + step_cost;
+            // Pathmax: f never decreases along a path. A forgotten child comes back with the
+            // f that was backed up from its subtree.
+            let f = current
+                .forgotten
+                .iter()
+                .find(|&&(forgotten, _)| forgotten == pos)
+                .map_or(current.f.max(g + h(pos)), |&(_, f)| f);
+            if f.is_infinite() {
+                continue;
+            }
+            // Another node in memory already reaches this cell at least as cheaply
+            if nodes.iter().flatten().any(|n| n.pos == pos && n.g <= g) {
+                dominated.push(pos);
+                continue;
+            }
+            missing.push((pos, g, f));
+        }
+        // Generate the most promising missing successor
+        missing.sort_by(|a, b| a.2.total_cmp(&b.2));
+
+        let child_id = match missing.first() {
+            Some(&(pos, g, f)) => {
+                let depth = current.depth + 1;
+                let f = if !goals.contains(&pos) && depth + 1 >= memory_limit {
+                    // Its path alone fills the memory, so it can never be expanded
+                    hit_limit = true;
+                    f64::INFINITY
+                } else {
+                    f
+                };
+                let child = SmaNode {
+                    pos,
+                    parent: Some(id),
+                    g,
+                    f,
+                    depth,
+                    children: Vec::new(),
+                    forgotten: Vec::new(),
+                    next_f: f,
+                };
+                observer.on_event(SearchEvent::Open {
+                    pos,
+                    f,
+                    g,
+                    h: h(pos),
+                    side: Side::Forward,
+                });
+                let child_id = match free.pop() {
+                    Some(slot) => {
+                        nodes[slot] = Some(child);
+                        slot
+                    }
+                    None => {
+                        nodes.push(Some(child));
+                        nodes.len() - 1
+                    }
+                };
+                in_memory += 1;
+                Some(child_id)
+            }
+            None => None,
+        };
+        if let Some(current) = nodes[id].as_mut() {
+            if let Some(&(pos, _, _)) = missing.first() {
+                current.forgotten.retain(|&(forgotten, _)| forgotten != pos);
+            }
+            // Dominated successors are never generated from here
+            for pos in dominated {
+                current.forgotten.retain(|&(forgotten, _)| forgotten != pos);
+                current.forgotten.push((pos, f64::INFINITY));
+            }
+            current.children.extend(child_id);
+            current.next_f = missing.get(1).map_or(f64::INFINITY, |&(_, _, f)| f);
+        }
+        back_up(&mut nodes, id);
+
+        // Out of memory: forget the shallowest of the highest-f leaves
+        if in_memory > memory_limit {
+            let leaf = nodes
+                .iter()
+                .enumerate()
+                .filter_map(|(leaf_id, node)| node.as_ref().map(|n| (leaf_id, n)))
+                .filter(|&(leaf_id, n)| {
+                    n.children.is_empty() && n.parent.is_some() && Some(leaf_id) != child_id
+                })
+                .max_by(|(_, a), (_, b)| a.f.total_cmp(&b.f).then(b.depth.cmp(&a.depth)))
+                .map(|(leaf_id, _)| leaf_id);
+            let Some(leaf_id) = leaf else {
+                return Err(PathError::MemoryLimit(stats));
+            };
+            let leaf = nodes[leaf_id].take().expect("live node");
+            free.push(leaf_id);
+            in_memory -= 1;
+            observer.on_event(SearchEvent::Forget { pos: leaf.pos });
+            if let Some(parent) = leaf.parent.and_then(|parent| nodes[parent].as_mut()) {
+                parent.children.retain(|&child| child != leaf_id);
+                parent.forgotten.push((leaf.pos, leaf.f));
+                parent.next_f = parent.next_f.min(leaf.f);
+            }
+        }
+    }
+}
+
+// A node is only as good as its best child or the best successor it can still generate;
+// pass changes upwards
+fn back_up(nodes: &mut [Option<SmaNode>], mut id: usize) {
+    loop {
+        let Some(node) = nodes[id].as_ref() else {
+            return;
+        };
+        let f = node
+            .children
+            .iter()
+            .filter_map(|&child| nodes[child].as_ref().map(|child| child.f))
+            .fold(node.next_f, f64::min);
+        if f == node.f {
+            return;
+        }
+        let parent = node.parent;
+        if let Some(node) = nodes[id].as_mut() {
+            node.f = f;
+        }
+        match parent {
+            Some(parent) => id = parent,
+            None => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pathfinding::test_support::{
+        assert_close, movements, parse, random_grid, reference_cost,
+    };
+    use crate::pathfinding::{
+        Algorithm, Grid, Heuristic, Movement, PathError, PathQuery, PathResult, Pathfinder,
+        SearchEvent, SearchObserver,
+    };
+
+    fn find(
+        grid: &Grid,
+        algorithm: Algorithm,
+        observer: &mut impl SearchObserver,
+    ) -> Result<PathResult, PathError> {
+        let heuristic = match grid.movement() {
+            Movement::FourWay => Heuristic::Manhattan,
+            Movement::EightWay { .. } => Heuristic::Octile,
+        };
+        let goal = (grid.width() - 1, grid.height() - 1);
+        let query = PathQuery::new((0, 0), goal)
+            .with_algorithm(algorithm)
+            .with_heuristic(heuristic);
+        Pathfinder::new().find_path(grid, &query, observer)
+    }
+
+    #[test]
+    fn memory_bounded_paths_are_optimal() {
+        let mut reached = 0;
+        for seed in 0..30 {
+            let mut grid = random_grid(seed, 7, 6, 0.2, seed % 3 == 0);
+            grid.set_movement(movements()[seed as usize % 4]);
+            let Some(cost) = reference_cost(&grid, (0, 0), (6, 5)) else {
+                for algorithm in [
+                    Algorithm::IterativeDeepeningAStar,
+                    Algorithm::SmaStar { memory_limit: 20 },
+                ] {
+                    let result = find(&grid, algorithm, &mut ());
+                    assert!(matches!(result, Err(PathError::Unreachable(_))));
+                }
+                continue;
+            };
+            for algorithm in [
+                Algorithm::IterativeDeepeningAStar,
+                Algorithm::SmaStar { memory_limit: 1000 },
+            ] {
+                let path = find(&grid, algorithm, &mut ()).unwrap();
+                assert_close(path.cost, cost);
+                assert_close(grid.path_cost(&path.cells), cost);
+            }
+            // With less memory SMA* still finds the best path, or says it can't fit one
+            match find(&grid, Algorithm::SmaStar { memory_limit: 20 }, &mut ()) {
+                Ok(path) => assert_close(path.cost, cost),
+                Err(PathError::MemoryLimit(_)) => {}
+                result => panic!("seed {seed}: {result:?}"),
+            }
+            reached += 1;
+        }
+        assert!(reached > 0);
+    }
+
+    #[test]
+    fn ida_star_raises_its_bound_between_passes() {
+        let (grid, _, _) = parse(
+            "
+            ...#...
+            .#.#.#.
+            .#...#.
+            ",
+        );
+        let mut events = Vec::new();
+        let path = find(&grid, Algorithm::IterativeDeepeningAStar, &mut events).unwrap();
+        let bounds: Vec<f64> = events
+            .iter()
+            .filter_map(|event| match *event {
+                SearchEvent::Threshold { bound } => Some(bound),
+                _ => None,
+            })
+            .collect();
+        assert!(bounds.len() > 1);
+        assert!(bounds.windows(2).all(|pair| pair[0] < pair[1]));
+        // The last pass is the one that found the path
+        assert_close(*bounds.last().unwrap(), path.cost);
+        assert_close(path.cost, reference_cost(&grid, (0, 0), (6, 2)).unwrap());
+    }
+
+    #[test]
+    fn sma_star_forgets_nodes_when_memory_runs_out() {
+        let (grid, _, _) = parse(
+            "
+            ......#.....
+            .####.#.###.
+            .#....#...#.
+            .#.####.#.#.
+            .#......#...
+            ",
+        );
+        let cost = reference_cost(&grid, (0, 0), (11, 4)).unwrap();
+        // Just enough memory for the 26 cells of the path
+        let mut events = Vec::new();
+        let path = find(&grid, Algorithm::SmaStar { memory_limit: 26 }, &mut events).unwrap();
+        assert_eq!(path.cells.len(), 26);
+        assert_close(path.cost, cost);
+        assert!(
+            events
+                .iter()
+                .any(|event| matches!(event, SearchEvent::Forget { .. }))
+        );
+        // One node less and the path doesn't fit
+        let result = find(&grid, Algorithm::SmaStar { memory_limit: 25 }, &mut ());
+        assert!(
+            matches!(result, Err(PathError::MemoryLimit(_))),
+            "{result:?}"
+        );
+    }
+
+    // Open 10x10 grid with the far corner walled in
+    fn walled_in_corner() -> Grid {
+        let mut grid = Grid::new(10, 10);
+        grid.set_wall((8, 9), true);
+        grid.set_wall((9, 8), true);
+        grid
+    }
+
+    #[test]
+    fn ida_star_gives_up_on_a_walled_in_goal_right_away() {
+        let result = find(
+            &walled_in_corner(),
+            Algorithm::IterativeDeepeningAStar,
+            &mut (),
+        );
+        assert_eq!(result, Err(PathError::Unreachable(Default::default())));
+    }
+
+    #[test]
+    fn sma_star_gives_up_on_a_walled_in_goal_right_away() {
+        // The limit the demo uses by default
+        let algorithm = Algorithm::SmaStar { memory_limit: 64 };
+        let result = find(&walled_in_corner(), algorithm, &mut ());
+        assert_eq!(result, Err(PathError::Unreachable(Default::default())));
+    }
+
+    #[test]
+    fn unreachable_goals() {
+        let (grid, _, _) = parse("..#.");
+        for algorithm in [
+            Algorithm::IterativeDeepeningAStar,
+            Algorithm::SmaStar { memory_limit: 100 },
+        ] {
+            let result = find(&grid, algorithm, &mut ());
+            assert!(
+                matches!(result, Err(PathError::Unreachable(_))),
+                "{result:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,51 @@
+// Headless pathfinding core. Nothing in here depends on Godot, so it can run in
+// `cargo test` and in server-side tools; the `Game`/`Block` layer only observes it.
+mod algorithm;
+mod bidirectional;
+mod dstar_lite;
+mod error;
+mod flow_field;
+mod grid;
+mod heuristic;
+mod hierarchical;
+mod jps;
+mod memory_bounded;
+mod movement;
+mod multi_agent;
+mod one_way;
+mod path;
+mod portal;
+mod region;
+mod route;
+mod search;
+mod space_time;
+mod terrain;
+#[cfg(test)]
+mod test_support;
+mod timed_wall;
+mod topology;
+mod wrap;
+
+pub use algorithm::Algorithm;
+pub use dstar_lite::DStarLite;
+pub use error::PathError;
+pub use flow_field::FlowField;
+pub use grid::{Cell, Grid};
+pub use heuristic::Heuristic;
+pub use hierarchical::Hierarchy;
+pub use jps::JumpTable;
+pub use movement::{CornerCutting, Movement};
+pub use multi_agent::{Coordination, MultiAgentPlan, TimedPath};
+pub use one_way::OneWay;
+pub use path::{PathQuery, PathResult, SearchStats};
+pub use portal::Portal;
+pub use region::GoalRegion;
+pub use route::Route;
+pub use search::{Node, Pathfinder, SearchEvent, SearchObserver, Side};
+pub use terrain::Terrain;
+pub use timed_wall::TimedWall;
+pub use topology::{HexOffset, HexOrientation, Topology};
+pub use wrap::Wrap;
+
+// Grid cell coordinate (x, y)
+pub type Pos = (i32, i32);
@@ -0,0 +1,152 @@
+use std::f64::consts::SQRT_2;
+
+// Which moves a unit may make from a cell
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum Movement {
+    // Up, right, down, left; every step costs 1
+    #[default]
+    FourWay,
+    // Also the four diagonals, each costing `diagonal_cost`
+    EightWay {
+        diagonal_cost: f64,
+        corner_cutting: CornerCutting,
+    },
+}
+
+impl Movement {
+    // Diagonal step cost of the classic 10/14 integer grid
+    pub const DIAGONAL_COST_14: f64 = 1.4;
+    // Exact diagonal step cost
+    pub const DIAGONAL_COST_SQRT2: f64 = SQRT_2;
+
+    pub fn eight_way(corner_cutting: CornerCutting) -> Self {
+        Movement::EightWay {
+            diagonal_cost: Self::DIAGONAL_COST_SQRT2,
+            corner_cutting,
+        }
+    }
+
+    pub fn diagonal_cost(&self) -> Option<f64> {
+        match self {
+            Movement::FourWay => None,
+            Movement::EightWay { diagonal_cost, .. } => Some(*diagonal_cost),
+        }
+    }
+
+    // Rule for squeezing past wall corners; 4-way units never cut corners
+    pub fn corner_cutting(&self) -> CornerCutting {
+        match self {
+            Movement::FourWay => CornerCutting::BothOrthogonalsFree,
+            Movement::EightWay { corner_cutting, .. } => *corner_cutting,
+        }
+    }
+}
+
+// When a diagonal move may pass the corners of the walls next to it
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum CornerCutting {
+    // Always allowed, even squeezing between two walls that touch at their corners
+    Always,
+    // Allowed past one wall corner, but never through the gap between two walls
+    #[default]
+    NotBetweenWalls,
+    // Only allowed when both orthogonal neighbors are free
+    BothOrthogonalsFree,
+}
+
+impl CornerCutting {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CornerCutting::Always => "Always",
+            CornerCutting::NotBetweenWalls => "Not between walls",
+            CornerCutting::BothOrthogonalsFree => "Both orthogonals free",
+        }
+    }
+
+    // Whether a diagonal move is allowed given how many of its two orthogonal neighbors are free
+    pub fn allows(&self, free_orthogonals: usize) -> bool {
+        match self {
+            CornerCutting::Always => true,
+            CornerCutting::NotBetweenWalls => free_orthogonals >= 1,
+            CornerCutting::BothOrthogonalsFree => free_orthogonals == 2,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pathfinding::test_support::{assert_close, parse};
+    use crate::pathfinding::{Heuristic, PathError, PathQuery, Pathfinder};
+
+    #[test]
+    fn corner_cutting_counts_free_orthogonals() {
+        let rules = [
+            (CornerCutting::Always, [true, true, true]),
+            (CornerCutting::NotBetweenWalls, [false, true, true]),
+            (CornerCutting::BothOrthogonalsFree, [false, false, true]),
+        ];
+        for (rule, allowed) in rules {
+            for (free, allowed) in allowed.into_iter().enumerate() {
+                assert_eq!(
+                    rule.allows(free),
+                    allowed,
+                    "{} with {free} free",
+                    rule.name()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn four_way_units_have_no_diagonals() {
+        assert_eq!(Movement::FourWay.diagonal_cost(), None);
+        assert_eq!(
+            Movement::FourWay.corner_cutting(),
+            CornerCutting::BothOrthogonalsFree
+        );
+        let movement = Movement::eight_way(CornerCutting::Always);
+        assert_eq!(movement.diagonal_cost(), Some(SQRT_2));
+        assert_eq!(movement.corner_cutting(), CornerCutting::Always);
+    }
+
+    #[test]
+    fn paths_cut_corners_only_where_the_rule_allows() {
+        let find = |map: &str, movement: Movement| {
+            let (mut grid, start, goal) = parse(map);
+            grid.set_movement(movement);
+            let query = PathQuery::new(start, goal).with_heuristic(Heuristic::Octile);
+            Pathfinder::new()
+                .find_path(&grid, &query, &mut ())
+                .map(|path| path.cost)
+        };
+        // Past one wall corner
+        let one_corner = "
+            S#.
+            ..G
+        ";
+        // Between two walls touching at their corners
+        let two_corners = "
+            S#
+            #G
+        ";
+        let cases = [
+            (CornerCutting::Always, Some(SQRT_2 + 1.0), Some(SQRT_2)),
+            (CornerCutting::NotBetweenWalls, Some(SQRT_2 + 1.0), None),
+            (CornerCutting::BothOrthogonalsFree, Some(3.0), None),
+        ];
+        for (rule, past_one, between_two) in cases {
+            let movement = Movement::eight_way(rule);
+            for (map, expected) in [(one_corner, past_one), (two_corners, between_two)] {
+                match (find(map, movement), expected) {
+                    (Ok(cost), Some(expected)) => assert_close(cost, expected),
+                    (Err(PathError::Unreachable(_)), None) => {}
+                    (result, expected) => {
+                        panic!("{}: {result:?} against {expected:?}", rule.name())
+                    }
+                }
+            }
+        }
+        assert_close(find(one_corner, Movement::FourWay).unwrap(), 3.0);
+    }
+}
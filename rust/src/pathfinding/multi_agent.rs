@@ -0,0 +1,634 @@
+use super::{Grid, PathError, Pathfinder, Pos, SearchEvent, SearchObserver, SearchStats, Side};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+// Several units sharing one grid. Every move, waiting in place included, takes one time step,
+// and two agents collide when they are in the same cell at the same step or swap cells during
+// one. Cooperative A* plans the agents one after another, each avoiding the cells the earlier
+// ones reserved in a space-time table: fast, but neither optimal nor guaranteed to find a
+// plan. Conflict-Based Search finds the plan with the lowest sum of costs by searching a tree
+// of constraints, replanning one agent whenever two of them collide.
+
+// Cost of standing still for one step
+pub(super) const WAIT_COST: f64 = 1.0;
+
+// CBS gives up after expanding this many constraint tree nodes; unsolvable instances would
+// otherwise keep it busy forever
+const CBS_NODE_LIMIT: usize = 2000;
+
+// How the paths of several agents are kept apart
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Coordination {
+    // Plan in order against a reservation table of the agents planned before
+    #[default]
+    Cooperative,
+    // Conflict-Based Search, optimal for the sum of path costs
+    ConflictBased,
+}
+
+impl Coordination {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Coordination::Cooperative => "Cooperative A*",
+            Coordination::ConflictBased => "CBS",
+        }
+    }
+}
+
+// Where one agent is at every time step; waiting repeats a cell. After the last step the
+// agent stays at its goal.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TimedPath {
+    pub cells: Vec<Pos>,
+    // Sum of the move and wait costs
+    pub cost: f64,
+}
+
+impl TimedPath {
+    // Cell the agent is in at time step `t`
+    pub fn at(&self, t: usize) -> Pos {
+        self.cells[t.min(self.cells.len() - 1)]
+    }
+
+    // Number of steps until the agent is at its goal for good
+    pub fn steps(&self) -> usize {
+        self.cells.len().saturating_sub(1)
+    }
+}
+
+// Collision-free paths for all agents, in the order the agents were given
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MultiAgentPlan {
+    pub paths: Vec<TimedPath>,
+    pub stats: SearchStats,
+}
+
+impl MultiAgentPlan {
+    // Sum of the agents' path costs
+    pub fn cost(&self) -> f64 {
+        self.paths.iter().map(|path| path.cost).sum()
+    }
+
+    // Steps until every agent is at its goal
+    pub fn makespan(&self) -> usize {
+        self.paths.iter().map(TimedPath::steps).max().unwrap_or(0)
+    }
+}
+
+// What a single agent may not do
+#[derive(Clone, Debug, Default)]
+struct Constraints {
+    // Be in a cell at a time step
+    cells: HashSet<(Pos, usize)>,
+    // Move from the first cell to the second, arriving at a time step
+    moves: HashSet<(Pos, Pos, usize)>,
+    // Enter a cell from a time step on, because another agent stays there (cooperative only)
+    parked: HashMap<Pos, usize>,
+    // Last time step any constraint mentions; after it the grid no longer changes
+    horizon: usize,
+}
+
+impl Constraints {
+    fn forbid_cell(&mut self, pos: Pos, t: usize) {
+        self.cells.insert((pos, t));
+        self.horizon = self.horizon.max(t);
+    }
+
+    fn forbid_move(&mut self, from: Pos, to: Pos, t: usize) {
+        self.moves.insert((from, to, t));
+        self.horizon = self.horizon.max(t);
+    }
+
+    // Reserve everything `path` occupies for the agents planned after it
+    fn reserve(&mut self, path: &TimedPath) {
+        for (t, &pos) in path.cells.iter().enumerate() {
+            self.forbid_cell(pos, t);
+        }
+        // Nobody may take the opposite move at the same time
+        for (t, pair) in path.cells.windows(2).enumerate() {
+            self.forbid_move(pair[1], pair[0], t + 1);
+        }
+        self.parked.insert(path.at(path.steps()), path.steps());
+    }
+
+    fn allows(&self, from: Pos, to: Pos, t: usize) -> bool {
+        !self.cells.contains(&(to, t))
+            && !self.moves.contains(&(from, to, t))
+            && self.parked.get(&to).is_none_or(|&since| t < since)
+    }
+
+    // An agent reaching its goal at `t` may only stop there if nothing forces it out later
+    fn can_stay(&self, goal: Pos, t: usize) -> bool {
+        !self.cells.iter().any(|&(pos, at)| pos == goal && at >= t)
+            && self.parked.get(&goal).is_none_or(|&since| t < since)
+    }
+}
+
+// Open set entry of a space-time search
+#[derive(Copy, Clone, Debug)]
+pub(super) struct TimedNode {
+    pub(super) pos: Pos,
+    pub(super) t: usize,
+    pub(super) g: f64,
+    pub(super) f: f64,
+}
+
+// Reverse ordering for min-heap; later time steps first among equal f, so the search dives
+impl Ord for TimedNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .f
+            .total_cmp(&self.f)
+            .then_with(|| self.t.cmp(&other.t))
+            .then_with(|| other.pos.cmp(&self.pos))
+    }
+}
+
+impl PartialOrd for TimedNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for TimedNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for TimedNode {}
+
+// One agent's goal and the true distance to it from every cell, the space-time search's
+// heuristic
+struct Agent {
+    start: Pos,
+    goal: Pos,
+    distances: HashMap<Pos, f64>,
+}
+
+impl Agent {
+    fn new(grid: &Grid, start: Pos, goal: Pos) -> Self {
+        // Dijkstra backwards from the goal, ignoring the other agents
+        let mut distances = HashMap::from([(goal, 0.0)]);
+        let mut open_set = BinaryHeap::from([TimedNode {
+            pos: goal,
+            t: 0,
+            g: 0.0,
+            f: 0.0,
+        }]);
+        while let Some(current) = open_set.pop() {
+            if distances[&current.pos] < current.g {
+                continue;
+            }
+            for (predecessor, cost) in grid.get_predecessors(current.pos) {
+                let g = current.g + cost;
+                if distances.get(&predecessor).is_none_or(|&best| g < best) {
+                    distances.insert(predecessor, g);
+                    open_set.push(TimedNode {
+                        pos: predecessor,
+                        t: 0,
+                        g,
+                        f: g,
+                    });
+                }
+            }
+        }
+        Self {
+            start,
+            goal,
+            distances,
+        }
+    }
+
+    // A* over (cell, time step) that respects `constraints`; Ok(None) when they leave no way
+    fn plan(
+        &self,
+        grid: &Grid,
+        index: usize,
+        constraints: &Constraints,
+        stats: &mut SearchStats,
+        observer: &mut impl SearchObserver,
+    ) -> Result<Option<TimedPath>, PathError> {
+        observer.on_event(SearchEvent::Agent {
+            index,
+            start: self.start,
+            goal: self.goal,
+        });
+        let h = |pos: Pos| self.distances[&pos];
+        // Past the horizon nothing changes anymore, so waiting longer than it takes to cross
+        // every cell can't help
+        let last_step = constraints.horizon + (grid.width() * grid.height()) as usize;
+
+        let mut open_set = BinaryHeap::from([TimedNode {
+            pos: self.start,
+            t: 0,
+            g: 0.0,
+            f: h(self.start),
+        }]);
+        let mut best_g = HashMap::from([((self.start, 0), 0.0)]);
+        let mut came_from: HashMap<(Pos, usize), (Pos, usize)> = HashMap::new();
+        let mut closed_set = HashSet::new();
+        while let Some(current) = open_set.pop() {
+            if observer.is_cancelled() {
+                return Err(PathError::Cancelled(*stats));
+            }
+            let state = (current.pos, current.t);
+            if !closed_set.insert(state) {
+                stats.stale_skipped += 1;
+                continue;
+            }
+
+            stats.nodes_expanded += 1;
+            let h_score = current.f - current.g;
+            observer.on_event(SearchEvent::Expand {
+                pos: current.pos,
+                f: current.f,
+                g: current.g,
+                h: h_score,
+                side: Side::Forward,
+            });
+            if current.pos == self.goal && constraints.can_stay(self.goal, current.t) {
+                let mut cells = vec![current.pos];
+                let mut state = state;
+                while let Some(&previous) = came_from.get(&state) {
+                    cells.push(previous.0);
+                    state = previous;
+                }
+                cells.reverse();
+                return Ok(Some(TimedPath {
+                    cells,
+                    cost: current.g,
+                }));
+            }
+            if current.t >= last_step {
+                continue;
+            }
+
+            let t = current.t + 1;
+            let moves = grid
+                .get_neighbors(current.pos)
+                .into_iter()
+                .chain([(current.pos, WAIT_COST)]);
+            for (next, cost) in moves {
+                // Cells the goal can't be reached from are dead ends
+                let Some(&h_score) = self.distances.get(&next) else {
+                    continue;
+                };
+                if !constraints.allows(current.pos, next, t) || closed_set.contains(&(next, t)) {
+                    continue;
+                }
+                let g = current.g + cost;
+                if best_g.get(&(next, t)).is_some_and(|&best| best <= g) {
+                    continue;
+                }
+                if best_g.insert((next, t), g).is_some() {
+                    stats.duplicate_entries += 1;
+                }
+                came_from.insert((next, t), state);
+                let node = TimedNode {
+                    pos: next,
+                    t,
+                    g,
+                    f: g + h_score,
+                };
+                open_set.push(node);
+                observer.on_event(SearchEvent::Open {
+                    pos: next,
+                    f: node.f,
+                    g,
+                    h: h_score,
+                    side: Side::Forward,
+                });
+            }
+        }
+        Ok(None)
+    }
+}
+
+// Two agents in each other's way at time step `t`
+#[derive(Copy, Clone, Debug)]
+enum Conflict {
+    // Both are in `pos`
+    Cell {
+        agents: (usize, usize),
+        pos: Pos,
+        t: usize,
+    },
+    // The first moves `from` -> `to` while the second moves the other way, arriving at `t`
+    Swap {
+        agents: (usize, usize),
+        from: Pos,
+        to: Pos,
+        t: usize,
+    },
+}
+
+// The earliest collision between any two paths
+fn first_conflict(paths: &[TimedPath]) -> Option<Conflict> {
+    let makespan = paths.iter().map(TimedPath::steps).max().unwrap_or(0);
+    for t in 0..=makespan {
+        for a in 0..paths.len() {
+            for b in a + 1..paths.len() {
+                let pos = paths[a].at(t);
+                if pos == paths[b].at(t) {
+                    return Some(Conflict::Cell {
+                        agents: (a, b),
+                        pos,
+                        t,
+                    });
+                }
+                if t > 0 && paths[a].at(t - 1) == paths[b].at(t) && paths[b].at(t - 1) == pos {
+                    return Some(Conflict::Swap {
+                        agents: (a, b),
+                        from: paths[a].at(t - 1),
+                        to: pos,
+                        t,
+                    });
+                }
+            }
+        }
+    }
+    None
+}
+
+// Constraint tree node of CBS
+struct CbsNode {
+    constraints: Vec<Constraints>,
+    paths: Vec<TimedPath>,
+}
+
+// Open set entry of CBS, by the sum of costs of the node's paths
+#[derive(Copy, Clone, Debug)]
+struct CbsEntry {
+    cost: f64,
+    id: usize,
+}
+
+// Reverse ordering for min-heap; among equal costs the node created first
+impl Ord for CbsEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .cost
+            .total_cmp(&self.cost)
+            .then_with(|| other.id.cmp(&self.id))
+    }
+}
+
+impl PartialOrd for CbsEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for CbsEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for CbsEntry {}
+
+impl Pathfinder {
+    // Collision-free paths for every (start, goal) pair. Fails with `PathError::Deadlock` when
+    // the agents can each reach their goal but not without running into each other.
+    pub fn find_paths(
+        &mut self,
+        grid: &Grid,
+        agents: &[(Pos, Pos)],
+        coordination: Coordination,
+        observer: &mut impl SearchObserver,
+    ) -> Result<MultiAgentPlan, PathError> {
+        for &(start, goal) in agents {
+            grid.validate_endpoints(start, goal)?;
+        }
+        // Agents sharing a start collide before they move, and agents sharing a goal once the
+        // second one arrives; no search can get around that
+        let (mut starts, mut goals) = (HashSet::new(), HashSet::new());
+        for &(start, goal) in agents {
+            if !starts.insert(start) {
+                return Err(PathError::SharedStart(start));
+            }
+            if !goals.insert(goal) {
+                return Err(PathError::SharedEnd(goal));
+            }
+        }
+        let agents: Vec<Agent> = agents
+            .iter()
+            .map(|&(start, goal)| Agent::new(grid, start, goal))
+            .collect();
+        let mut stats = SearchStats::default();
+        if agents
+            .iter()
+            .any(|agent| !agent.distances.contains_key(&agent.start))
+        {
+            return Err(PathError::Unreachable(stats));
+        }
+
+        match coordination {
+            Coordination::Cooperative => {
+                let mut reserved = Constraints::default();
+                let mut paths = Vec::with_capacity(agents.len());
+                for (index, agent) in agents.iter().enumerate() {
+                    let path = agent
+                        .plan(grid, index, &reserved, &mut stats, observer)?
+                        .ok_or(PathError::Deadlock(stats))?;
+                    reserved.reserve(&path);
+                    paths.push(path);
+                }
+                Ok(MultiAgentPlan { paths, stats })
+            }
+            Coordination::ConflictBased => find_paths_cbs(grid, &agents, stats, observer),
+        }
+    }
+}
+
+fn find_paths_cbs(
+    grid: &Grid,
+    agents: &[Agent],
+    mut stats: SearchStats,
+    observer: &mut impl SearchObserver,
+) -> Result<MultiAgentPlan, PathError> {
+    let constraints = vec![Constraints::default(); agents.len()];
+    let mut paths = Vec::with_capacity(agents.len());
+    for (index, agent) in agents.iter().enumerate() {
+        let path = agent
+            .plan(grid, index, &constraints[index], &mut stats, observer)?
+            .ok_or(PathError::Unreachable(stats))?;
+        paths.push(path);
+    }
+    let cost = paths.iter().map(|path| path.cost).sum();
+
+    let mut nodes = vec![CbsNode { constraints, paths }];
+    let mut open_set = BinaryHeap::from([CbsEntry { cost, id: 0 }]);
+    let mut expanded = 0;
+    while let Some(CbsEntry { id, .. }) = open_set.pop() {
+        if expanded == CBS_NODE_LIMIT {
+            break;
+        }
+        expanded += 1;
+        let Some(conflict) = first_conflict(&nodes[id].paths) else {
+            let paths = std::mem::take(&mut nodes[id].paths);
+            return Ok(MultiAgentPlan { paths, stats });
+        };
+
+        // Either agent gives way: one child forbids the collision to each
+        let (agents_in_conflict, t) = match conflict {
+            Conflict::Cell { agents, t, .. } | Conflict::Swap { agents, t, .. } => (agents, t),
+        };
+        for (side, agent) in [agents_in_conflict.0, agents_in_conflict.1]
+            .into_iter()
+            .enumerate()
+        {
+            let mut constraints = nodes[id].constraints.clone();
+            match conflict {
+                Conflict::Cell { pos, .. } => constraints[agent].forbid_cell(pos, t),
+                Conflict::Swap { from, to, .. } if side == 0 => {
+                    constraints[agent].forbid_move(from, to, t)
+                }
+                Conflict::Swap { from, to, .. } => constraints[agent].forbid_move(to, from, t),
+            }
+            let Some(path) =
+                agents[agent].plan(grid, agent, &constraints[agent], &mut stats, observer)?
+            else {
+                continue;
+            };
+            let mut paths = nodes[id].paths.clone();
+            paths[agent] = path;
+            let cost = paths.iter().map(|path| path.cost).sum();
+            nodes.push(CbsNode { constraints, paths });
+            open_set.push(CbsEntry {
+                cost,
+                id: nodes.len() - 1,
+            });
+        }
+    }
+    Err(PathError::Deadlock(stats))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pathfinding::test_support::{assert_close, movements, parse, random_grid};
+
+    const COORDINATIONS: [Coordination; 2] =
+        [Coordination::Cooperative, Coordination::ConflictBased];
+
+    fn plan(
+        grid: &Grid,
+        agents: &[(Pos, Pos)],
+        coordination: Coordination,
+    ) -> Result<MultiAgentPlan, PathError> {
+        Pathfinder::new().find_paths(grid, agents, coordination, &mut ())
+    }
+
+    // Every path runs from its agent's start to its goal in single moves or waits whose costs
+    // add up to the path's, and no two agents meet in a cell or swap cells
+    fn assert_collision_free(grid: &Grid, agents: &[(Pos, Pos)], plan: &MultiAgentPlan) {
+        assert_eq!(plan.paths.len(), agents.len());
+        for (path, &(start, goal)) in plan.paths.iter().zip(agents) {
+            assert_eq!(path.cells.first(), Some(&start));
+            assert_eq!(path.cells.last(), Some(&goal));
+            let mut cost = 0.0;
+            for pair in path.cells.windows(2) {
+                cost += if pair[0] == pair[1] {
+                    WAIT_COST
+                } else {
+                    grid.get_neighbors(pair[0])
+                        .into_iter()
+                        .find(|&(next, _)| next == pair[1])
+                        .unwrap_or_else(|| panic!("{pair:?} is not a move"))
+                        .1
+                };
+            }
+            assert_close(path.cost, cost);
+        }
+        for t in 0..=plan.makespan() {
+            for (i, a) in plan.paths.iter().enumerate() {
+                for b in &plan.paths[i + 1..] {
+                    assert_ne!(a.at(t), b.at(t), "agents meet at step {t}");
+                    assert!(
+                        t == 0 || a.at(t - 1) != b.at(t) || b.at(t - 1) != a.at(t),
+                        "agents swap cells at step {t}"
+                    );
+                }
+            }
+        }
+    }
+
+    // Two to four agents crossing the grid between its corners
+    fn corner_agents(width: i32, height: i32, count: usize) -> Vec<(Pos, Pos)> {
+        let (right, bottom) = (width - 1, height - 1);
+        [
+            ((0, 0), (right, bottom)),
+            ((right, bottom), (0, 0)),
+            ((right, 0), (0, bottom)),
+            ((0, bottom), (right, 0)),
+        ][..count]
+            .to_vec()
+    }
+
+    #[test]
+    fn agents_swapping_in_a_corridor_use_the_pocket() {
+        let (grid, _, _) = parse(
+            "
+            .#..
+            ....
+            ",
+        );
+        let agents = [((0, 1), (3, 1)), ((3, 1), (0, 1))];
+        for coordination in COORDINATIONS {
+            let plan = plan(&grid, &agents, coordination).unwrap();
+            assert_collision_free(&grid, &agents, &plan);
+        }
+    }
+
+    #[test]
+    fn plans_on_random_grids_are_collision_free() {
+        let mut planned = 0;
+        for seed in 0..40 {
+            let mut grid = random_grid(seed, 7, 5, 0.15, seed % 3 == 0);
+            grid.set_movement(movements()[seed as usize % 4]);
+            let agents = corner_agents(7, 5, 2 + seed as usize % 3);
+            for &(start, goal) in &agents {
+                grid.set_wall(start, false);
+                grid.set_wall(goal, false);
+            }
+            let cooperative = plan(&grid, &agents, Coordination::Cooperative);
+            let conflict_based = plan(&grid, &agents, Coordination::ConflictBased);
+            for result in [&cooperative, &conflict_based] {
+                match result {
+                    Ok(plan) => assert_collision_free(&grid, &agents, plan),
+                    Err(PathError::Unreachable(_) | PathError::Deadlock(_)) => {}
+                    Err(error) => panic!("seed {seed}: {error}"),
+                }
+            }
+            // CBS finds the cheapest plan, so it never does worse than planning one by one
+            if let (Ok(cooperative), Ok(conflict_based)) = (&cooperative, &conflict_based) {
+                assert!(
+                    conflict_based.cost() <= cooperative.cost() + 1e-9,
+                    "seed {seed}"
+                );
+                planned += 1;
+            }
+        }
+        assert!(planned > 0);
+    }
+
+    #[test]
+    fn shared_starts_and_goals_fail_before_searching() {
+        let grid = Grid::new(4, 4);
+        for coordination in COORDINATIONS {
+            let mut events = Vec::new();
+            let agents = [((0, 0), (3, 3)), ((1, 0), (3, 0)), ((0, 0), (0, 3))];
+            let result = Pathfinder::new().find_paths(&grid, &agents, coordination, &mut events);
+            assert_eq!(result, Err(PathError::SharedStart((0, 0))));
+            assert!(events.is_empty());
+
+            let agents = [((0, 0), (3, 3)), ((1, 0), (3, 3))];
+            assert_eq!(
+                plan(&grid, &agents, coordination),
+                Err(PathError::SharedEnd((3, 3)))
+            );
+        }
+    }
+}
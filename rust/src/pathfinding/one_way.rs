@@ -0,0 +1,151 @@
+// Direction a one-way cell (a conveyor, a ledge) is crossed in: units only step onto it and
+// off it going that way, or partly that way, never against it or straight across it. Hex
+// steps are judged by where the neighbor is shown in the brick layout.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum OneWay {
+    Up,
+    Right,
+    Down,
+    Left,
+}
+
+impl OneWay {
+    pub const ALL: [OneWay; 4] = [OneWay::Up, OneWay::Right, OneWay::Down, OneWay::Left];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            OneWay::Up => "Up",
+            OneWay::Right => "Right",
+            OneWay::Down => "Down",
+            OneWay::Left => "Left",
+        }
+    }
+
+    // The arrow as a grid offset, e.g. (0, -1) for up
+    pub fn delta(&self) -> (i32, i32) {
+        match self {
+            OneWay::Up => (0, -1),
+            OneWay::Right => (1, 0),
+            OneWay::Down => (0, 1),
+            OneWay::Left => (-1, 0),
+        }
+    }
+
+    // Whether a step going `direction` on screen goes with the arrow
+    pub fn allows(&self, (dx, dy): (i32, i32)) -> bool {
+        let (ax, ay) = self.delta();
+        dx * ax + dy * ay > 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pathfinding::test_support::{
+        assert_close, movements, parse, random_grid, reference_cost,
+    };
+    use crate::pathfinding::{Algorithm, Grid, Heuristic, PathError, PathQuery, Pathfinder};
+
+    #[test]
+    fn arrows_allow_steps_that_go_partly_their_way() {
+        assert!(OneWay::Right.allows((1, 0)));
+        assert!(OneWay::Right.allows((1, -1)));
+        assert!(!OneWay::Right.allows((0, 1)));
+        assert!(!OneWay::Right.allows((-1, 1)));
+        for one_way in OneWay::ALL {
+            assert!(one_way.allows(one_way.delta()));
+            assert!(!one_way.allows((0, 0)));
+        }
+    }
+
+    #[test]
+    fn steps_onto_and_off_a_one_way_cell() {
+        let mut grid = Grid::new(3, 2);
+        grid.set_one_way((1, 0), Some(OneWay::Right));
+        assert!(grid.allows_step((0, 0), (1, 0)));
+        assert!(grid.allows_step((1, 0), (2, 0)));
+        assert!(!grid.allows_step((2, 0), (1, 0)));
+        assert!(!grid.allows_step((1, 0), (0, 0)));
+        assert!(!grid.allows_step((1, 1), (1, 0)));
+        assert!(grid.allows_step((0, 1), (1, 1)));
+
+        // Both ends count when they are both one-way
+        grid.set_one_way((2, 0), Some(OneWay::Down));
+        assert!(!grid.allows_step((1, 0), (2, 0)));
+    }
+
+    #[test]
+    fn a_conveyor_sends_the_path_around() {
+        let (mut grid, start, goal) = parse(
+            "
+            G.S
+            ...
+            ",
+        );
+        grid.set_one_way((1, 0), Some(OneWay::Right));
+        let path = Pathfinder::new()
+            .find_path(&grid, &PathQuery::new(start, goal), &mut ())
+            .unwrap();
+        assert_eq!(path.cells, [(2, 0), (2, 1), (1, 1), (0, 1), (0, 0)]);
+        let back = PathQuery::new(goal, start);
+        let path = Pathfinder::new().find_path(&grid, &back, &mut ()).unwrap();
+        assert_eq!(path.cells, [(0, 0), (1, 0), (2, 0)]);
+
+        for algorithm in [Algorithm::JumpPointSearch, Algorithm::ThetaStar] {
+            let query = PathQuery::new(start, goal).with_algorithm(algorithm);
+            let result = Pathfinder::new().find_path(&grid, &query, &mut ());
+            assert!(matches!(result, Err(PathError::Unsupported(_))));
+        }
+    }
+
+    #[test]
+    fn searches_from_either_end_respect_one_way_cells() {
+        let algorithms = [
+            Algorithm::AStar,
+            Algorithm::Dijkstra,
+            Algorithm::BidirectionalAStar,
+            Algorithm::DStarLite,
+        ];
+        let mut reached = 0;
+        for seed in 0..16 {
+            let mut grid = random_grid(seed, 8, 6, 0.15, seed % 2 == 0);
+            grid.set_movement(movements()[seed as usize % 4]);
+            for y in 0..grid.height() {
+                for x in 0..grid.width() {
+                    if (x * 7 + y * 13 + seed as i32 * 5) % 4 == 0 {
+                        let arrow = OneWay::ALL[(x + y + seed as i32) as usize % 4];
+                        grid.set_one_way((x, y), Some(arrow));
+                    }
+                }
+            }
+            for y in 0..grid.height() {
+                for x in (0..grid.width()).filter(|&x| !grid.is_wall((x, y))) {
+                    for (to, cost) in grid.get_neighbors((x, y)) {
+                        assert!(grid.get_predecessors(to).contains(&((x, y), cost)));
+                    }
+                }
+            }
+            let (start, goal) = ((0, 0), (7, 5));
+            let expected = reference_cost(&grid, start, goal);
+            for algorithm in algorithms {
+                let query = PathQuery::new(start, goal)
+                    .with_algorithm(algorithm)
+                    .with_heuristic(Heuristic::Octile);
+                match (
+                    Pathfinder::new().find_path(&grid, &query, &mut ()),
+                    expected,
+                ) {
+                    (Ok(path), Some(cost)) => {
+                        assert_close(path.cost, cost);
+                        reached += 1;
+                    }
+                    (Err(PathError::Unreachable(_)), None) => {}
+                    (result, cost) => {
+                        panic!("seed {seed} {algorithm:?}: {result:?} against {cost:?}")
+                    }
+                }
+            }
+        }
+        assert!(reached > 0);
+    }
+}
@@ -0,0 +1,267 @@
+use super::{Algorithm, GoalRegion, Grid, Heuristic, PathError, Pos};
+use std::ops::AddAssign;
+
+// A single path request: where from, where to and how to search
+#[derive(Clone, Debug, PartialEq)]
+pub struct PathQuery {
+    pub start: Pos,
+    pub goal: Pos,
+    // More cells that count as arriving; the search ends at whichever goal is cheapest to reach
+    pub extra_goals: Vec<Pos>,
+    // Area that counts as arriving as soon as the path enters any open cell of it
+    pub goal_region: Option<GoalRegion>,
+    pub algorithm: Algorithm,
+    pub heuristic: Heuristic,
+    // Side of the square footprint of the unit, which `start` and the goals are the top-left
+    // cell of; only cells with at least this much clearance can be entered
+    pub agent_size: i32,
+}
+
+impl PathQuery {
+    pub fn new(start: Pos, goal: Pos) -> Self {
+        Self {
+            start,
+            goal,
+            extra_goals: Vec::new(),
+            goal_region: None,
+            algorithm: Algorithm::default(),
+            heuristic: Heuristic::default(),
+            agent_size: 1,
+        }
+    }
+
+    // Query that ends anywhere in `region`; None when the region has no cells
+    pub fn to_region(start: Pos, region: GoalRegion) -> Option<Self> {
+        let anchor = region.anchor()?;
+        Some(Self::new(start, anchor).with_goal_region(region))
+    }
+
+    // Query from whichever ends have been picked so far; the end may be left out when there is
+    // a goal region to head for instead
+    pub fn from_ends(
+        start: Option<Pos>,
+        end: Option<Pos>,
+        region: Option<GoalRegion>,
+    ) -> Result<Self, PathError> {
+        let start = start.ok_or(PathError::MissingStart)?;
+        let query = match (end, region) {
+            (Some(end), Some(region)) => Some(Self::new(start, end).with_goal_region(region)),
+            (Some(end), None) => Some(Self::new(start, end)),
+            (None, Some(region)) => Self::to_region(start, region),
+            (None, None) => None,
+        };
+        query.ok_or(PathError::MissingEnd)
+    }
+
+    pub fn with_algorithm(mut self, algorithm: Algorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    pub fn with_heuristic(mut self, heuristic: Heuristic) -> Self {
+        self.heuristic = heuristic;
+        self
+    }
+
+    pub fn with_agent_size(mut self, size: i32) -> Self {
+        self.agent_size = size;
+        self
+    }
+
+    pub fn with_extra_goals(mut self, goals: impl IntoIterator<Item = Pos>) -> Self {
+        self.extra_goals.extend(goals);
+        self
+    }
+
+    pub fn with_goal_region(mut self, region: GoalRegion) -> Self {
+        self.goal_region = Some(region);
+        self
+    }
+
+    // Whether arriving at `pos` ends the search, walls aside
+    pub fn is_goal(&self, pos: Pos) -> bool {
+        pos == self.goal
+            || self.extra_goals.contains(&pos)
+            || self
+                .goal_region
+                .as_ref()
+                .is_some_and(|region| region.contains(pos))
+    }
+}
+
+// Counters collected while searching, reported whether or not a path was found
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SearchStats {
+    pub nodes_expanded: usize,
+    // Pushes for a cell that already had a (more expensive) entry in the open set
+    pub duplicate_entries: usize,
+    // Popped entries that were discarded because the cell was already expanded more cheaply
+    pub stale_skipped: usize,
+}
+
+impl AddAssign for SearchStats {
+    fn add_assign(&mut self, other: Self) {
+        self.nodes_expanded += other.nodes_expanded;
+        self.duplicate_entries += other.duplicate_entries;
+        self.stale_skipped += other.stale_skipped;
+    }
+}
+
+// A found path: the ordered cells from start to goal (both inclusive) and what it cost. A
+// space-time path has one cell per time step, so it repeats a cell for every step it waits.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PathResult {
+    pub cells: Vec<Pos>,
+    // Start, goal and every cell where the path changes direction; consecutive waypoints are
+    // joined by straight segments, except for the two ends of a portal taken
+    pub waypoints: Vec<Pos>,
+    pub cost: f64,
+    pub stats: SearchStats,
+}
+
+impl PathResult {
+    // The goal the path ends at, which tells multi-goal queries which one was nearest
+    pub fn goal(&self) -> Option<Pos> {
+        self.cells.last().copied()
+    }
+
+    // Number of moves along the path (one less than the number of cells)
+    pub fn steps(&self) -> usize {
+        self.cells.len().saturating_sub(1)
+    }
+
+    // Straight segments between consecutive waypoints with their Euclidean length on `grid`
+    // (segments may cross its wrapped edges); portal hops aren't walked, so they are left out
+    pub fn segments<'a>(&'a self, grid: &'a Grid) -> impl Iterator<Item = (Pos, Pos, f64)> + 'a {
+        self.waypoints
+            .windows(2)
+            .filter(|pair| !grid.is_portal_hop(pair[0], pair[1]))
+            .map(|pair| (pair[0], pair[1], grid.line_length(pair[0], pair[1])))
+    }
+
+    // Euclidean length of the path, ignoring terrain
+    pub fn length(&self, grid: &Grid) -> f64 {
+        self.segments(grid).map(|(_, _, length)| length).sum()
+    }
+}
+
+// Drop the points that lie on a straight line between their neighbors. Hex lines are only
+// straight in axial coordinates, so the points are compared in those, and lines may go on
+// across wrapped edges. Both ends of a portal hop are kept.
+pub(crate) fn turning_points(grid: &Grid, points: &[Pos]) -> Vec<Pos> {
+    let topology = grid.topology();
+    let offset = |from: Pos, to: Pos| {
+        let (dx, dy) = grid.delta(from, to);
+        let (q1, r1) = topology.to_axial(from);
+        let (q2, r2) = topology.to_axial((from.0 + dx, from.1 + dy));
+        (q2 - q1, r2 - r1)
+    };
+    let mut turns: Vec<Pos> = Vec::with_capacity(points.len());
+    for (i, &point) in points.iter().enumerate() {
+        if let (Some(&prev), Some(&next)) = (turns.last(), points.get(i + 1)) {
+            let (ax, ay) = offset(prev, point);
+            let (bx, by) = offset(point, next);
+            if ax * by == ay * bx
+                && ax * bx + ay * by > 0
+                && !grid.is_portal_hop(prev, point)
+                && !grid.is_portal_hop(point, next)
+            {
+                continue;
+            }
+        }
+        turns.push(point);
+    }
+    turns
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pathfinding::test_support::{assert_close, parse};
+    use crate::pathfinding::{Pathfinder, Portal, Wrap};
+
+    #[test]
+    fn result_of_a_search() {
+        let (grid, start, goal) = parse(
+            "
+            S...
+            ###.
+            G...
+            ",
+        );
+        let path = Pathfinder::new()
+            .find_path(&grid, &PathQuery::new(start, goal), &mut ())
+            .unwrap();
+        assert_eq!(
+            path.cells,
+            [
+                (0, 0),
+                (1, 0),
+                (2, 0),
+                (3, 0),
+                (3, 1),
+                (3, 2),
+                (2, 2),
+                (1, 2),
+                (0, 2),
+            ]
+        );
+        assert_close(path.cost, 8.0);
+        assert_eq!(path.waypoints, [(0, 0), (3, 0), (3, 2), (0, 2)]);
+        // Every open cell lies on the corridor, and the search had to go through all of them
+        assert_eq!(path.stats.nodes_expanded, 9);
+        assert_eq!(path.goal(), Some(goal));
+        assert_eq!(path.steps(), 8);
+        assert_close(path.length(&grid), 8.0);
+    }
+
+    #[test]
+    fn no_path_reports_the_search() {
+        let (grid, start, goal) = parse("S.#G");
+        let result = Pathfinder::new().find_path(&grid, &PathQuery::new(start, goal), &mut ());
+        let Err(PathError::Unreachable(stats)) = result else {
+            panic!("expected no path, got {result:?}");
+        };
+        assert_eq!(stats.nodes_expanded, 2);
+        assert_eq!(PathResult::default().goal(), None);
+        assert_eq!(PathResult::default().steps(), 0);
+    }
+
+    #[test]
+    fn turning_points_keep_the_ends_and_the_turns() {
+        let grid = Grid::new(5, 5);
+        assert_eq!(turning_points(&grid, &[]), []);
+        assert_eq!(turning_points(&grid, &[(2, 2)]), [(2, 2)]);
+        assert_eq!(
+            turning_points(&grid, &[(0, 0), (1, 0), (2, 0), (2, 1), (2, 2)]),
+            [(0, 0), (2, 0), (2, 2)]
+        );
+        // Diagonal runs are straight too, and so are points that skip cells
+        assert_eq!(
+            turning_points(&grid, &[(0, 0), (1, 1), (2, 2), (4, 2)]),
+            [(0, 0), (2, 2), (4, 2)]
+        );
+        // Turning back is a turn
+        assert_eq!(
+            turning_points(&grid, &[(0, 0), (1, 0), (0, 0)]),
+            [(0, 0), (1, 0), (0, 0)]
+        );
+    }
+
+    #[test]
+    fn turning_points_across_wrapped_edges_and_portals() {
+        let mut grid = Grid::new(5, 3);
+        grid.set_wrap(Wrap::Horizontal);
+        assert_eq!(
+            turning_points(&grid, &[(3, 0), (4, 0), (0, 0), (1, 0)]),
+            [(3, 0), (1, 0)]
+        );
+
+        grid.set_wrap(Wrap::None);
+        grid.add_portal(Portal::new((1, 0), (2, 0)));
+        assert_eq!(
+            turning_points(&grid, &[(0, 0), (1, 0), (2, 0), (3, 0)]),
+            [(0, 0), (1, 0), (2, 0), (3, 0)]
+        );
+    }
+}
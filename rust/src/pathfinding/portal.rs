@@ -0,0 +1,120 @@
+use super::Pos;
+
+// Two cells linked both ways: a unit standing on one end can step to the other for `cost`,
+// however far apart they are. A cell is the end of at most one portal.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Portal {
+    pub a: Pos,
+    pub b: Pos,
+    pub cost: f64,
+}
+
+impl Portal {
+    // Teleporting takes no time
+    pub fn new(a: Pos, b: Pos) -> Self {
+        Self { a, b, cost: 0.0 }
+    }
+
+    pub fn with_cost(mut self, cost: f64) -> Self {
+        self.cost = cost;
+        self
+    }
+
+    // The end a unit comes out of when it enters at `pos`; None if `pos` isn't an end
+    pub fn other_end(&self, pos: Pos) -> Option<Pos> {
+        if pos == self.a {
+            Some(self.b)
+        } else if pos == self.b {
+            Some(self.a)
+        } else {
+            None
+        }
+    }
+
+    pub fn touches(&self, pos: Pos) -> bool {
+        self.other_end(pos).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pathfinding::test_support::{
+        assert_close, movements, parse, random_grid, reference_cost,
+    };
+    use crate::pathfinding::{Grid, Heuristic, PathQuery, Pathfinder};
+
+    #[test]
+    fn portals_lead_both_ways() {
+        let portal = Portal::new((0, 0), (3, 2)).with_cost(2.0);
+        assert_eq!(portal.other_end((0, 0)), Some((3, 2)));
+        assert_eq!(portal.other_end((3, 2)), Some((0, 0)));
+        assert_eq!(portal.other_end((1, 1)), None);
+        assert!(portal.touches((3, 2)) && !portal.touches((1, 1)));
+    }
+
+    #[test]
+    fn adding_a_portal_replaces_the_ones_at_its_ends() {
+        let mut grid = Grid::new(4, 4);
+        grid.add_portal(Portal::new((0, 0), (3, 3)));
+        grid.add_portal(Portal::new((3, 3), (0, 3)).with_cost(-1.0));
+        assert_eq!(grid.portals(), [Portal::new((3, 3), (0, 3))]);
+        // Portals onto themselves or off the grid are ignored
+        grid.add_portal(Portal::new((1, 1), (1, 1)));
+        grid.add_portal(Portal::new((1, 1), (4, 1)));
+        assert_eq!(grid.portals().len(), 1);
+        assert!(grid.is_portal_hop((0, 3), (3, 3)));
+        assert_eq!(
+            grid.remove_portal((0, 3)),
+            Some(Portal::new((3, 3), (0, 3)))
+        );
+        assert!(grid.portals().is_empty());
+    }
+
+    #[test]
+    fn estimates_count_chains_of_portals() {
+        let (mut grid, start, goal) = parse("S.........G");
+        grid.add_portal(Portal::new((1, 0), (5, 0)).with_cost(1.0));
+        grid.add_portal(Portal::new((6, 0), (9, 0)).with_cost(1.0));
+        // 1 to the first portal, 1 through it, 1 to the second, 1 through it and 1 to the goal
+        assert_close(Heuristic::Manhattan.estimate(&grid, start, goal), 5.0);
+        let path = Pathfinder::new()
+            .find_path(&grid, &PathQuery::new(start, goal), &mut ())
+            .unwrap();
+        assert_close(path.cost, 5.0);
+        assert_eq!(
+            path.waypoints,
+            [start, (1, 0), (5, 0), (6, 0), (9, 0), goal]
+        );
+    }
+
+    #[test]
+    fn paths_through_portals_are_optimal_on_random_grids() {
+        let mut reached = 0;
+        for seed in 0..16 {
+            let mut grid = random_grid(seed, 8, 6, 0.25, seed % 2 == 0);
+            grid.set_movement(movements()[seed as usize % 4]);
+            grid.add_portal(Portal::new((1, 4), (6, 1)).with_cost((seed % 3) as f64));
+            grid.add_portal(Portal::new((7, 0), (0, 5)).with_cost(0.5));
+            let goal = (7, 5);
+            for y in 0..grid.height() {
+                for x in 0..grid.width() {
+                    if let Some(cost) = reference_cost(&grid, (x, y), goal) {
+                        let estimate = Heuristic::Octile.estimate(&grid, (x, y), goal);
+                        assert!(estimate <= cost + 1e-9, "seed {seed} at {:?}", (x, y));
+                    }
+                }
+            }
+            let query = PathQuery::new((0, 0), goal).with_heuristic(Heuristic::Octile);
+            let path = Pathfinder::new().find_path(&grid, &query, &mut ());
+            match reference_cost(&grid, (0, 0), goal) {
+                Some(cost) => {
+                    assert_close(path.unwrap().cost, cost);
+                    reached += 1;
+                }
+                None => assert!(path.is_err()),
+            }
+        }
+        assert!(reached > 0);
+    }
+}
@@ -0,0 +1,105 @@
+use super::Pos;
+
+// An area a query may end anywhere in, e.g. "anywhere inside the base". Walls inside it are
+// simply not goals, and cells outside the grid are ignored.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GoalRegion {
+    // Every cell between the two corners, both included
+    Rect { min: Pos, max: Pos },
+    // Any set of cells
+    Cells(Vec<Pos>),
+}
+
+impl GoalRegion {
+    // Rectangle spanned by two opposite corners, in any order
+    pub fn rect(a: Pos, b: Pos) -> Self {
+        GoalRegion::Rect {
+            min: (a.0.min(b.0), a.1.min(b.1)),
+            max: (a.0.max(b.0), a.1.max(b.1)),
+        }
+    }
+
+    pub fn contains(&self, pos: Pos) -> bool {
+        match self {
+            GoalRegion::Rect { min, max } => {
+                (min.0..=max.0).contains(&pos.0) && (min.1..=max.1).contains(&pos.1)
+            }
+            GoalRegion::Cells(cells) => cells.contains(&pos),
+        }
+    }
+
+    // Cells of the region, row by row for a rectangle
+    pub fn cells(&self) -> Vec<Pos> {
+        match self {
+            GoalRegion::Rect { min, max } => (min.1..=max.1)
+                .flat_map(|y| (min.0..=max.0).map(move |x| (x, y)))
+                .collect(),
+            GoalRegion::Cells(cells) => cells.clone(),
+        }
+    }
+
+    // Some cell of the region, used as the nominal goal of a query that targets only it
+    pub fn anchor(&self) -> Option<Pos> {
+        match self {
+            GoalRegion::Rect { min, .. } => Some(*min),
+            GoalRegion::Cells(cells) => cells.first().copied(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pathfinding::test_support::{assert_close, parse};
+    use crate::pathfinding::{PathError, PathQuery, Pathfinder};
+
+    #[test]
+    fn rect_from_any_two_corners() {
+        let region = GoalRegion::rect((3, 1), (1, 2));
+        assert_eq!(
+            region,
+            GoalRegion::Rect {
+                min: (1, 1),
+                max: (3, 2)
+            }
+        );
+        assert_eq!(
+            region.cells(),
+            [(1, 1), (2, 1), (3, 1), (1, 2), (2, 2), (3, 2)]
+        );
+        assert!(region.contains((3, 2)) && !region.contains((0, 1)));
+        assert_eq!(region.anchor(), Some((1, 1)));
+        assert_eq!(GoalRegion::Cells(Vec::new()).anchor(), None);
+    }
+
+    #[test]
+    fn search_ends_on_entering_the_region() {
+        // The region's near side is walled off, so the path goes round to its far side
+        let (grid, start, _) = parse(
+            "
+            S.....
+            ..###.
+            ..#...
+            ......
+            ",
+        );
+        let region = GoalRegion::rect((2, 1), (4, 2));
+        let query = PathQuery::to_region(start, region).unwrap();
+        let path = Pathfinder::new().find_path(&grid, &query, &mut ()).unwrap();
+        assert_eq!(path.goal(), Some((3, 2)));
+        assert_close(path.cost, 7.0);
+    }
+
+    #[test]
+    fn region_of_walls_only() {
+        let (grid, start, _) = parse(
+            "
+            S.##
+            ..##
+            ",
+        );
+        let query = PathQuery::to_region(start, GoalRegion::rect((2, 0), (3, 1))).unwrap();
+        let result = Pathfinder::new().find_path(&grid, &query, &mut ());
+        assert_eq!(result, Err(PathError::EndIsWall((2, 0))));
+    }
+}
@@ -0,0 +1,166 @@
+use super::{Grid, PathError, PathQuery, PathResult, Pathfinder, Pos, SearchEvent, SearchObserver};
+
+// A path through an ordered list of stops, searched one leg at a time
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Route {
+    // The legs stitched together from the first stop to the last
+    pub path: PathResult,
+    // One path per pair of consecutive stops
+    pub legs: Vec<PathResult>,
+}
+
+impl Route {
+    pub fn new(legs: Vec<PathResult>) -> Self {
+        let mut path = PathResult::default();
+        for leg in &legs {
+            // Each leg starts where the previous one ended
+            let skip = usize::from(!path.cells.is_empty());
+            path.cells.extend(leg.cells.iter().skip(skip));
+            path.waypoints.extend(leg.waypoints.iter().skip(skip));
+            path.cost += leg.cost;
+            path.stats += leg.stats;
+        }
+        Self { path, legs }
+    }
+
+    // Cost of every leg, in order
+    pub fn leg_costs(&self) -> impl Iterator<Item = f64> + '_ {
+        self.legs.iter().map(|leg| leg.cost)
+    }
+}
+
+impl Pathfinder {
+    // Route from the query's start through `stops` in order to its goal (or the nearest of its
+    // goals), each leg searched with the query's algorithm and heuristic. Fails with the error
+    // of the first leg that does.
+    pub fn find_route(
+        &mut self,
+        grid: &Grid,
+        query: &PathQuery,
+        stops: &[Pos],
+        observer: &mut impl SearchObserver,
+    ) -> Result<Route, PathError> {
+        let points: Vec<Pos> = [query.start]
+            .into_iter()
+            .chain(stops.iter().copied())
+            .chain([query.goal])
+            .collect();
+        // Only the last leg may end at any of the goals
+        let last = points.len() - 2;
+        let leg_queries: Vec<PathQuery> = points
+            .windows(2)
+            .enumerate()
+            .map(|(index, pair)| PathQuery {
+                start: pair[0],
+                goal: pair[1],
+                extra_goals: if index == last {
+                    query.extra_goals.clone()
+                } else {
+                    Vec::new()
+                },
+                goal_region: query.goal_region.clone().filter(|_| index == last),
+                ..query.clone()
+            })
+            .collect();
+        // Validate every stop up front so a bad one isn't found only after searching
+        for leg_query in &leg_queries {
+            grid.validate_query(leg_query)?;
+        }
+
+        let mut legs = Vec::with_capacity(leg_queries.len());
+        for (index, leg_query) in leg_queries.iter().enumerate() {
+            observer.on_event(SearchEvent::Leg {
+                index,
+                start: leg_query.start,
+                goal: leg_query.goal,
+            });
+            legs.push(self.find_path(grid, leg_query, observer)?);
+        }
+        Ok(Route::new(legs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pathfinding::test_support::{assert_close, parse, reference_cost};
+
+    #[test]
+    fn route_visits_the_stops_in_order() {
+        let (grid, start, goal) = parse(
+            "
+            S...#....
+            .##.#.##.
+            ....#...G
+            .##...##.
+            ",
+        );
+        let stops = [(8, 0), (0, 3)];
+        let mut events = Vec::new();
+        let route = Pathfinder::new()
+            .find_route(&grid, &PathQuery::new(start, goal), &stops, &mut events)
+            .unwrap();
+
+        let points = [start, stops[0], stops[1], goal];
+        assert_eq!(route.legs.len(), 3);
+        for (leg, pair) in route.legs.iter().zip(points.windows(2)) {
+            assert_eq!(leg.cells.first(), Some(&pair[0]));
+            assert_eq!(leg.goal(), Some(pair[1]));
+            assert_close(leg.cost, reference_cost(&grid, pair[0], pair[1]).unwrap());
+        }
+        // The legs are stitched together without repeating the stops
+        assert_eq!(
+            route.path.cells.len(),
+            route.legs.iter().map(PathResult::steps).sum::<usize>() + 1
+        );
+        assert_close(route.path.cost, route.leg_costs().sum());
+        assert_close(grid.path_cost(&route.path.cells), route.path.cost);
+        let legs: Vec<(usize, Pos, Pos)> = events
+            .iter()
+            .filter_map(|event| match *event {
+                SearchEvent::Leg { index, start, goal } => Some((index, start, goal)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            legs,
+            [
+                (0, start, stops[0]),
+                (1, stops[0], stops[1]),
+                (2, stops[1], goal)
+            ]
+        );
+    }
+
+    #[test]
+    fn without_stops_a_route_is_the_path() {
+        let (grid, start, goal) = parse("S..G");
+        let query = PathQuery::new(start, goal);
+        let route = Pathfinder::new()
+            .find_route(&grid, &query, &[], &mut ())
+            .unwrap();
+        let path = Pathfinder::new().find_path(&grid, &query, &mut ()).unwrap();
+        assert_eq!(route.legs.len(), 1);
+        assert_eq!(route.legs[0], path);
+        assert_eq!(route.path, path);
+    }
+
+    #[test]
+    fn bad_stops_are_caught_before_searching() {
+        let (grid, start, goal) = parse(
+            "
+            S.#G
+            ..#.
+            ",
+        );
+        let query = PathQuery::new(start, goal);
+        let mut events = Vec::new();
+        let result = Pathfinder::new().find_route(&grid, &query, &[(1, 1), (2, 0)], &mut events);
+        assert_eq!(result, Err(PathError::EndIsWall((2, 0))));
+        assert!(events.is_empty());
+
+        // A leg that can't be walked fails the route
+        let result = Pathfinder::new().find_route(&grid, &query, &[(1, 1)], &mut ());
+        assert!(matches!(result, Err(PathError::Unreachable(_))));
+    }
+}
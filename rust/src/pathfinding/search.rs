@@ -0,0 +1,907 @@
+use super::path::turning_points;
+use super::{
+    Algorithm, DStarLite, Grid, Hierarchy, PathError, PathQuery, PathResult, Pos, SearchStats, jps,
+    memory_bounded, space_time,
+};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+// Node structure for A* algorithm
+#[derive(Copy, Clone, Debug)]
+pub struct Node {
+    pub position: Pos,
+    pub f_score: f64, // f = g + h
+    pub g_score: f64, // cost from start to current node
+    pub h_score: f64, // heuristic (estimated cost from current to goal)
+}
+
+impl Node {
+    pub fn new(position: Pos, g_score: f64, h_score: f64) -> Self {
+        Self::with_f(position, g_score + h_score, g_score, h_score)
+    }
+
+    // For algorithms whose priority isn't plain g + h
+    pub fn with_f(position: Pos, f_score: f64, g_score: f64, h_score: f64) -> Self {
+        Self {
+            position,
+            f_score,
+            g_score,
+            h_score,
+        }
+    }
+}
+
+// Custom ordering for the priority queue (min-heap based on f_score)
+impl Ord for Node {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Reverse ordering for min-heap (lowest f_score has highest priority)
+        other
+            .f_score
+            .total_cmp(&self.f_score)
+            .then_with(|| other.h_score.total_cmp(&self.h_score)) // Tie-breaker: prefer lower h_score
+            .then_with(|| other.position.cmp(&self.position)) // Final tie-breaker: position
+    }
+}
+
+impl PartialOrd for Node {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Node {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Node {}
+
+// Which frontier an event belongs to; everything but bidirectional search is forward only
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Side {
+    // Growing from the start
+    #[default]
+    Forward,
+    // Growing from the goal
+    Backward,
+}
+
+// Everything the search does that a view may want to draw, in the order it happened
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SearchEvent {
+    // A node was pushed to the open set
+    Open {
+        pos: Pos,
+        f: f64,
+        g: f64,
+        h: f64,
+        side: Side,
+    },
+    // A node was popped from the open set and is being processed
+    Expand {
+        pos: Pos,
+        f: f64,
+        g: f64,
+        h: f64,
+        side: Side,
+    },
+    // Bidirectional search settled on the cell where the two frontiers join
+    Meet {
+        pos: Pos,
+    },
+    // IDA* starts another depth-first pass, pruning nodes with f above `bound`
+    Threshold {
+        bound: f64,
+    },
+    // SMA* ran out of memory and dropped this node
+    Forget {
+        pos: Pos,
+    },
+    // A route starts searching its leg from `start` to `goal`; the events up to the next
+    // Leg belong to it
+    Leg {
+        index: usize,
+        start: Pos,
+        goal: Pos,
+    },
+    // Multi-agent planning (re)plans agent `index` from `start` to `goal`; the events up to
+    // the next Agent belong to that search
+    Agent {
+        index: usize,
+        start: Pos,
+        goal: Pos,
+    },
+    // Jump point search found a jump point; an Open event for it follows
+    JumpPoint {
+        pos: Pos,
+    },
+    // Space-time search is about to expand a node at time step `t`; the Open events after
+    // that Expand are one step later
+    Time {
+        t: usize,
+    },
+}
+
+// Receives search events as they happen; the search itself never touches the view
+pub trait SearchObserver {
+    fn on_event(&mut self, event: SearchEvent);
+
+    // Polled once per iteration; returning true stops the search with `PathError::Cancelled`
+    fn is_cancelled(&self) -> bool {
+        false
+    }
+}
+
+// Headless callers that don't care about the search trace
+impl SearchObserver for () {
+    fn on_event(&mut self, _event: SearchEvent) {}
+}
+
+// Record the whole trace so it can be replayed later (e.g. in step mode)
+impl SearchObserver for Vec<SearchEvent> {
+    fn on_event(&mut self, event: SearchEvent) {
+        self.push(event);
+    }
+}
+
+// Bookkeeping of one search direction
+#[derive(Clone, Debug, Default)]
+pub(super) struct Frontier {
+    pub(super) open_set: BinaryHeap<Node>,
+    pub(super) closed_set: HashSet<Pos>,
+    // Cheapest g found so far per cell; open set entries with a higher g are stale
+    pub(super) best_g: HashMap<Pos, f64>,
+    pub(super) came_from: HashMap<Pos, Pos>,
+}
+
+impl Frontier {
+    pub(super) fn clear(&mut self) {
+        self.open_set.clear();
+        self.closed_set.clear();
+        self.best_g.clear();
+        self.came_from.clear();
+    }
+
+    // The cell this frontier started from on the way to `pos`, for frontiers grown from
+    // several cells at once
+    pub(super) fn root_of(&self, mut pos: Pos) -> Pos {
+        while let Some(&prev) = self.came_from.get(&pos) {
+            pos = prev;
+        }
+        pos
+    }
+
+    // Reconstruct the path from came_from map, from `root` (where this frontier started) to `pos`
+    pub(super) fn reconstruct_path(&self, root: Pos, pos: Pos) -> Vec<Pos> {
+        let mut current = pos;
+        let mut path = vec![current];
+
+        // Follow came_from back until we reach the root
+        while current != root {
+            match self.came_from.get(&current) {
+                Some(&prev) => {
+                    current = prev;
+                    path.push(current);
+                }
+                None => break,
+            }
+        }
+
+        path.reverse();
+        path
+    }
+}
+
+// Best-first search state, kept between runs so the allocations can be reused
+#[derive(Clone, Debug, Default)]
+pub struct Pathfinder {
+    pub(super) forward: Frontier,
+    // Only used by bidirectional search, which grows a second frontier from the goal
+    pub(super) backward: Frontier,
+}
+
+impl Pathfinder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Calculate the path with the query's algorithm
+    pub fn find_path(
+        &mut self,
+        grid: &Grid,
+        query: &PathQuery,
+        observer: &mut impl SearchObserver,
+    ) -> Result<PathResult, PathError> {
+        let PathQuery {
+            start,
+            algorithm,
+            ref heuristic,
+            ..
+        } = *query;
+        grid.validate_query(query)?;
+        if query.agent_size > 1 {
+            return self.find_path_sized(grid, query, observer);
+        }
+        // The search stops at the first goal it expands, heading for the nearest one meanwhile
+        let goals = grid.goal_cells(query);
+        if matches!(algorithm, Algorithm::BidirectionalAStar) {
+            return self.find_path_bidirectional(grid, start, &goals, heuristic, observer);
+        }
+        if matches!(algorithm, Algorithm::DStarLite) {
+            // One-off plan; keep a `DStarLite` around to replan incrementally
+            return DStarLite::new(grid, query).plan(grid, observer);
+        }
+        match algorithm {
+            Algorithm::IterativeDeepeningAStar => {
+                return memory_bounded::find_path_ida(grid, start, &goals, heuristic, observer);
+            }
+            Algorithm::SmaStar { memory_limit } => {
+                return memory_bounded::find_path_sma(
+                    grid,
+                    start,
+                    &goals,
+                    heuristic,
+                    memory_limit,
+                    observer,
+                );
+            }
+            Algorithm::HierarchicalAStar { cluster_size } => {
+                // One-off query; keep a `Hierarchy` around to reuse the abstract graph
+                return Hierarchy::new(grid, cluster_size).find_path(grid, query, observer);
+            }
+            Algorithm::SpaceTimeAStar => {
+                return space_time::find_path(grid, start, &goals, heuristic, observer);
+            }
+            _ => {}
+        }
+        let jump_table = match algorithm {
+            Algorithm::JumpPointSearch => {
+                jps::check_grid(grid)?;
+                None
+            }
+            Algorithm::JumpPointSearchPlus => {
+                jps::check_grid(grid)?;
+                Some(grid.jump_table())
+            }
+            _ => None,
+        };
+        if algorithm.is_any_angle() && grid.topology().is_hex() {
+            return Err(PathError::Unsupported(
+                "any-angle search needs a square grid",
+            ));
+        }
+        // Straight segments are measured by length, which a portal step doesn't have
+        if algorithm.is_any_angle() && !grid.portals().is_empty() {
+            return Err(PathError::Unsupported(
+                "any-angle search needs a grid without portals",
+            ));
+        }
+        // Line of sight doesn't know which way a segment crosses the cells it passes
+        if algorithm.is_any_angle() && grid.has_one_way() {
+            return Err(PathError::Unsupported(
+                "any-angle search needs a grid without one-way cells",
+            ));
+        }
+
+        let heuristic = |pos: Pos| {
+            if algorithm.uses_heuristic() {
+                heuristic.estimate_nearest(grid, pos, &goals)
+            } else {
+                0.0
+            }
+        };
+        let make_node = |pos: Pos, g_score: f64| {
+            let h_score = heuristic(pos);
+            Node::with_f(pos, algorithm.priority(g_score, h_score), g_score, h_score)
+        };
+
+        let mut stats = SearchStats::default();
+        self.forward.clear();
+
+        let start_node = make_node(start, 0.0);
+        self.forward.best_g.insert(start, 0.0);
+        self.forward.open_set.push(start_node);
+        observer.on_event(SearchEvent::Open {
+            pos: start,
+            f: start_node.f_score,
+            g: start_node.g_score,
+            h: start_node.h_score,
+            side: Side::Forward,
+        });
+
+        while let Some(mut current) = self.forward.open_set.pop() {
+            if observer.is_cancelled() {
+                return Err(PathError::Cancelled(stats));
+            }
+            let current_pos = current.position;
+
+            // Lazy deletion: skip entries already expanded or superseded by a cheaper one
+            if self.forward.closed_set.contains(&current_pos)
+                || current.g_score > self.forward.best_g[&current_pos]
+            {
+                stats.stale_skipped += 1;
+                continue;
+            }
+
+            if matches!(algorithm, Algorithm::LazyThetaStar)
+                && let Some(&parent) = self.forward.came_from.get(&current_pos)
+            {
+                let g_score = self.verify_parent(grid, current_pos, parent);
+                current = make_node(current_pos, g_score);
+            }
+
+            // If we reached the end, reconstruct and return the path
+            if goals.contains(&current_pos) {
+                observer.on_event(SearchEvent::Expand {
+                    pos: current_pos,
+                    f: current.f_score,
+                    g: current.g_score,
+                    h: current.h_score,
+                    side: Side::Forward,
+                });
+                stats.nodes_expanded += 1;
+                return Ok(self.build_result(grid, algorithm, start, current_pos, stats));
+            }
+
+            self.forward.closed_set.insert(current_pos);
+            stats.nodes_expanded += 1;
+            observer.on_event(SearchEvent::Expand {
+                pos: current_pos,
+                f: current.f_score,
+                g: current.g_score,
+                h: current.h_score,
+                side: Side::Forward,
+            });
+
+            let neighbors = if algorithm.is_jump_point_search() {
+                let parent = self.forward.came_from.get(&current_pos).copied();
+                jps::successors(grid, &goals, current_pos, parent, jump_table)
+            } else if algorithm.is_any_angle() {
+                // Segments are measured by their Euclidean length, diagonal steps included
+                grid.get_neighbors(current_pos)
+                    .into_iter()
+                    .filter_map(|(pos, _)| Some((pos, grid.line_cost(current_pos, pos)?)))
+                    .collect()
+            } else {
+                grid.get_neighbors(current_pos)
+            };
+
+            for (neighbor_pos, step_cost) in neighbors {
+                // Skip if in closed set
+                if self.forward.closed_set.contains(&neighbor_pos) {
+                    continue;
+                }
+
+                let mut parent = current_pos;
+                let mut tentative_g = current.g_score + algorithm.step_cost(step_cost);
+
+                // Any-angle searches try to link the neighbor straight to the current parent
+                if algorithm.is_any_angle()
+                    && let Some(&grandparent) = self.forward.came_from.get(&current_pos)
+                {
+                    let shortcut = if matches!(algorithm, Algorithm::LazyThetaStar) {
+                        // Assume line of sight for now; `verify_parent` checks it on expansion
+                        Some(grid.line_length(grandparent, neighbor_pos) * grid.cost(neighbor_pos))
+                    } else {
+                        grid.line_cost(grandparent, neighbor_pos)
+                    };
+                    if let Some(cost) = shortcut
+                        && self.forward.best_g[&grandparent] + cost <= tentative_g
+                    {
+                        parent = grandparent;
+                        tentative_g = self.forward.best_g[&grandparent] + cost;
+                    }
+                }
+
+                // Only relax if this is a cheaper way to reach the neighbor
+                let previous_g = self.forward.best_g.get(&neighbor_pos).copied();
+                if previous_g.is_some_and(|g| tentative_g >= g) {
+                    continue;
+                }
+                if previous_g.is_some() {
+                    // The older, more expensive entry stays in the heap until it is skipped
+                    stats.duplicate_entries += 1;
+                }
+                self.forward.best_g.insert(neighbor_pos, tentative_g);
+                self.forward.came_from.insert(neighbor_pos, parent);
+
+                let neighbor = make_node(neighbor_pos, tentative_g);
+                self.forward.open_set.push(neighbor);
+                if algorithm.is_jump_point_search() {
+                    observer.on_event(SearchEvent::JumpPoint { pos: neighbor_pos });
+                }
+                observer.on_event(SearchEvent::Open {
+                    pos: neighbor_pos,
+                    f: neighbor.f_score,
+                    g: neighbor.g_score,
+                    h: neighbor.h_score,
+                    side: Side::Forward,
+                });
+            }
+        }
+
+        Err(PathError::Unreachable(stats))
+    }
+
+    // A unit bigger than one cell moves like a one-cell unit over the grid as it sees it
+    fn find_path_sized(
+        &mut self,
+        grid: &Grid,
+        query: &PathQuery,
+        observer: &mut impl SearchObserver,
+    ) -> Result<PathResult, PathError> {
+        // Footprints are squares of offset coordinates, which don't make a hex shape
+        if grid.topology().is_hex() {
+            return Err(PathError::Unsupported(
+                "agent clearance needs a square grid",
+            ));
+        }
+        let sized = grid.for_agent_size(query.agent_size);
+        let query = PathQuery {
+            agent_size: 1,
+            ..query.clone()
+        };
+        // The ends are open cells, so if they are walls to the unit its footprint doesn't fit
+        self.find_path(&sized, &query, observer)
+            .map_err(|err| match err {
+                PathError::StartIsWall(pos) | PathError::EndIsWall(pos) => {
+                    PathError::TooNarrow(pos)
+                }
+                err => err,
+            })
+    }
+
+    // Lazy Theta* linked `pos` to `parent` without checking line of sight. Check it now and
+    // relink to the cheapest expanded neighbor if the segment is blocked (or too expensive).
+    // Returns the corrected g score.
+    fn verify_parent(&mut self, grid: &Grid, pos: Pos, parent: Pos) -> f64 {
+        let direct = grid
+            .line_cost(parent, pos)
+            .map(|cost| (parent, self.forward.best_g[&parent] + cost));
+        let best = direct
+            .into_iter()
+            .chain(
+                grid.get_neighbors(pos)
+                    .into_iter()
+                    .filter(|(neighbor, _)| self.forward.closed_set.contains(neighbor))
+                    .filter_map(|(neighbor, _)| {
+                        Some((
+                            neighbor,
+                            self.forward.best_g[&neighbor] + grid.line_cost(neighbor, pos)?,
+                        ))
+                    }),
+            )
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+
+        match best {
+            Some((parent, g_score)) => {
+                self.forward.came_from.insert(pos, parent);
+                self.forward.best_g.insert(pos, g_score);
+                g_score
+            }
+            None => self.forward.best_g[&pos],
+        }
+    }
+
+    // Turn the came_from chain into cells, waypoints and cost
+    fn build_result(
+        &self,
+        grid: &Grid,
+        algorithm: Algorithm,
+        start: Pos,
+        goal: Pos,
+        stats: SearchStats,
+    ) -> PathResult {
+        let chain = self.forward.reconstruct_path(start, goal);
+        if algorithm.is_any_angle() {
+            // The chain holds the segment end points; fill in the cells each segment crosses
+            let mut cells = vec![start];
+            let mut cost = 0.0;
+            for pair in chain.windows(2) {
+                cells.extend(grid.line_cells(pair[0], pair[1]).unwrap_or_default());
+                cost += grid.line_cost(pair[0], pair[1]).unwrap_or(f64::INFINITY);
+            }
+            return PathResult {
+                cells,
+                waypoints: turning_points(grid, &chain),
+                cost,
+                stats,
+            };
+        }
+
+        let cells = if algorithm.is_jump_point_search() {
+            jps::expand_path(&chain)
+        } else {
+            chain
+        };
+        PathResult {
+            cost: grid.path_cost(&cells),
+            waypoints: turning_points(grid, &cells),
+            cells,
+            stats,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pathfinding::test_support::{
+        assert_close, movements, parse, random_grid, reference_cost,
+    };
+    use crate::pathfinding::{
+        CornerCutting, Heuristic, HexOffset, HexOrientation, Movement, Topology,
+    };
+    use std::f64::consts::SQRT_2;
+
+    fn find(grid: &Grid, query: &PathQuery) -> Result<PathResult, PathError> {
+        Pathfinder::new().find_path(grid, query, &mut ())
+    }
+
+    #[test]
+    fn a_star_follows_the_only_corridor() {
+        let (grid, start, goal) = parse(
+            "
+            S..#
+            ##.#
+            ...#
+            .###
+            ...G
+            ",
+        );
+        let path = find(&grid, &PathQuery::new(start, goal)).unwrap();
+        assert_eq!(
+            path.cells,
+            [
+                (0, 0),
+                (1, 0),
+                (2, 0),
+                (2, 1),
+                (2, 2),
+                (1, 2),
+                (0, 2),
+                (0, 3),
+                (0, 4),
+                (1, 4),
+                (2, 4),
+                (3, 4),
+            ]
+        );
+        assert_close(path.cost, 11.0);
+    }
+
+    #[test]
+    fn a_star_goes_around_expensive_terrain() {
+        let (grid, start, goal) = parse(
+            "
+            S~G
+            ...
+            ",
+        );
+        let path = find(&grid, &PathQuery::new(start, goal)).unwrap();
+        assert_eq!(path.cells, [(0, 0), (0, 1), (1, 1), (2, 1), (2, 0)]);
+        assert_close(path.cost, 4.0);
+    }
+
+    #[test]
+    fn a_star_takes_diagonals_in_eight_way_mode() {
+        let (mut grid, start, goal) = parse(
+            "
+            S..
+            ...
+            ..G
+            ",
+        );
+        grid.set_movement(Movement::eight_way(CornerCutting::NotBetweenWalls));
+        let query = PathQuery::new(start, goal).with_heuristic(Heuristic::Octile);
+        let path = find(&grid, &query).unwrap();
+        assert_eq!(path.cells, [(0, 0), (1, 1), (2, 2)]);
+        assert_close(path.cost, 2.0 * SQRT_2);
+    }
+
+    #[test]
+    fn start_on_the_goal() {
+        let (grid, start, _) = parse("S.");
+        let path = find(&grid, &PathQuery::new(start, start)).unwrap();
+        assert_eq!(path.cells, [start]);
+        assert_close(path.cost, 0.0);
+    }
+
+    #[test]
+    fn optimal_algorithms_agree_with_a_star() {
+        let (grid, start, goal) = parse(
+            "
+            S.....#.....
+            .####.#.###.
+            .#....#...#.
+            .#.####.#.#.
+            .#......#..G
+            ",
+        );
+        let a_star = find(&grid, &PathQuery::new(start, goal)).unwrap();
+        assert_close(a_star.cost, reference_cost(&grid, start, goal).unwrap());
+        for algorithm in [Algorithm::Dijkstra, Algorithm::BreadthFirst] {
+            let query = PathQuery::new(start, goal).with_algorithm(algorithm);
+            assert_close(find(&grid, &query).unwrap().cost, a_star.cost);
+        }
+    }
+
+    #[test]
+    fn a_star_is_optimal_on_random_grids() {
+        let mut reached = 0;
+        for seed in 0..20 {
+            let mut grid = random_grid(seed, 12, 9, 0.25, true);
+            if seed % 2 == 1 {
+                grid.set_movement(Movement::eight_way(CornerCutting::NotBetweenWalls));
+            }
+            let (start, goal) = ((0, 0), (11, 8));
+            let query = PathQuery::new(start, goal).with_heuristic(Heuristic::Octile);
+            match (find(&grid, &query), reference_cost(&grid, start, goal)) {
+                (Ok(path), Some(cost)) => {
+                    assert_close(path.cost, cost);
+                    assert_close(grid.path_cost(&path.cells), cost);
+                    reached += 1;
+                }
+                (Err(PathError::Unreachable(_)), None) => {}
+                (result, cost) => panic!("seed {seed}: {result:?} against {cost:?}"),
+            }
+        }
+        assert!(reached > 0);
+    }
+
+    #[test]
+    fn weighted_and_greedy_searches_stay_within_their_bounds() {
+        let mut reached = 0;
+        for seed in 0..20 {
+            let mut grid = random_grid(seed, 12, 9, 0.25, true);
+            grid.set_movement(movements()[seed as usize % 4]);
+            let (start, goal) = ((0, 0), (11, 8));
+            let optimum = reference_cost(&grid, start, goal);
+            let algorithms = [1.0, 1.5, 3.0]
+                .map(|epsilon| Algorithm::WeightedAStar { epsilon })
+                .into_iter()
+                .chain([Algorithm::GreedyBestFirst]);
+            for algorithm in algorithms {
+                let query = PathQuery::new(start, goal)
+                    .with_algorithm(algorithm)
+                    .with_heuristic(Heuristic::Octile);
+                match (find(&grid, &query), optimum) {
+                    (Ok(path), Some(optimum)) => {
+                        // Made of real steps, from the start to the goal
+                        assert_eq!(path.cells.first(), Some(&start));
+                        assert_eq!(path.goal(), Some(goal));
+                        assert_close(grid.path_cost(&path.cells), path.cost);
+                        if let Algorithm::WeightedAStar { epsilon } = algorithm {
+                            assert!(path.cost <= epsilon * optimum + 1e-9, "seed {seed}");
+                        }
+                        reached += 1;
+                    }
+                    (Err(PathError::Unreachable(_)), None) => {}
+                    (result, cost) => panic!("seed {seed}: {result:?} against {cost:?}"),
+                }
+            }
+        }
+        assert!(reached > 0);
+    }
+
+    #[test]
+    fn cheaper_way_found_later_supersedes_the_open_entry() {
+        // Ties on f go to the lower h, so the search runs up the left side first and opens
+        // (2, 2) from (2, 1) at g = 4 before it comes back to (1, 2), from where it costs 2
+        let (grid, start, goal) = parse(
+            "
+            ..~G
+            ...~
+            S...
+            ",
+        );
+        let mut events = Vec::new();
+        let path = Pathfinder::new()
+            .find_path(&grid, &PathQuery::new(start, goal), &mut events)
+            .unwrap();
+        let opened_at: Vec<f64> = events
+            .iter()
+            .filter_map(|event| match *event {
+                SearchEvent::Open { pos: (2, 2), g, .. } => Some(g),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(opened_at, [4.0, 2.0]);
+        assert_eq!(path.stats.duplicate_entries, 1);
+        // The g = 4 entry is popped after (2, 2) was expanded and thrown away
+        assert_eq!(path.stats.stale_skipped, 1);
+        assert_close(path.cost, reference_cost(&grid, start, goal).unwrap());
+        assert_close(path.cost, 9.0);
+    }
+
+    #[test]
+    fn every_search_heads_for_the_nearest_goal() {
+        let optimal = [
+            Algorithm::AStar,
+            Algorithm::Dijkstra,
+            Algorithm::JumpPointSearch,
+            Algorithm::JumpPointSearchPlus,
+            Algorithm::BidirectionalAStar,
+            Algorithm::DStarLite,
+            Algorithm::IterativeDeepeningAStar,
+            Algorithm::SmaStar {
+                memory_limit: 10_000,
+            },
+            Algorithm::SpaceTimeAStar,
+        ];
+        let start = (5, 4);
+        let goals = [(0, 0), (11, 0), (0, 8), (11, 8)];
+        let mut reached = 0;
+        for seed in 0..16 {
+            let mut grid = random_grid(seed, 12, 9, 0.2, false);
+            grid.set_movement(movements()[seed as usize % 4]);
+            for pos in [start].iter().chain(&goals) {
+                grid.set_wall(*pos, false);
+            }
+            let nearest = goals
+                .iter()
+                .filter_map(|&goal| reference_cost(&grid, start, goal))
+                .fold(f64::INFINITY, f64::min);
+            let query = PathQuery::new(start, goals[0])
+                .with_extra_goals(goals[1..].iter().copied())
+                .with_heuristic(Heuristic::Octile);
+            for algorithm in optimal {
+                let query = query.clone().with_algorithm(algorithm);
+                match find(&grid, &query) {
+                    Ok(path) => {
+                        assert_close(path.cost, nearest);
+                        assert!(goals.contains(&path.goal().unwrap()));
+                        assert_close(grid.path_cost(&path.cells), path.cost);
+                        reached += 1;
+                    }
+                    Err(PathError::Unreachable(_)) => assert!(nearest.is_infinite()),
+                    Err(error) => panic!("seed {seed}, {}: {error}", algorithm.name()),
+                }
+            }
+            // HPA* isn't optimal, but it still ends at one of the goals
+            let query = query.with_algorithm(Algorithm::HierarchicalAStar { cluster_size: 4 });
+            if let Ok(path) = find(&grid, &query) {
+                assert!(goals.contains(&path.goal().unwrap()));
+            }
+        }
+        assert!(reached > 0);
+    }
+
+    const ANY_ANGLE: [Algorithm; 2] = [Algorithm::ThetaStar, Algorithm::LazyThetaStar];
+
+    #[test]
+    fn any_angle_paths_cut_straight_across_open_ground() {
+        let (grid, start, goal) = parse(
+            "
+            S.........
+            ..........
+            .........G
+            ",
+        );
+        for algorithm in ANY_ANGLE {
+            let query = PathQuery::new(start, goal).with_algorithm(algorithm);
+            let path = find(&grid, &query).unwrap();
+            assert_eq!(path.waypoints, [start, goal], "{}", algorithm.name());
+            assert_close(path.cost, grid.line_length(start, goal));
+            // The cells are the ones the segment crosses
+            assert_eq!(path.cells[0], start);
+            assert_eq!(path.cells[1..], grid.line_cells(start, goal).unwrap());
+        }
+    }
+
+    #[test]
+    fn any_angle_paths_keep_line_of_sight() {
+        let mut reached = 0;
+        for seed in 0..40 {
+            let mut grid = random_grid(seed, 17, 13, 0.25, false);
+            grid.set_movement(movements()[seed as usize % 4]);
+            let (start, goal) = ((0, 0), (16, 12));
+            let grid_path = find(
+                &grid,
+                &PathQuery::new(start, goal).with_heuristic(Heuristic::Octile),
+            );
+            for algorithm in ANY_ANGLE {
+                let query = PathQuery::new(start, goal)
+                    .with_algorithm(algorithm)
+                    .with_heuristic(Heuristic::Euclidean);
+                match (&grid_path, find(&grid, &query)) {
+                    (Ok(grid_path), Ok(path)) => {
+                        assert_eq!(path.waypoints.first(), Some(&start));
+                        assert_eq!(path.waypoints.last(), Some(&goal));
+                        // Every segment can be walked straight, and they add up to the cost
+                        let segments: Option<Vec<f64>> = path
+                            .waypoints
+                            .windows(2)
+                            .map(|pair| grid.line_cost(pair[0], pair[1]))
+                            .collect();
+                        let segments = segments.expect("segment without line of sight");
+                        assert_close(path.cost, segments.iter().sum());
+                        assert!(path.cost + 1e-9 >= grid.line_length(start, goal));
+                        // Cutting corners never makes the path longer than moving on the grid
+                        if grid.movement() != Movement::FourWay {
+                            assert!(path.cost <= grid_path.cost + 1e-9, "seed {seed}");
+                        }
+                        reached += 1;
+                    }
+                    (Err(PathError::Unreachable(_)), Err(PathError::Unreachable(_))) => {}
+                    (grid_path, result) => {
+                        panic!(
+                            "seed {seed}, {}: {result:?} against {grid_path:?}",
+                            algorithm.name()
+                        )
+                    }
+                }
+            }
+        }
+        assert!(reached > 0);
+    }
+
+    #[test]
+    fn any_angle_search_needs_a_square_grid() {
+        let mut grid = Grid::new(4, 4);
+        grid.set_topology(Topology::hex(HexOrientation::Pointy, HexOffset::Odd));
+        let query = PathQuery::new((0, 0), (3, 3)).with_algorithm(Algorithm::ThetaStar);
+        assert_eq!(
+            find(&grid, &query),
+            Err(PathError::Unsupported(
+                "any-angle search needs a square grid"
+            ))
+        );
+    }
+
+    #[test]
+    fn walled_off_goal_is_unreachable() {
+        let (grid, start, goal) = parse(
+            "
+            S.#.
+            ..#G
+            ",
+        );
+        let result = find(&grid, &PathQuery::new(start, goal));
+        assert!(matches!(result, Err(PathError::Unreachable(_))));
+    }
+
+    #[test]
+    fn bigger_units_take_the_wide_gap() {
+        let (grid, start, goal) = parse(
+            "
+            S.......
+            ........
+            ###.##..
+            ........
+            G.......
+            ",
+        );
+        let narrow = find(&grid, &PathQuery::new(start, goal)).unwrap();
+        assert!(narrow.cells.contains(&(3, 2)));
+
+        let goal = (0, 3);
+        let query = PathQuery::new(start, goal).with_agent_size(2);
+        let path = find(&grid, &query).unwrap();
+        assert!(path.cells.iter().all(|&pos| grid.clearance(pos) >= 2));
+        assert!(path.cells.contains(&(6, 2)));
+        let sized = grid.for_agent_size(2);
+        assert_close(path.cost, reference_cost(&sized, start, goal).unwrap());
+    }
+
+    #[test]
+    fn footprints_that_dont_fit_are_too_narrow() {
+        let (grid, start, goal) = parse(
+            "
+            S...
+            .#..
+            ...G
+            ",
+        );
+        let query = PathQuery::new(start, goal).with_agent_size(2);
+        assert_eq!(find(&grid, &query), Err(PathError::TooNarrow(start)));
+        let query = PathQuery::new((2, 0), goal).with_agent_size(2);
+        assert_eq!(find(&grid, &query), Err(PathError::TooNarrow(goal)));
+
+        let mut hex = grid.clone();
+        hex.set_topology(Topology::hex(HexOrientation::Pointy, HexOffset::Odd));
+        let query = PathQuery::new((2, 0), (2, 1)).with_agent_size(2);
+        assert!(matches!(find(&hex, &query), Err(PathError::Unsupported(_))));
+    }
+}
@@ -0,0 +1,210 @@
+use super::multi_agent::{TimedNode, WAIT_COST};
+use super::path::turning_points;
+use super::{
+    Grid, Heuristic, PathError, PathResult, Pos, SearchEvent, SearchObserver, SearchStats, Side,
+};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+// A* over (cell, time step) states for grids with timed walls. Every move, and waiting in
+// place, takes one time step, and a cell can only be entered or stayed in at a step its
+// timed walls don't stand. From the grid's time cycle on the walls repeat, so times past it
+// are folded back onto one cycle: a state met again one cycle later is the same state, which
+// keeps the search finite and lets it give up on unreachable goals.
+
+// Time step that behaves like `t` for every timed wall
+fn phase(t: usize, (settle, period): (usize, usize)) -> usize {
+    if t < settle {
+        t
+    } else {
+        settle + (t - settle) % period
+    }
+}
+
+pub(super) fn find_path(
+    grid: &Grid,
+    start: Pos,
+    goals: &[Pos],
+    heuristic: &Heuristic,
+    observer: &mut impl SearchObserver,
+) -> Result<PathResult, PathError> {
+    // The unit stands on its start at step 0, so a timed wall there at that step walls it in
+    if !grid.is_passable_at(start, 0) {
+        return Err(PathError::StartIsWall(start));
+    }
+    let h = |pos: Pos| heuristic.estimate_nearest(grid, pos, goals);
+    let cycle = grid.time_cycle();
+
+    let mut stats = SearchStats::default();
+    let start_node = TimedNode {
+        pos: start,
+        t: 0,
+        g: 0.0,
+        f: h(start),
+    };
+    let mut open_set = BinaryHeap::from([start_node]);
+    observer.on_event(SearchEvent::Open {
+        pos: start,
+        f: start_node.f,
+        g: 0.0,
+        h: start_node.f,
+        side: Side::Forward,
+    });
+    // Keyed by (cell, phase); `came_from` leads back through the phases the path went through
+    let mut best_g = HashMap::from([((start, 0), 0.0)]);
+    let mut came_from: HashMap<(Pos, usize), (Pos, usize)> = HashMap::new();
+    let mut closed_set = HashSet::new();
+    while let Some(current) = open_set.pop() {
+        if observer.is_cancelled() {
+            return Err(PathError::Cancelled(stats));
+        }
+        let state = (current.pos, phase(current.t, cycle));
+        if !closed_set.insert(state) {
+            stats.stale_skipped += 1;
+            continue;
+        }
+
+        stats.nodes_expanded += 1;
+        observer.on_event(SearchEvent::Time { t: current.t });
+        observer.on_event(SearchEvent::Expand {
+            pos: current.pos,
+            f: current.f,
+            g: current.g,
+            h: current.f - current.g,
+            side: Side::Forward,
+        });
+        if goals.contains(&current.pos) {
+            // One cell per time step, so waiting repeats a cell
+            let mut cells = vec![current.pos];
+            let mut state = state;
+            while let Some(&previous) = came_from.get(&state) {
+                cells.push(previous.0);
+                state = previous;
+            }
+            cells.reverse();
+            let mut turns = cells.clone();
+            turns.dedup();
+            return Ok(PathResult {
+                waypoints: turning_points(grid, &turns),
+                cells,
+                cost: current.g,
+                stats,
+            });
+        }
+
+        let t = current.t + 1;
+        let moves = grid
+            .get_neighbors(current.pos)
+            .into_iter()
+            .chain([(current.pos, WAIT_COST)]);
+        for (next, cost) in moves {
+            let next_state = (next, phase(t, cycle));
+            if !grid.is_passable_at(next, t) || closed_set.contains(&next_state) {
+                continue;
+            }
+            let g = current.g + cost;
+            if best_g.get(&next_state).is_some_and(|&best| best <= g) {
+                continue;
+            }
+            if best_g.insert(next_state, g).is_some() {
+                stats.duplicate_entries += 1;
+            }
+            came_from.insert(next_state, state);
+            let h_score = h(next);
+            let node = TimedNode {
+                pos: next,
+                t,
+                g,
+                f: g + h_score,
+            };
+            open_set.push(node);
+            observer.on_event(SearchEvent::Open {
+                pos: next,
+                f: node.f,
+                g,
+                h: h_score,
+                side: Side::Forward,
+            });
+        }
+    }
+
+    Err(PathError::Unreachable(stats))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pathfinding::test_support::{assert_close, parse};
+    use crate::pathfinding::{Algorithm, PathQuery, Pathfinder, TimedWall};
+
+    fn find(grid: &Grid, start: Pos, goal: Pos) -> Result<PathResult, PathError> {
+        let query = PathQuery::new(start, goal).with_algorithm(Algorithm::SpaceTimeAStar);
+        Pathfinder::new().find_path(grid, &query, &mut ())
+    }
+
+    #[test]
+    fn a_timed_wall_on_the_start_at_step_0_walls_it_in() {
+        let (mut grid, start, goal) = parse("S.G");
+        grid.add_timed_wall(TimedWall::new(start, 0, 2));
+        let mut events = Vec::new();
+        let query = PathQuery::new(start, goal).with_algorithm(Algorithm::SpaceTimeAStar);
+        let result = Pathfinder::new().find_path(&grid, &query, &mut events);
+        assert_eq!(result, Err(PathError::StartIsWall(start)));
+        assert!(events.is_empty());
+
+        // A wall that only comes up once the unit has left is no obstacle
+        grid.remove_timed_walls(start);
+        grid.add_timed_wall(TimedWall::new(start, 1, 3));
+        assert_eq!(
+            find(&grid, start, goal).unwrap().cells,
+            [start, (1, 0), goal]
+        );
+    }
+
+    #[test]
+    fn waits_for_a_door_to_open() {
+        let (mut grid, start, goal) = parse("S.G");
+        grid.add_timed_wall(TimedWall::new((1, 0), 0, 3));
+        let path = find(&grid, start, goal).unwrap();
+        assert_eq!(path.cells, [start, start, start, (1, 0), goal]);
+        assert_close(path.cost, 4.0);
+        assert_eq!(path.waypoints, [start, goal]);
+
+        // Every other search takes the door for open
+        let path = Pathfinder::new()
+            .find_path(&grid, &PathQuery::new(start, goal), &mut ())
+            .unwrap();
+        assert_eq!(path.cells, [start, (1, 0), goal]);
+    }
+
+    #[test]
+    fn goes_around_a_door_that_stays_shut_too_long() {
+        let (mut grid, start, goal) = parse(
+            "
+            S.G
+            ...
+            ",
+        );
+        grid.add_timed_wall(TimedWall::new((1, 0), 0, 10));
+        let path = find(&grid, start, goal).unwrap();
+        assert_eq!(path.cells, [start, (0, 1), (1, 1), (2, 1), goal]);
+        assert_close(path.cost, 4.0);
+    }
+
+    #[test]
+    fn slips_through_a_repeating_door_when_it_opens() {
+        let (mut grid, start, goal) = parse("S.G");
+        grid.add_timed_wall(TimedWall::new((1, 0), 0, 3).repeating(4));
+        let path = find(&grid, start, goal).unwrap();
+        assert_eq!(path.cells, [start, start, start, (1, 0), goal]);
+
+        // A door that never opens is given up on once the start was waited on in every phase:
+        // steps 0 to 2 before the walls settle, then the 4 of their cycle
+        grid.add_timed_wall(TimedWall::new((1, 0), 3, 4).repeating(4));
+        let result = find(&grid, start, goal);
+        let Err(PathError::Unreachable(stats)) = result else {
+            panic!("expected no path, got {result:?}");
+        };
+        assert_eq!(grid.time_cycle(), (3, 4));
+        assert_eq!(stats.nodes_expanded, 7);
+    }
+}
@@ -0,0 +1,108 @@
+// Ground type of a cell; entering a cell costs the step cost times its terrain cost
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Terrain {
+    #[default]
+    Ground,
+    Grass,
+    Mud,
+    Water,
+}
+
+impl Terrain {
+    pub const ALL: [Terrain; 4] = [
+        Terrain::Ground,
+        Terrain::Grass,
+        Terrain::Mud,
+        Terrain::Water,
+    ];
+
+    // Never below 1, so the distance heuristics stay admissible
+    pub fn cost(&self) -> f64 {
+        match self {
+            Terrain::Ground => 1.0,
+            Terrain::Grass => 2.0,
+            Terrain::Mud => 3.0,
+            Terrain::Water => 5.0,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Terrain::Ground => "Ground",
+            Terrain::Grass => "Grass",
+            Terrain::Mud => "Mud",
+            Terrain::Water => "Water",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pathfinding::test_support::{assert_close, parse};
+    use crate::pathfinding::{CornerCutting, Grid, Heuristic, Movement, PathQuery, Pathfinder};
+
+    #[test]
+    fn costs_grow_from_ground_to_water() {
+        assert_eq!(Terrain::default(), Terrain::Ground);
+        assert_close(Terrain::Ground.cost(), 1.0);
+        assert!(
+            Terrain::ALL
+                .windows(2)
+                .all(|pair| pair[0].cost() < pair[1].cost())
+        );
+    }
+
+    #[test]
+    fn entering_a_cell_costs_its_terrain() {
+        let mut grid = Grid::new(2, 2);
+        grid.set_movement(Movement::eight_way(CornerCutting::Always));
+        grid.set_terrain((1, 1), Terrain::Mud);
+        let cost_to = |grid: &Grid, from, to| {
+            grid.get_neighbors(from)
+                .into_iter()
+                .find(|&(pos, _)| pos == to)
+                .unwrap()
+                .1
+        };
+        assert_close(
+            cost_to(&grid, (0, 0), (1, 1)),
+            3.0 * std::f64::consts::SQRT_2,
+        );
+        assert_close(cost_to(&grid, (0, 1), (1, 1)), 3.0);
+        // Leaving it costs what the cell entered costs
+        assert_close(cost_to(&grid, (1, 1), (0, 1)), 1.0);
+        assert_eq!(grid.uniform_cost(), None);
+        grid.set_terrain((1, 1), Terrain::Ground);
+        assert_eq!(grid.uniform_cost(), Some(1.0));
+    }
+
+    #[test]
+    fn paths_weigh_a_detour_against_the_terrain() {
+        let find = |map: &str| {
+            let (grid, start, goal) = parse(map);
+            let query = PathQuery::new(start, goal).with_heuristic(Heuristic::Manhattan);
+            Pathfinder::new().find_path(&grid, &query, &mut ()).unwrap()
+        };
+        // Two cells of detour are cheaper than wading through water
+        let path = find(
+            "
+            S~G
+            ...
+            ",
+        );
+        assert_eq!(path.cells, [(0, 0), (0, 1), (1, 1), (2, 1), (2, 0)]);
+        assert_close(path.cost, 4.0);
+        // A longer detour isn't
+        let path = find(
+            "
+            S~G
+            .#.
+            .#.
+            ...
+            ",
+        );
+        assert_eq!(path.cells, [(0, 0), (1, 0), (2, 0)]);
+        assert_close(path.cost, Terrain::Water.cost() + 1.0);
+    }
+}
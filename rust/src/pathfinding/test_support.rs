@@ -0,0 +1,96 @@
+use super::{CornerCutting, Grid, Movement, Pos, Terrain};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet};
+
+// Helpers shared by the unit tests of the pathfinding modules
+
+// Grid drawn as text, one line per row: '#' is a wall, '~' water, 'S' the start and 'G' the
+// goal; anything else is open ground. Blank lines and indentation are ignored.
+pub(super) fn parse(map: &str) -> (Grid, Pos, Pos) {
+    let rows: Vec<&str> = map
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+    let mut grid = Grid::new(rows[0].len() as i32, rows.len() as i32);
+    let (mut start, mut goal) = ((0, 0), (0, 0));
+    for (y, row) in rows.iter().enumerate() {
+        for (x, c) in row.chars().enumerate() {
+            let pos = (x as i32, y as i32);
+            match c {
+                '#' => grid.set_wall(pos, true),
+                '~' => grid.set_terrain(pos, Terrain::Water),
+                'S' => start = pos,
+                'G' => goal = pos,
+                _ => {}
+            }
+        }
+    }
+    (grid, start, goal)
+}
+
+// Grid with about `walls` of its cells walled off, the same for the same seed; the two
+// corners are kept open. With `terrain` the open cells get mixed terrain costs.
+pub(super) fn random_grid(seed: u64, width: i32, height: i32, walls: f64, terrain: bool) -> Grid {
+    let mut state = seed;
+    let mut next = || {
+        state = state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (state >> 11) as f64 / (1u64 << 53) as f64
+    };
+    let mut grid = Grid::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            if next() < walls {
+                grid.set_wall((x, y), true);
+            }
+            if terrain {
+                grid.set_terrain((x, y), Terrain::ALL[(next() * 4.0) as usize]);
+            }
+        }
+    }
+    grid.set_wall((0, 0), false);
+    grid.set_wall((width - 1, height - 1), false);
+    grid
+}
+
+// Every movement rule, 4-way first
+pub(super) fn movements() -> [Movement; 4] {
+    [
+        Movement::FourWay,
+        Movement::eight_way(CornerCutting::Always),
+        Movement::eight_way(CornerCutting::NotBetweenWalls),
+        Movement::eight_way(CornerCutting::BothOrthogonalsFree),
+    ]
+}
+
+// Cost of the cheapest way from `start` to `goal`, by a plain Dijkstra over the grid's
+// neighbors that shares no code with the searches under test
+pub(super) fn reference_cost(grid: &Grid, start: Pos, goal: Pos) -> Option<f64> {
+    // Costs are never negative, and so their bits sort like them
+    let mut open = BinaryHeap::from([Reverse((0.0f64.to_bits(), start))]);
+    let mut done = HashSet::new();
+    while let Some(Reverse((bits, pos))) = open.pop() {
+        let cost = f64::from_bits(bits);
+        if pos == goal {
+            return Some(cost);
+        }
+        if !done.insert(pos) {
+            continue;
+        }
+        for (next, step) in grid.get_neighbors(pos) {
+            if !done.contains(&next) {
+                open.push(Reverse(((cost + step).to_bits(), next)));
+            }
+        }
+    }
+    None
+}
+
+pub(super) fn assert_close(actual: f64, expected: f64) {
+    assert!(
+        (actual - expected).abs() < 1e-9,
+        "expected {expected}, got {actual}"
+    );
+}
@@ -0,0 +1,93 @@
+use super::Pos;
+
+// A wall that only stands at some time steps: a door that closes, a cell a patrol walks
+// through, a platform that moves away. It blocks `pos` from step `start` up to but not
+// including step `end`, and if it repeats, the same steps again every `period` steps. Only
+// space-time search sees it; every other search takes the cell for open.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TimedWall {
+    pub pos: Pos,
+    pub start: usize,
+    pub end: usize,
+    pub period: Option<usize>,
+}
+
+impl TimedWall {
+    // Stands once, from `start` to `end`
+    pub fn new(pos: Pos, start: usize, end: usize) -> Self {
+        Self {
+            pos,
+            start,
+            end,
+            period: None,
+        }
+    }
+
+    pub fn repeating(mut self, period: usize) -> Self {
+        self.period = Some(period).filter(|&period| period > 0);
+        self
+    }
+
+    // Whether the wall stands at time step `t`
+    pub fn is_active(&self, t: usize) -> bool {
+        if t < self.start {
+            return false;
+        }
+        let since = match self.period {
+            Some(period) => (t - self.start) % period,
+            None => t - self.start,
+        };
+        since < self.end.saturating_sub(self.start)
+    }
+
+    // Time step from which the wall is gone for good or stands every `period` steps alike
+    pub fn settles_at(&self) -> usize {
+        match self.period {
+            Some(_) => self.start,
+            None => self.end,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pathfinding::Grid;
+
+    #[test]
+    fn a_wall_stands_from_start_to_end() {
+        let wall = TimedWall::new((0, 0), 2, 4);
+        let active: Vec<usize> = (0..10).filter(|&t| wall.is_active(t)).collect();
+        assert_eq!(active, [2, 3]);
+        assert_eq!(wall.settles_at(), 4);
+        assert!(!TimedWall::new((0, 0), 3, 3).is_active(3));
+    }
+
+    #[test]
+    fn a_repeating_wall_stands_again_every_period() {
+        let wall = TimedWall::new((0, 0), 1, 3).repeating(4);
+        let active: Vec<usize> = (0..12).filter(|&t| wall.is_active(t)).collect();
+        assert_eq!(active, [1, 2, 5, 6, 9, 10]);
+        assert_eq!(wall.settles_at(), 1);
+        // A period of 0 would never come round, so the wall stands just once
+        assert_eq!(TimedWall::new((0, 0), 1, 3).repeating(0).period, None);
+    }
+
+    #[test]
+    fn the_time_cycle_covers_every_wall() {
+        let mut grid = Grid::new(4, 1);
+        assert_eq!(grid.time_cycle(), (0, 1));
+        grid.add_timed_wall(TimedWall::new((0, 0), 2, 5));
+        grid.add_timed_wall(TimedWall::new((1, 0), 1, 2).repeating(4));
+        grid.add_timed_wall(TimedWall::new((2, 0), 0, 3).repeating(6));
+        let (settle, period) = grid.time_cycle();
+        assert_eq!((settle, period), (5, 12));
+        for wall in grid.timed_walls() {
+            for t in settle..settle + period {
+                assert_eq!(wall.is_active(t), wall.is_active(t + period));
+            }
+        }
+        assert!(!grid.is_passable_at((1, 0), 1 + 12));
+        assert!(grid.is_passable_at((1, 0), 2 + 12));
+    }
+}
@@ -0,0 +1,238 @@
+use super::Pos;
+
+// Shape of the cells, and so which cells touch. Positions are always (column, row) in the
+// rectangular map. Hex maps store their cells the same way and shift every other row
+// (pointy-topped hexes) or column (flat-topped hexes) by half a cell: the "offset" layout.
+// Axial coordinates (q, r) skew the map instead, so that the six neighbors of every hex are
+// at the same offsets; `to_axial`/`from_axial` convert between the two.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Topology {
+    // Square cells, connected as the grid's movement says
+    #[default]
+    Square,
+    // Hexagonal cells with six neighbors each; the grid's movement doesn't apply
+    Hex {
+        orientation: HexOrientation,
+        offset: HexOffset,
+    },
+}
+
+// Which way the hexes point, and so what is shifted
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum HexOrientation {
+    // A corner at the top; rows are shifted
+    #[default]
+    Pointy,
+    // An edge at the top; columns are shifted
+    Flat,
+}
+
+// Which of the rows (or columns) are shifted forward by half a cell
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum HexOffset {
+    #[default]
+    Odd,
+    Even,
+}
+
+impl Topology {
+    // The six neighbors in axial coordinates, counterclockwise starting east
+    const AXIAL_DIRECTIONS: [(i32, i32); 6] = [(1, 0), (1, -1), (0, -1), (-1, 0), (-1, 1), (0, 1)];
+
+    pub fn hex(orientation: HexOrientation, offset: HexOffset) -> Self {
+        Topology::Hex {
+            orientation,
+            offset,
+        }
+    }
+
+    pub fn is_hex(&self) -> bool {
+        matches!(self, Topology::Hex { .. })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Topology::Square => "Square",
+            Topology::Hex {
+                orientation: HexOrientation::Pointy,
+                offset: HexOffset::Odd,
+            } => "Pointy hex (odd rows shifted)",
+            Topology::Hex {
+                orientation: HexOrientation::Pointy,
+                offset: HexOffset::Even,
+            } => "Pointy hex (even rows shifted)",
+            Topology::Hex {
+                orientation: HexOrientation::Flat,
+                offset: HexOffset::Odd,
+            } => "Flat hex (odd columns shifted)",
+            Topology::Hex {
+                orientation: HexOrientation::Flat,
+                offset: HexOffset::Even,
+            } => "Flat hex (even columns shifted)",
+        }
+    }
+
+    // Axial (q, r) of a map position; square positions stay as they are
+    pub fn to_axial(&self, (x, y): Pos) -> (i32, i32) {
+        match *self {
+            Topology::Square => (x, y),
+            Topology::Hex {
+                orientation: HexOrientation::Pointy,
+                offset,
+            } => (x - Self::shift(y, offset), y),
+            Topology::Hex {
+                orientation: HexOrientation::Flat,
+                offset,
+            } => (x, y - Self::shift(x, offset)),
+        }
+    }
+
+    // Map position of an axial (q, r)
+    pub fn from_axial(&self, (q, r): (i32, i32)) -> Pos {
+        match *self {
+            Topology::Square => (q, r),
+            Topology::Hex {
+                orientation: HexOrientation::Pointy,
+                offset,
+            } => (q + Self::shift(r, offset), r),
+            Topology::Hex {
+                orientation: HexOrientation::Flat,
+                offset,
+            } => (q, r + Self::shift(q, offset)),
+        }
+    }
+
+    // How far row (or column) `line` is skewed against axial coordinates
+    fn shift(line: i32, offset: HexOffset) -> i32 {
+        match offset {
+            HexOffset::Odd => (line - (line & 1)) / 2,
+            HexOffset::Even => (line + (line & 1)) / 2,
+        }
+    }
+
+    // The six cells around a hex; empty on square grids, whose neighbors depend on movement
+    pub fn hex_neighbors(&self, pos: Pos) -> Vec<Pos> {
+        if !self.is_hex() {
+            return Vec::new();
+        }
+        let (q, r) = self.to_axial(pos);
+        Self::AXIAL_DIRECTIONS
+            .iter()
+            .map(|(dq, dr)| self.from_axial((q + dq, r + dr)))
+            .collect()
+    }
+
+    // Number of hex steps between two cells with nothing in the way
+    pub fn hex_distance(&self, from: Pos, to: Pos) -> i32 {
+        let (q1, r1) = self.to_axial(from);
+        let (q2, r2) = self.to_axial(to);
+        let (dq, dr) = (q2 - q1, r2 - r1);
+        (dq.abs() + dr.abs() + (dq + dr).abs()) / 2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pathfinding::test_support::{assert_close, random_grid, reference_cost};
+    use crate::pathfinding::{Grid, Heuristic, PathQuery, Pathfinder};
+
+    const HEXES: [Topology; 4] = [
+        Topology::Hex {
+            orientation: HexOrientation::Pointy,
+            offset: HexOffset::Odd,
+        },
+        Topology::Hex {
+            orientation: HexOrientation::Pointy,
+            offset: HexOffset::Even,
+        },
+        Topology::Hex {
+            orientation: HexOrientation::Flat,
+            offset: HexOffset::Odd,
+        },
+        Topology::Hex {
+            orientation: HexOrientation::Flat,
+            offset: HexOffset::Even,
+        },
+    ];
+
+    #[test]
+    fn axial_coordinates_round_trip() {
+        for topology in [Topology::Square].into_iter().chain(HEXES) {
+            for y in -3..4 {
+                for x in -3..4 {
+                    assert_eq!(topology.from_axial(topology.to_axial((x, y))), (x, y));
+                }
+            }
+        }
+        assert_eq!(Topology::Square.to_axial((3, 5)), (3, 5));
+    }
+
+    #[test]
+    fn hex_neighbors_of_a_shifted_row() {
+        let odd_rows = Topology::hex(HexOrientation::Pointy, HexOffset::Odd);
+        assert_eq!(
+            odd_rows.hex_neighbors((1, 1)),
+            [(2, 1), (2, 0), (1, 0), (0, 1), (1, 2), (2, 2)]
+        );
+        assert_eq!(
+            odd_rows.hex_neighbors((1, 2)),
+            [(2, 2), (1, 1), (0, 1), (0, 2), (0, 3), (1, 3)]
+        );
+        // Column 2 is shifted down, so it sits between rows 2 and 3 of the columns beside it
+        let even_columns = Topology::hex(HexOrientation::Flat, HexOffset::Even);
+        assert_eq!(
+            even_columns.hex_neighbors((2, 2)),
+            [(3, 3), (3, 2), (2, 1), (1, 2), (1, 3), (2, 3)]
+        );
+        assert!(Topology::Square.hex_neighbors((1, 1)).is_empty());
+    }
+
+    #[test]
+    fn hex_neighbors_are_mutual_and_one_step_away() {
+        for topology in HEXES {
+            for y in -2..3 {
+                for x in -2..3 {
+                    let neighbors = topology.hex_neighbors((x, y));
+                    assert_eq!(neighbors.len(), 6);
+                    for neighbor in neighbors {
+                        assert_eq!(topology.hex_distance((x, y), neighbor), 1);
+                        assert!(topology.hex_neighbors(neighbor).contains(&(x, y)));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn hex_paths_are_optimal_and_distance_is_exact_in_the_open() {
+        let mut reached = 0;
+        for (seed, topology) in HEXES.into_iter().enumerate() {
+            let mut open = Grid::new(7, 6);
+            open.set_topology(topology);
+            for y in 0..6 {
+                for x in 0..7 {
+                    let steps = reference_cost(&open, (0, 0), (x, y)).unwrap();
+                    assert_close(f64::from(topology.hex_distance((0, 0), (x, y))), steps);
+                }
+            }
+
+            let mut grid = random_grid(seed as u64, 7, 6, 0.25, true);
+            grid.set_topology(topology);
+            let query = PathQuery::new((0, 0), (6, 5)).with_heuristic(Heuristic::Hex);
+            let path = Pathfinder::new().find_path(&grid, &query, &mut ());
+            match reference_cost(&grid, (0, 0), (6, 5)) {
+                Some(cost) => {
+                    let path = path.unwrap();
+                    assert_close(path.cost, cost);
+                    for pair in path.cells.windows(2) {
+                        assert!(topology.hex_neighbors(pair[0]).contains(&pair[1]));
+                    }
+                    reached += 1;
+                }
+                None => assert!(path.is_err()),
+            }
+        }
+        assert!(reached > 0);
+    }
+}
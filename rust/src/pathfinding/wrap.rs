@@ -0,0 +1,105 @@
+// Which edges of the grid connect to the opposite one, for worlds that loop around: a unit
+// stepping off the right edge comes back in on the left, and the other way round. Hex grids
+// only line up across an edge whose shifted lines come in pairs, i.e. an even height for
+// pointy hexes wrapping vertically and an even width for flat ones wrapping horizontally.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Wrap {
+    // Every edge is a border
+    #[default]
+    None,
+    // Left and right edges connect
+    Horizontal,
+    // Top and bottom edges connect
+    Vertical,
+    // The grid is a torus
+    Both,
+}
+
+impl Wrap {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Wrap::None => "None",
+            Wrap::Horizontal => "Horizontal",
+            Wrap::Vertical => "Vertical",
+            Wrap::Both => "Both",
+        }
+    }
+
+    pub fn wraps_x(&self) -> bool {
+        matches!(self, Wrap::Horizontal | Wrap::Both)
+    }
+
+    pub fn wraps_y(&self) -> bool {
+        matches!(self, Wrap::Vertical | Wrap::Both)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pathfinding::test_support::{assert_close, movements, random_grid, reference_cost};
+    use crate::pathfinding::{Grid, Heuristic, Movement, PathQuery, Pathfinder};
+
+    const WRAPS: [Wrap; 4] = [Wrap::None, Wrap::Horizontal, Wrap::Vertical, Wrap::Both];
+
+    #[test]
+    fn positions_and_offsets_go_round_wrapped_edges_only() {
+        let mut grid = Grid::new(5, 4);
+        grid.set_wrap(Wrap::Horizontal);
+        assert_eq!(grid.wrap_pos((5, 1)), (0, 1));
+        assert_eq!(grid.wrap_pos((-1, 4)), (4, 4));
+        assert_eq!(grid.delta((0, 0), (4, 0)), (-1, 0));
+        assert_eq!(grid.delta((0, 0), (0, 3)), (0, 3));
+        assert!(
+            grid.get_neighbors((0, 1))
+                .iter()
+                .any(|&(pos, _)| pos == (4, 1))
+        );
+        assert!(
+            !grid
+                .get_neighbors((0, 0))
+                .iter()
+                .any(|&(pos, _)| pos == (0, 3))
+        );
+
+        grid.set_wrap(Wrap::Both);
+        assert_eq!(grid.wrap_pos((-1, -1)), (4, 3));
+        assert_eq!(grid.delta((0, 0), (4, 3)), (-1, -1));
+        assert_eq!(grid.delta((0, 0), (2, 2)), (2, 2));
+    }
+
+    #[test]
+    fn heuristics_stay_admissible_across_wrapped_edges() {
+        let mut reached = 0;
+        for seed in 0..16 {
+            let mut grid = random_grid(seed, 7, 6, 0.2, seed % 2 == 0);
+            grid.set_wrap(WRAPS[seed as usize % 4]);
+            let movement = movements()[seed as usize / 4];
+            grid.set_movement(movement);
+            let mut heuristics = vec![Heuristic::Octile];
+            if movement == Movement::FourWay {
+                heuristics.push(Heuristic::Manhattan);
+            }
+            let goal = (6, 5);
+            for heuristic in heuristics {
+                for y in 0..grid.height() {
+                    for x in 0..grid.width() {
+                        if let Some(cost) = reference_cost(&grid, (x, y), goal) {
+                            assert!(heuristic.estimate(&grid, (x, y), goal) <= cost + 1e-9);
+                        }
+                    }
+                }
+                let query = PathQuery::new((0, 0), goal).with_heuristic(heuristic);
+                let path = Pathfinder::new().find_path(&grid, &query, &mut ());
+                match reference_cost(&grid, (0, 0), goal) {
+                    Some(cost) => {
+                        assert_close(path.unwrap().cost, cost);
+                        reached += 1;
+                    }
+                    None => assert!(path.is_err()),
+                }
+            }
+        }
+        assert!(reached > 0);
+    }
+}